@@ -0,0 +1,219 @@
+-- The schema of the SQLite migrations up to 20230205090000_entity_version in one step, see the
+-- comments there for the meaning of the tables. The queries are shared with SQLite, so:
+--   * integers are BIGINT and reals DOUBLE PRECISION, the types of SQLite values in Rust
+--   * times are UTC text in the format of CURRENT_TIMESTAMP of SQLite, and compared as text
+--   * group_concat is defined below as it is in SQLite
+
+CREATE FUNCTION utc_timestamp() RETURNS TEXT AS $$
+  SELECT to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+$$ LANGUAGE SQL STABLE;
+
+CREATE FUNCTION group_concat_step(joined TEXT, value TEXT, separator TEXT) RETURNS TEXT AS $$
+  SELECT CASE
+    WHEN value IS NULL THEN joined
+    WHEN joined IS NULL THEN value
+    ELSE joined || separator || value
+  END
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE AGGREGATE group_concat(TEXT, TEXT) (
+  SFUNC = group_concat_step,
+  STYPE = TEXT
+);
+
+CREATE TABLE reviewer (
+  id   BIGINT PRIMARY KEY,
+  name TEXT,
+  role TEXT NOT NULL DEFAULT 'reviewer'
+);
+
+CREATE TABLE restaurant (
+  id            BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  name          TEXT,
+  address       TEXT,
+  phone         TEXT,
+  opening_hours TEXT,
+  latitude      DOUBLE PRECISION,
+  longitude     DOUBLE PRECISION
+);
+
+CREATE TABLE dish (
+  id         BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  restaurant BIGINT REFERENCES restaurant(id),
+  name       TEXT,
+  image      TEXT,
+  price      DOUBLE PRECISION,
+  currency   TEXT
+);
+
+CREATE TABLE review (
+  id       BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  reviewer BIGINT REFERENCES reviewer(id),
+  dish     BIGINT REFERENCES dish(id),
+  details  TEXT,
+  score    BIGINT,
+  price    DOUBLE PRECISION
+);
+
+CREATE TABLE api_token (
+  hash       TEXT PRIMARY KEY,
+  reviewer   BIGINT NOT NULL REFERENCES reviewer(id),
+  created_at TEXT NOT NULL DEFAULT utc_timestamp()
+);
+
+CREATE TABLE chat_allowlist (
+  chat BIGINT PRIMARY KEY
+);
+
+CREATE TABLE audit_log (
+  id          BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  actor       BIGINT,
+  action      TEXT NOT NULL,
+  entity      TEXT NOT NULL,
+  entity_id   BIGINT NOT NULL,
+  before      TEXT,
+  after       TEXT,
+  created_at  TEXT NOT NULL DEFAULT utc_timestamp(),
+  reverts     BIGINT REFERENCES audit_log(id),
+  reverted_by BIGINT REFERENCES audit_log(id)
+);
+
+CREATE INDEX audit_log_entity ON audit_log (entity, entity_id);
+
+CREATE TABLE review_rating (
+  review    BIGINT NOT NULL REFERENCES review(id) ON DELETE CASCADE,
+  dimension TEXT NOT NULL,
+  score     BIGINT NOT NULL,
+  PRIMARY KEY(review, dimension)
+);
+
+CREATE TABLE dish_price (
+  id          BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  dish        BIGINT NOT NULL REFERENCES dish(id) ON DELETE CASCADE,
+  amount      DOUBLE PRECISION NOT NULL,
+  currency    TEXT NOT NULL,
+  observed_at TEXT NOT NULL DEFAULT utc_timestamp(),
+  reporter    BIGINT REFERENCES reviewer(id)
+);
+
+CREATE INDEX dish_price_dish ON dish_price (dish, observed_at);
+
+CREATE TABLE tag (
+  id   BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE restaurant_tag (
+  restaurant BIGINT NOT NULL REFERENCES restaurant(id) ON DELETE CASCADE,
+  tag        BIGINT NOT NULL REFERENCES tag(id) ON DELETE CASCADE,
+  PRIMARY KEY(restaurant, tag)
+);
+
+CREATE TABLE dish_tag (
+  dish BIGINT NOT NULL REFERENCES dish(id) ON DELETE CASCADE,
+  tag  BIGINT NOT NULL REFERENCES tag(id) ON DELETE CASCADE,
+  PRIMARY KEY(dish, tag)
+);
+
+CREATE TABLE hours_exception (
+  id          BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  restaurant  BIGINT NOT NULL REFERENCES restaurant(id) ON DELETE CASCADE,
+  start_date  TEXT NOT NULL,
+  end_date    TEXT NOT NULL,
+  hours       TEXT,
+  note        TEXT
+);
+
+CREATE INDEX hours_exception_restaurant ON hours_exception (restaurant, end_date);
+
+CREATE TABLE visit (
+  id          BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  reviewer    BIGINT NOT NULL REFERENCES reviewer(id),
+  restaurant  BIGINT NOT NULL REFERENCES restaurant(id),
+  visited_on  TEXT NOT NULL,
+  party_size  BIGINT,
+  spend       DOUBLE PRECISION,
+  created_at  TEXT NOT NULL DEFAULT utc_timestamp()
+);
+
+CREATE INDEX visit_restaurant ON visit (restaurant, visited_on);
+CREATE INDEX visit_reviewer ON visit (reviewer, visited_on);
+
+CREATE TABLE subscription (
+  subscriber  BIGINT NOT NULL REFERENCES reviewer(id),
+  target_kind TEXT NOT NULL,
+  target      BIGINT NOT NULL,
+  created_at  TEXT NOT NULL DEFAULT utc_timestamp(),
+  -- the order of insertion, implicit in SQLite
+  rowid       BIGINT GENERATED ALWAYS AS IDENTITY,
+  PRIMARY KEY(subscriber, target_kind, target)
+);
+
+CREATE INDEX subscription_target ON subscription (target_kind, target);
+
+CREATE TABLE digest_setting (
+  chat      BIGINT PRIMARY KEY,
+  weekday   BIGINT NOT NULL,
+  hour      BIGINT NOT NULL,
+  last_sent TEXT NOT NULL
+);
+
+CREATE TABLE webhook (
+  id         BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  url        TEXT NOT NULL,
+  secret     TEXT NOT NULL,
+  events     TEXT NOT NULL,
+  created_by BIGINT REFERENCES reviewer(id),
+  created_at TEXT NOT NULL DEFAULT utc_timestamp()
+);
+
+CREATE TABLE webhook_delivery (
+  id              BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  webhook         BIGINT NOT NULL REFERENCES webhook(id) ON DELETE CASCADE,
+  event           TEXT NOT NULL,
+  payload         TEXT NOT NULL,
+  status          TEXT NOT NULL DEFAULT 'pending',
+  attempts        BIGINT NOT NULL DEFAULT 0,
+  next_attempt_at TEXT NOT NULL DEFAULT utc_timestamp(),
+  response_status BIGINT,
+  last_error      TEXT,
+  created_at      TEXT NOT NULL DEFAULT utc_timestamp(),
+  delivered_at    TEXT
+);
+
+CREATE INDEX webhook_delivery_due ON webhook_delivery (status, next_attempt_at);
+CREATE INDEX webhook_delivery_webhook ON webhook_delivery (webhook, id);
+
+CREATE TABLE entity_version (
+  kind    TEXT PRIMARY KEY,
+  version BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO entity_version (kind) VALUES ('restaurant'), ('dish'), ('review');
+
+-- bump the counters of the kinds given as the arguments of the trigger
+CREATE FUNCTION bump_entity_version() RETURNS TRIGGER AS $$
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = ANY(TG_ARGV);
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER restaurant_version AFTER INSERT OR UPDATE OR DELETE ON restaurant
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('restaurant');
+CREATE TRIGGER restaurant_tag_version AFTER INSERT OR UPDATE OR DELETE ON restaurant_tag
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('restaurant');
+CREATE TRIGGER dish_version AFTER INSERT OR UPDATE OR DELETE ON dish
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('dish');
+CREATE TRIGGER dish_tag_version AFTER INSERT OR UPDATE OR DELETE ON dish_tag
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('dish');
+CREATE TRIGGER dish_price_version AFTER INSERT OR UPDATE OR DELETE ON dish_price
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('dish');
+CREATE TRIGGER tag_version AFTER INSERT OR UPDATE OR DELETE ON tag
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('restaurant', 'dish');
+CREATE TRIGGER review_version AFTER INSERT OR UPDATE OR DELETE ON review
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('review');
+CREATE TRIGGER review_rating_version AFTER INSERT OR UPDATE OR DELETE ON review_rating
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('review');
+CREATE TRIGGER reviewer_version AFTER INSERT OR UPDATE OR DELETE ON reviewer
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('review');
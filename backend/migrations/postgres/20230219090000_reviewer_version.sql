@@ -0,0 +1,10 @@
+-- The reviews show only the name and the role of their reviewer, so an update of the reviewer
+-- that keeps both doesn't change them
+DROP TRIGGER reviewer_version ON reviewer;
+
+CREATE TRIGGER reviewer_version AFTER INSERT OR DELETE ON reviewer
+  FOR EACH ROW EXECUTE FUNCTION bump_entity_version('review');
+CREATE TRIGGER reviewer_update_version AFTER UPDATE ON reviewer
+  FOR EACH ROW
+  WHEN (OLD.name IS DISTINCT FROM NEW.name OR OLD.role IS DISTINCT FROM NEW.role)
+  EXECUTE FUNCTION bump_entity_version('review');
@@ -0,0 +1,6 @@
+-- Optional details collected by the restaurant creation wizard
+ALTER TABLE restaurant ADD COLUMN phone TEXT;
+ALTER TABLE restaurant ADD COLUMN opening_hours TEXT;
+ALTER TABLE restaurant ADD COLUMN tags TEXT;
+ALTER TABLE restaurant ADD COLUMN latitude REAL;
+ALTER TABLE restaurant ADD COLUMN longitude REAL;
@@ -0,0 +1,15 @@
+-- Permission of each user, one of admin, editor, reviewer and banned
+ALTER TABLE reviewer ADD COLUMN role TEXT NOT NULL DEFAULT 'reviewer';
+
+-- Token for accessing the mutating API routes, only the sha256 hash is stored
+CREATE TABLE IF NOT EXISTS api_token (
+  hash       TEXT PRIMARY KEY,
+  reviewer   INT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY(reviewer) REFERENCES reviewer(id)
+);
+
+-- Chats that are allowed to use the bot when it runs in private mode
+CREATE TABLE IF NOT EXISTS chat_allowlist (
+  chat INTEGER PRIMARY KEY
+);
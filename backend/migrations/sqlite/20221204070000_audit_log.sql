@@ -0,0 +1,19 @@
+-- Every change of the data, written in the same transaction of the change
+CREATE TABLE IF NOT EXISTS audit_log (
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  -- reviewer id, NULL for system operations
+  actor       INT,
+  action      TEXT NOT NULL,
+  entity      TEXT NOT NULL,
+  entity_id   INT NOT NULL,
+  -- the row before and after the change, as JSON object
+  before      TEXT,
+  after       TEXT,
+  created_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  reverts     INT,
+  reverted_by INT,
+  FOREIGN KEY(reverts) REFERENCES audit_log(id),
+  FOREIGN KEY(reverted_by) REFERENCES audit_log(id)
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_entity ON audit_log (entity, entity_id);
@@ -0,0 +1,11 @@
+-- Price paid for the dish, NULL for the reviews without it
+ALTER TABLE review ADD COLUMN price REAL;
+
+-- Optional sub-scores of a review, the overall score stays in review.score
+CREATE TABLE IF NOT EXISTS review_rating (
+  review    INT NOT NULL,
+  dimension TEXT NOT NULL,
+  score     INT NOT NULL,
+  PRIMARY KEY(review, dimension),
+  FOREIGN KEY(review) REFERENCES review(id) ON DELETE CASCADE
+);
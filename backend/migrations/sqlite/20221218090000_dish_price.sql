@@ -0,0 +1,18 @@
+-- Latest known price of the dish
+ALTER TABLE dish ADD COLUMN price REAL;
+ALTER TABLE dish ADD COLUMN currency TEXT;
+
+-- Every price reported for a dish
+CREATE TABLE IF NOT EXISTS dish_price (
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  dish        INT NOT NULL,
+  amount      REAL NOT NULL,
+  currency    TEXT NOT NULL,
+  observed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  -- reviewer id, NULL for system operations
+  reporter    INT,
+  FOREIGN KEY(dish) REFERENCES dish(id) ON DELETE CASCADE,
+  FOREIGN KEY(reporter) REFERENCES reviewer(id)
+);
+
+CREATE INDEX IF NOT EXISTS dish_price_dish ON dish_price (dish, observed_at);
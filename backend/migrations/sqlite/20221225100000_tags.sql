@@ -0,0 +1,41 @@
+CREATE TABLE IF NOT EXISTS tag (
+  id   INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS restaurant_tag (
+  restaurant INT NOT NULL,
+  tag        INT NOT NULL,
+  PRIMARY KEY(restaurant, tag),
+  FOREIGN KEY(restaurant) REFERENCES restaurant(id) ON DELETE CASCADE,
+  FOREIGN KEY(tag) REFERENCES tag(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS dish_tag (
+  dish INT NOT NULL,
+  tag  INT NOT NULL,
+  PRIMARY KEY(dish, tag),
+  FOREIGN KEY(dish) REFERENCES dish(id) ON DELETE CASCADE,
+  FOREIGN KEY(tag) REFERENCES tag(id) ON DELETE CASCADE
+);
+
+-- Move the comma separated restaurant.tags into the tag tables
+CREATE TEMP TABLE split_tag AS
+WITH RECURSIVE split(restaurant, name, rest) AS (
+  SELECT id, '', replace(tags, '，', ',') || ',' FROM restaurant
+  WHERE tags IS NOT NULL AND tags != ''
+  UNION ALL
+  SELECT
+    restaurant,
+    lower(trim(substr(rest, 1, instr(rest, ',') - 1))),
+    substr(rest, instr(rest, ',') + 1)
+  FROM split WHERE rest != ''
+)
+SELECT restaurant, name FROM split WHERE name != '';
+
+INSERT OR IGNORE INTO tag (name) SELECT DISTINCT name FROM split_tag;
+INSERT OR IGNORE INTO restaurant_tag (restaurant, tag)
+  SELECT split_tag.restaurant, tag.id FROM split_tag JOIN tag ON split_tag.name = tag.name;
+DROP TABLE split_tag;
+
+ALTER TABLE restaurant DROP COLUMN tags;
@@ -0,0 +1,15 @@
+-- Days that don't follow the weekly opening hours, like holidays and temporary closure.
+-- The weekly hours stay in restaurant.opening_hours, in the format written by the parser.
+CREATE TABLE IF NOT EXISTS hours_exception (
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  restaurant  INT NOT NULL,
+  -- inclusive date range like 2023-01-21
+  start_date  TEXT NOT NULL,
+  end_date    TEXT NOT NULL,
+  -- time ranges like "10:00-14:00 17:00-20:00", NULL for closed all day
+  hours       TEXT,
+  note        TEXT,
+  FOREIGN KEY(restaurant) REFERENCES restaurant(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS hours_exception_restaurant ON hours_exception (restaurant, end_date);
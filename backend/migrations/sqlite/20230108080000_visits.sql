@@ -0,0 +1,18 @@
+-- A meal at a restaurant, with or without reviews
+CREATE TABLE IF NOT EXISTS visit (
+  id          INTEGER PRIMARY KEY AUTOINCREMENT,
+  reviewer    INT NOT NULL,
+  restaurant  INT NOT NULL,
+  -- local date like 2023-01-08
+  visited_on  TEXT NOT NULL,
+  party_size  INT,
+  -- total spend of the party, in the default currency
+  spend       REAL,
+  created_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY(reviewer) REFERENCES reviewer(id),
+  -- visits are history, they are moved when merging restaurants but never dropped
+  FOREIGN KEY(restaurant) REFERENCES restaurant(id)
+);
+
+CREATE INDEX IF NOT EXISTS visit_restaurant ON visit (restaurant, visited_on);
+CREATE INDEX IF NOT EXISTS visit_reviewer ON visit (reviewer, visited_on);
@@ -0,0 +1,13 @@
+-- Restaurants and reviewers followed by each user, whose new reviews, dishes and photos are
+-- sent to the user by the bot
+CREATE TABLE IF NOT EXISTS subscription (
+  subscriber  INT NOT NULL,
+  -- restaurant or reviewer
+  target_kind TEXT NOT NULL,
+  target      INT NOT NULL,
+  created_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY(subscriber, target_kind, target),
+  FOREIGN KEY(subscriber) REFERENCES reviewer(id)
+);
+
+CREATE INDEX IF NOT EXISTS subscription_target ON subscription (target_kind, target);
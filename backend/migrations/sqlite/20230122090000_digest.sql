@@ -0,0 +1,9 @@
+-- Chats that get the weekly digest, at the local hour of the weekday
+CREATE TABLE IF NOT EXISTS digest_setting (
+  chat      INTEGER PRIMARY KEY,
+  -- days from monday, 0 - 6
+  weekday   INT NOT NULL,
+  hour      INT NOT NULL,
+  -- local time of the last post, digests scheduled before it are skipped
+  last_sent TEXT NOT NULL
+);
@@ -0,0 +1,33 @@
+-- Outbound webhooks, every change of restaurants, dishes and reviews is queued for the matching
+-- ones in the transaction of the change
+CREATE TABLE IF NOT EXISTS webhook (
+  id         INTEGER PRIMARY KEY AUTOINCREMENT,
+  url        TEXT NOT NULL,
+  -- key of the HMAC-SHA256 signature of the payload
+  secret     TEXT NOT NULL,
+  -- comma separated event patterns like review.create, dish.* or *
+  events     TEXT NOT NULL,
+  created_by INT,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY(created_by) REFERENCES reviewer(id)
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  webhook         INT NOT NULL,
+  event           TEXT NOT NULL,
+  payload         TEXT NOT NULL,
+  -- pending, delivered or failed
+  status          TEXT NOT NULL DEFAULT 'pending',
+  attempts        INT NOT NULL DEFAULT 0,
+  next_attempt_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  -- HTTP status of the last attempt, NULL when the request failed
+  response_status INT,
+  last_error      TEXT,
+  created_at      TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  delivered_at    TEXT,
+  FOREIGN KEY(webhook) REFERENCES webhook(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_due ON webhook_delivery (status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS webhook_delivery_webhook ON webhook_delivery (webhook, id);
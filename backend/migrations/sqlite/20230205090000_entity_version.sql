@@ -0,0 +1,144 @@
+-- Counter of each kind of rows for the HTTP caching of the api-server. It's bumped by the
+-- triggers below on every change of the rows and the tags, prices and ratings in their responses,
+-- so the writes of the bot and the admin tool are counted too.
+CREATE TABLE IF NOT EXISTS entity_version (
+  kind    TEXT PRIMARY KEY,
+  version INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT OR IGNORE INTO entity_version (kind) VALUES ('restaurant'), ('dish'), ('review');
+
+CREATE TRIGGER IF NOT EXISTS restaurant_insert_version AFTER INSERT ON restaurant
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_update_version AFTER UPDATE ON restaurant
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_delete_version AFTER DELETE ON restaurant
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_tag_insert_version AFTER INSERT ON restaurant_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_tag_update_version AFTER UPDATE ON restaurant_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_tag_delete_version AFTER DELETE ON restaurant_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'restaurant';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_insert_version AFTER INSERT ON dish
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_update_version AFTER UPDATE ON dish
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_delete_version AFTER DELETE ON dish
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_tag_insert_version AFTER INSERT ON dish_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_tag_update_version AFTER UPDATE ON dish_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_tag_delete_version AFTER DELETE ON dish_tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_price_insert_version AFTER INSERT ON dish_price
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_price_update_version AFTER UPDATE ON dish_price
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS dish_price_delete_version AFTER DELETE ON dish_price
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'dish';
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_insert_version AFTER INSERT ON tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind IN ('restaurant', 'dish');
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_update_version AFTER UPDATE ON tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind IN ('restaurant', 'dish');
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_delete_version AFTER DELETE ON tag
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind IN ('restaurant', 'dish');
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_insert_version AFTER INSERT ON review
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_update_version AFTER UPDATE ON review
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_delete_version AFTER DELETE ON review
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_rating_insert_version AFTER INSERT ON review_rating
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_rating_update_version AFTER UPDATE ON review_rating
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS review_rating_delete_version AFTER DELETE ON review_rating
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS reviewer_insert_version AFTER INSERT ON reviewer
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS reviewer_update_version AFTER UPDATE ON reviewer
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
+
+CREATE TRIGGER IF NOT EXISTS reviewer_delete_version AFTER DELETE ON reviewer
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
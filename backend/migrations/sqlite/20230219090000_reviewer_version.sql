@@ -0,0 +1,9 @@
+-- The reviews show only the name and the role of their reviewer, so an update of the reviewer
+-- that keeps both doesn't change them
+DROP TRIGGER IF EXISTS reviewer_update_version;
+
+CREATE TRIGGER IF NOT EXISTS reviewer_update_version AFTER UPDATE ON reviewer
+WHEN OLD.name IS NOT NEW.name OR OLD.role IS NOT NEW.role
+BEGIN
+  UPDATE entity_version SET version = version + 1 WHERE kind = 'review';
+END;
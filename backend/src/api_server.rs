@@ -0,0 +1,69 @@
+use crate::{
+    db,
+    ops::{self, Tasks},
+};
+use actix_cors::Cors;
+use actix_web::{
+    dev::{Server, Service},
+    web, App, HttpServer,
+};
+
+mod api;
+mod cache;
+mod events;
+mod graphql;
+mod metrics;
+mod openapi;
+mod ratelimit;
+mod webhook;
+
+/// Start the background tasks in `tasks` and the HTTP server on the pool, with the extra routes
+/// from `configure` which do their own rate limiting. The server doesn't handle the signals, stop
+/// it with its handle.
+pub async fn start<F>(pool: db::Pool, tasks: &mut Tasks, configure: F) -> anyhow::Result<Server>
+where
+    F: Fn(&mut web::ServiceConfig) + Clone + Send + 'static,
+{
+    let state = api::ApiState::from_pool(pool);
+    let pool = state.db_pool();
+    tasks.spawn(|shutdown| webhook::run(pool, shutdown));
+    // the bot may run in another process, publish its changes to the event streams
+    let watcher = db::ChangeWatcher::new(state.db_pool()).await?;
+    tasks.spawn(|shutdown| watcher.run(events::POLL_INTERVAL, shutdown));
+    let pool = web::Data::new(state.db_pool());
+    let data = web::Data::new(state);
+    let schema = web::Data::new(graphql::schema());
+    // shared by the workers
+    let rate_limit = ratelimit::RateLimit::from_env();
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap_fn(|req, srv| {
+                let start = std::time::Instant::now();
+                let resp = srv.call(req);
+                async move {
+                    let resp = resp.await?;
+                    metrics::observe(start, &resp);
+                    Ok(resp)
+                }
+            })
+            .wrap(Cors::default().allow_any_method().allow_any_origin())
+            .app_data(data.clone())
+            .app_data(schema.clone())
+            .app_data(pool.clone())
+            // matched before the scope below, so they are not limited
+            .configure(configure.clone())
+            .service(
+                web::scope("")
+                    .wrap(rate_limit.clone())
+                    .configure(ops::configure)
+                    .configure(openapi::configure)
+                    .service(openapi::docs())
+                    .service(graphql::graphql)
+                    .service(graphql::graphiql),
+            )
+    })
+    .disable_signals()
+    .bind(("127.0.0.1", 8080))?
+    .run();
+    Ok(server)
+}
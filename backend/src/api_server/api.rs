@@ -0,0 +1,1601 @@
+use super::cache::ReadCache;
+use super::metrics;
+use crate::db::{self as db_api, Actor, EntityKind, OpenStatus, OpeningHours, Permission, Role};
+use crate::ratelimit::Throttled;
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag, Header, IfNoneMatch};
+use actix_web::{http::StatusCode, web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+
+pub(super) struct ApiState {
+    db_pool: db_api::Pool,
+    cache: ReadCache,
+}
+
+impl ApiState {
+    pub(super) fn db_pool(&self) -> db_api::Pool {
+        self.db_pool.clone()
+    }
+
+    pub(super) fn from_pool(db_pool: db_api::Pool) -> Self {
+        Self {
+            db_pool,
+            cache: ReadCache::default(),
+        }
+    }
+}
+
+/// Answer a read route with the JSON of `render`, tagged with the versions of `kinds`.
+///
+/// A client holding the current ETag gets 304, and a body rendered at the current ETag is served
+/// from the cache without querying the database.
+async fn cached_json<T, F>(
+    req: &HttpRequest,
+    data: &ApiState,
+    kinds: &[EntityKind],
+    render: F,
+) -> HttpResponse
+where
+    T: serde::Serialize,
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let route = req.match_pattern().unwrap_or_default();
+    let versions = db_api::entity_versions(&data.db_pool, kinds);
+    let versions = match metrics::time_query("entity_versions", versions).await {
+        Ok(versions) => versions,
+        Err(err) => {
+            return HttpResponse::Ok().json(ErrJsonResp {
+                message: err.to_string(),
+            })
+        }
+    };
+    let tag = versions
+        .iter()
+        .map(i64::to_string)
+        .collect::<Vec<_>>()
+        .join("-");
+    let etag = EntityTag::new_strong(tag.clone());
+    let fresh = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if fresh {
+        data.cache.count(true);
+        return HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+            .finish();
+    }
+
+    let uri = req.uri().to_string();
+    let body = match data.cache.get(&uri, &tag) {
+        Some(body) => body,
+        None => {
+            let rendered = metrics::time_query(&route, render).await;
+            let body = match rendered.and_then(|value| Ok(serde_json::to_vec(&value)?)) {
+                Ok(body) => web::Bytes::from(body),
+                Err(err) => {
+                    return HttpResponse::Ok().json(ErrJsonResp {
+                        message: err.to_string(),
+                    })
+                }
+            };
+            data.cache.put(&uri, &tag, body.clone());
+            let (hits, misses) = data.cache.stats();
+            tracing::debug!(hits, misses, "rendered {uri} at {tag}");
+            body
+        }
+    };
+    HttpResponse::Ok()
+        .content_type(header::ContentType::json())
+        .insert_header(header::ETag(etag))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(body)
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(super) struct ErrJsonResp {
+    message: String,
+}
+
+/// Error of the mutating routes, rendered as [`ErrJsonResp`] with the matching status code
+#[derive(Debug)]
+pub(super) enum ApiError {
+    Unauthorized,
+    Forbidden(db_api::PermissionDenied),
+    BadRequest(String),
+    NotFound(String),
+    TooManyRequests(Throttled),
+    Internal(anyhow::Error),
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unauthorized => write!(f, "missing or invalid API token"),
+            Self::Forbidden(denied) => write!(f, "{denied}"),
+            Self::BadRequest(reason) | Self::NotFound(reason) => write!(f, "{reason}"),
+            Self::TooManyRequests(throttled) => write!(f, "{throttled}"),
+            Self::Internal(err) => write!(f, "{err:#}"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+        if let Self::TooManyRequests(throttled) = self {
+            let secs = throttled.retry_after().as_secs_f64().ceil() as u64;
+            resp.insert_header((header::RETRY_AFTER, secs.max(1)));
+        }
+        resp.json(ErrJsonResp {
+            message: self.to_string(),
+        })
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Internal(err)
+    }
+}
+
+/// The user behind the `Authorization: Bearer <token>` header. Every mutating route takes it and
+/// checks the permission before touching the database.
+pub(super) struct Caller {
+    pub(super) id: i64,
+    role: Role,
+}
+
+impl Caller {
+    pub(super) fn require(&self, permission: Permission) -> Result<(), ApiError> {
+        self.role.check(permission).map_err(ApiError::Forbidden)
+    }
+
+    pub(super) fn actor(&self) -> Actor {
+        Actor::User(self.id)
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<ApiState>>().cloned();
+        let token = req
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|v| v.trim().to_string());
+
+        Box::pin(async move {
+            let (Some(state), Some(token)) = (state, token) else {
+                return Err(ApiError::Unauthorized);
+            };
+            let owner = db_api::token_owner(&state.db_pool, &token);
+            let (id, role) = metrics::time_query("token_owner", owner)
+                .await?
+                .ok_or(ApiError::Unauthorized)?;
+            Ok(Self { id, role })
+        })
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct ListQuery {
+    /// Comma separated tags, every one of them is required
+    tags: Option<String>,
+    /// Part of the name
+    q: Option<String>,
+    /// Only the restaurants that are open now when true
+    #[serde(default)]
+    open_now: bool,
+}
+
+impl ListQuery {
+    fn matches(&self, name: &str, tags: Option<&str>) -> bool {
+        let wanted = self
+            .tags
+            .as_deref()
+            .map(db_api::split_tags)
+            .unwrap_or_default();
+        let keyword = self.q.as_deref().map(str::to_lowercase).unwrap_or_default();
+        db_api::has_tags(tags, &wanted) && name.to_lowercase().contains(&keyword)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/restaurants",
+    tag = "restaurants",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "Restaurants matching the query", body = [Restaurant]),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+    )
+)]
+#[actix_web::get("/api/v1/restaurants")]
+pub(super) async fn restaurants(
+    req: HttpRequest,
+    data: web::Data<ApiState>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    if !query.open_now {
+        return cached_json(&req, &data, &[EntityKind::Restaurant], async {
+            let mut restaurants =
+                db_api::get_restaurant(&data.db_pool, db_api::RestaurantSearchProps::All).await?;
+            restaurants.retain(|r| query.matches(&r.name, r.tags.as_deref()));
+            Ok(restaurants)
+        })
+        .await;
+    }
+    // the open ones change with the clock, not with the data
+    let result = db_api::get_restaurant(&data.db_pool, db_api::RestaurantSearchProps::All).await;
+    let hours = db_api::all_opening_hours(&data.db_pool).await;
+    match result.and_then(|r| Ok((r, hours?))) {
+        Ok((mut restaurants, hours)) => {
+            restaurants.retain(|r| query.matches(&r.name, r.tags.as_deref()));
+            let now = db_api::local_now();
+            restaurants.retain(|r| {
+                hours.get(&r.id).map(|h| h.status_at(now)) == Some(db_api::OpenStatus::Open)
+            });
+            HttpResponse::Ok().json(restaurants)
+        }
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct RestaurantPath {
+    /// Restaurant id
+    id: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/restaurants/{id}",
+    tag = "dishes",
+    params(RestaurantPath, ListQuery),
+    responses((
+        status = 200,
+        description = "Dishes of the restaurant matching the query",
+        body = [Dish],
+    ),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+    )
+)]
+#[actix_web::get("/api/v1/restaurants/{id}")]
+pub(super) async fn dishes(
+    req: HttpRequest,
+    data: web::Data<ApiState>,
+    path: web::Path<RestaurantPath>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    cached_json(&req, &data, &[EntityKind::Dish], async {
+        let mut dishes = db_api::get_dish(&data.db_pool, path.id, None).await?;
+        dishes.retain(|d| query.matches(&d.name, d.tags.as_deref()));
+        Ok(dishes)
+    })
+    .await
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct DishesPath {
+    /// Dish id
+    id: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/dishes/{id}",
+    tag = "dishes",
+    params(DishesPath),
+    responses(
+        (status = 200, description = "Review of the dish", body = Review),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+    )
+)]
+#[actix_web::get("/api/v1/dishes/{id}")]
+pub(super) async fn reviewes(
+    req: HttpRequest,
+    data: web::Data<ApiState>,
+    path: web::Path<DishesPath>,
+) -> HttpResponse {
+    let prop = db_api::GetReviewPropsBuilder::default()
+        .dish_id(path.id)
+        .build()
+        .unwrap();
+    cached_json(
+        &req,
+        &data,
+        &[EntityKind::Review],
+        db_api::get_review(&data.db_pool, prop),
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/restaurants/{id}/rating",
+    tag = "restaurants",
+    params(RestaurantPath),
+    responses(
+        (status = 200, body = RatingSummary),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+    )
+)]
+#[actix_web::get("/api/v1/restaurants/{id}/rating")]
+pub(super) async fn restaurant_rating(
+    req: HttpRequest,
+    data: web::Data<ApiState>,
+    path: web::Path<RestaurantPath>,
+) -> HttpResponse {
+    let render = db_api::restaurant_rating(&data.db_pool, path.id);
+    cached_json(&req, &data, &[EntityKind::Dish, EntityKind::Review], render).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/dishes/{id}/rating",
+    tag = "dishes",
+    params(DishesPath),
+    responses(
+        (status = 200, body = RatingSummary),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+    )
+)]
+#[actix_web::get("/api/v1/dishes/{id}/rating")]
+pub(super) async fn dish_rating(
+    req: HttpRequest,
+    data: web::Data<ApiState>,
+    path: web::Path<DishesPath>,
+) -> HttpResponse {
+    let render = db_api::dish_rating(&data.db_pool, path.id);
+    cached_json(&req, &data, &[EntityKind::Review], render).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/dishes/{id}/price",
+    tag = "dishes",
+    params(DishesPath),
+    responses((status = 200, body = DishPrice))
+)]
+#[actix_web::get("/api/v1/dishes/{id}/price")]
+pub(super) async fn dish_price(
+    data: web::Data<ApiState>,
+    path: web::Path<DishesPath>,
+) -> HttpResponse {
+    match db_api::dish_price(&data.db_pool, path.id).await {
+        Ok(price) => HttpResponse::Ok().json(price),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(super) struct HoursResp {
+    #[serde(flatten)]
+    hours: OpeningHours,
+    status: OpenStatus,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/restaurants/{id}/hours",
+    tag = "restaurants",
+    params(RestaurantPath),
+    responses((
+        status = 200,
+        description = "Opening hours and whether the restaurant is open now",
+        body = HoursResp,
+    ))
+)]
+#[actix_web::get("/api/v1/restaurants/{id}/hours")]
+pub(super) async fn opening_hours(
+    data: web::Data<ApiState>,
+    path: web::Path<RestaurantPath>,
+) -> HttpResponse {
+    match db_api::opening_hours(&data.db_pool, path.id).await {
+        Ok(hours) => HttpResponse::Ok().json(HoursResp {
+            status: hours.status_at(db_api::local_now()),
+            hours,
+        }),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/restaurants/{id}/visits",
+    tag = "restaurants",
+    params(RestaurantPath),
+    responses((status = 200, body = VisitStats))
+)]
+#[actix_web::get("/api/v1/restaurants/{id}/visits")]
+pub(super) async fn restaurant_visits(
+    data: web::Data<ApiState>,
+    path: web::Path<RestaurantPath>,
+) -> HttpResponse {
+    match db_api::restaurant_visits(&data.db_pool, path.id).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct ReviewerPath {
+    /// Telegram user id of the reviewer
+    id: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/reviewers/{id}",
+    tag = "reviewers",
+    params(ReviewerPath),
+    responses((status = 200, body = ReviewerProfile))
+)]
+#[actix_web::get("/api/v1/reviewers/{id}")]
+pub(super) async fn reviewer_profile(
+    data: web::Data<ApiState>,
+    path: web::Path<ReviewerPath>,
+) -> HttpResponse {
+    match db_api::reviewer_profile(&data.db_pool, path.id).await {
+        Ok(profile) => HttpResponse::Ok().json(profile),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/reviewers/{id}/visits",
+    tag = "reviewers",
+    params(ReviewerPath),
+    responses((status = 200, body = VisitStats))
+)]
+#[actix_web::get("/api/v1/reviewers/{id}/visits")]
+pub(super) async fn reviewer_visits(
+    data: web::Data<ApiState>,
+    path: web::Path<ReviewerPath>,
+) -> HttpResponse {
+    match db_api::reviewer_visits(&data.db_pool, path.id).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags",
+    tag = "tags",
+    responses((
+        status = 200,
+        description = "Every tag in use, the most used first",
+        body = [TagCount],
+    ))
+)]
+#[actix_web::get("/api/v1/tags")]
+pub(super) async fn tag_cloud(data: web::Data<ApiState>) -> HttpResponse {
+    match db_api::tag_cloud(&data.db_pool).await {
+        Ok(cloud) => HttpResponse::Ok().json(cloud),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewRestaurantBody {
+    name: String,
+    address: String,
+    phone: Option<String>,
+    opening_hours: Option<String>,
+    tags: Option<String>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(super) struct CreatedResp {
+    id: i64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/restaurants",
+    tag = "restaurants",
+    request_body = NewRestaurantBody,
+    responses(
+        (status = 201, body = CreatedResp),
+        (status = 400, description = "Invalid opening hours", body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/restaurants")]
+pub(super) async fn create_restaurant(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    body: web::Json<NewRestaurantBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Create)?;
+
+    let body = body.into_inner();
+    let mut props = db_api::NewRestaurantPropsBuilder::default();
+    props.name(body.name).address(body.address);
+    if let Some(phone) = body.phone {
+        props.phone(phone);
+    }
+    if let Some(hours) = body.opening_hours {
+        if let Err(e) = hours.parse::<db_api::WeeklyHours>() {
+            return Err(ApiError::BadRequest(format!("{e:#}")));
+        }
+        props.opening_hours(hours);
+    }
+    if let Some(tags) = body.tags {
+        props.tags(tags);
+    }
+    let props = props
+        .build()
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let id = db_api::add_restaurant_detail(&data.db_pool, caller.actor(), props).await?;
+    Ok(HttpResponse::Created().json(CreatedResp { id }))
+}
+
+/// Fail with 404 before changing a restaurant that doesn't exist
+pub(super) async fn require_restaurant(pool: &db_api::Pool, id: i64) -> Result<(), ApiError> {
+    let found = db_api::get_restaurant(pool, db_api::RestaurantSearchProps::Id(id)).await?;
+    if found.is_empty() {
+        return Err(ApiError::NotFound(format!("restaurant {id} not found")));
+    }
+    Ok(())
+}
+
+pub(super) async fn require_dish(pool: &db_api::Pool, id: i64) -> Result<(), ApiError> {
+    if db_api::get_dishes(pool, &[id]).await?.is_empty() {
+        return Err(ApiError::NotFound(format!("dish {id} not found")));
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct UpdateRestaurantBody {
+    name: Option<String>,
+    address: Option<String>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/restaurants/{id}",
+    tag = "restaurants",
+    params(RestaurantPath),
+    request_body = UpdateRestaurantBody,
+    responses(
+        (status = 204),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+        (status = 404, description = "No restaurant with the id", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::patch("/api/v1/restaurants/{id}")]
+pub(super) async fn update_restaurant(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<UpdateRestaurantBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Edit)?;
+    require_restaurant(&data.db_pool, path.id).await?;
+
+    let UpdateRestaurantBody { name, address } = body.into_inner();
+    if name.is_some() || address.is_some() {
+        let props = db_api::UpdateRestaurantProps::UpdateDetails { name, address };
+        db_api::update_restaurant(&data.db_pool, caller.actor(), path.id, props).await?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/restaurants/{id}",
+    tag = "restaurants",
+    params(RestaurantPath),
+    responses(
+        (status = 204),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+        (status = 404, description = "No restaurant with the id", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::delete("/api/v1/restaurants/{id}")]
+pub(super) async fn delete_restaurant(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Delete)?;
+    require_restaurant(&data.db_pool, path.id).await?;
+
+    let props = db_api::UpdateRestaurantProps::Delete;
+    db_api::update_restaurant(&data.db_pool, caller.actor(), path.id, props).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct HoursBody {
+    /// Weekly hours like "10:00-22:00, Mon closed", or null to remove them
+    hours: Option<String>,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/restaurants/{id}/hours",
+    tag = "restaurants",
+    params(RestaurantPath),
+    request_body = HoursBody,
+    responses(
+        (status = 200, description = "The parsed weekly hours", body = Option<WeeklyHours>),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::put("/api/v1/restaurants/{id}/hours")]
+pub(super) async fn set_opening_hours(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<HoursBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Edit)?;
+
+    let hours = body.hours.as_deref();
+    let weekly = db_api::set_opening_hours(&data.db_pool, caller.actor(), path.id, hours)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::Ok().json(weekly))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct HoursExceptionBody {
+    start_date: chrono::NaiveDate,
+    /// Same as the start date if not given
+    end_date: Option<chrono::NaiveDate>,
+    /// Time ranges like "10:00-14:00", closed all day if not given
+    hours: Option<String>,
+    note: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/restaurants/{id}/hours/exceptions",
+    tag = "restaurants",
+    params(RestaurantPath),
+    request_body = HoursExceptionBody,
+    responses(
+        (status = 201, body = CreatedResp),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/restaurants/{id}/hours/exceptions")]
+pub(super) async fn add_hours_exception(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<HoursExceptionBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Edit)?;
+
+    let body = body.into_inner();
+    let dates = (body.start_date, body.end_date.unwrap_or(body.start_date));
+    let id = db_api::add_hours_exception(
+        &data.db_pool,
+        caller.actor(),
+        path.id,
+        dates,
+        body.hours.as_deref(),
+        body.note.as_deref(),
+    )
+    .await
+    .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::Created().json(CreatedResp { id }))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct HoursExceptionPath {
+    /// Restaurant id
+    id: i64,
+    /// Hours exception id
+    exception: i64,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/restaurants/{id}/hours/exceptions/{exception}",
+    tag = "restaurants",
+    params(HoursExceptionPath),
+    responses(
+        (status = 204),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::delete("/api/v1/restaurants/{id}/hours/exceptions/{exception}")]
+pub(super) async fn remove_hours_exception(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<HoursExceptionPath>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Edit)?;
+
+    db_api::remove_hours_exception(&data.db_pool, caller.actor(), path.id, path.exception)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewDishBody {
+    name: String,
+    image: Option<String>,
+    price: Option<f64>,
+    currency: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/restaurants/{id}/dishes",
+    tag = "dishes",
+    params(RestaurantPath),
+    request_body = NewDishBody,
+    responses(
+        (status = 201, body = CreatedResp),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+        (status = 404, description = "No restaurant with the id", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/restaurants/{id}/dishes")]
+pub(super) async fn create_dish(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<NewDishBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Create)?;
+    require_restaurant(&data.db_pool, path.id).await?;
+
+    let body = body.into_inner();
+    if let Some(price) = body.price {
+        db_api::check_price(price).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+    let price = body.price.map(|price| (price, body.currency.as_deref()));
+    let id = db_api::add_dish_with_price(
+        &data.db_pool,
+        caller.actor(),
+        path.id,
+        &body.name,
+        body.image,
+        price,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(CreatedResp { id }))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewVisitBody {
+    /// Today if not given
+    date: Option<chrono::NaiveDate>,
+    party_size: Option<u32>,
+    spend: Option<f64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/restaurants/{id}/visits",
+    tag = "restaurants",
+    params(RestaurantPath),
+    request_body = NewVisitBody,
+    responses(
+        (status = 201, body = CreatedResp),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/restaurants/{id}/visits")]
+pub(super) async fn create_visit(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<NewVisitBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Review)?;
+
+    let body = body.into_inner();
+    let mut props = db_api::NewVisitPropsBuilder::default();
+    props
+        .reviewer(caller.id)
+        .restaurant(db_api::RestaurantProp::Id(path.id));
+    if let Some(date) = body.date {
+        props.date(date);
+    }
+    if let Some(party_size) = body.party_size {
+        props.party_size(party_size);
+    }
+    if let Some(spend) = body.spend {
+        props.spend(spend);
+    }
+    let props = props.build().unwrap();
+    let id = db_api::add_visit(&data.db_pool, props)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::Created().json(CreatedResp { id }))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewPriceBody {
+    amount: f64,
+    currency: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/dishes/{id}/prices",
+    tag = "dishes",
+    params(DishesPath),
+    request_body = NewPriceBody,
+    responses(
+        (status = 201),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/dishes/{id}/prices")]
+pub(super) async fn report_price(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<DishesPath>,
+    body: web::Json<NewPriceBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Review)?;
+
+    let body = body.into_inner();
+    db_api::check_price(body.amount).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let currency = body.currency.as_deref();
+    db_api::report_price(
+        &data.db_pool,
+        caller.actor(),
+        path.id,
+        body.amount,
+        currency,
+    )
+    .await?;
+    Ok(HttpResponse::Created().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewReviewBody {
+    details: String,
+    score: u8,
+    /// Optional sub-scores like `{"taste": 5, "value": 3}`
+    #[serde(default)]
+    ratings: BTreeMap<db_api::RatingDimension, u8>,
+    price: Option<f64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/dishes/{id}/reviews",
+    tag = "dishes",
+    params(DishesPath),
+    request_body = NewReviewBody,
+    responses(
+        (status = 201),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+        (status = 404, description = "No dish with the id", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/dishes/{id}/reviews")]
+pub(super) async fn create_review(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<DishesPath>,
+    body: web::Json<NewReviewBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Review)?;
+    require_dish(&data.db_pool, path.id).await?;
+
+    let body = body.into_inner();
+    if body.score > 5 {
+        return Err(ApiError::BadRequest(
+            "score should be in range 0 - 5".to_string(),
+        ));
+    }
+    if let Some((dimension, _)) = body.ratings.iter().find(|(_, s)| **s > 5) {
+        return Err(ApiError::BadRequest(format!(
+            "{dimension} rating should be in range 0 - 5"
+        )));
+    }
+    let mut prop = db_api::NewReviewPropsBuilder::default();
+    prop.dish(db_api::DishProp::Id(path.id))
+        .reviewer(db_api::ReviewerProp::Id(caller.id))
+        .details(body.details)
+        .score(body.score)
+        .ratings(body.ratings.into_iter().collect());
+    if let Some(price) = body.price {
+        prop.price(price);
+    }
+    let prop = prop.build().unwrap();
+    db_api::add_new_review(&data.db_pool, prop).await?;
+    Ok(HttpResponse::Created().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct TagsBody {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+async fn edit_tags(
+    data: &ApiState,
+    caller: &Caller,
+    kind: db_api::EntityKind,
+    id: i64,
+    body: TagsBody,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Edit)?;
+
+    let edit = |e: anyhow::Error| ApiError::BadRequest(format!("{e:#}"));
+    let tags = db_api::edit_tags(
+        &data.db_pool,
+        caller.actor(),
+        kind,
+        id,
+        &body.add,
+        &body.remove,
+    )
+    .await
+    .map_err(edit)?;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/restaurants/{id}/tags",
+    tag = "tags",
+    params(RestaurantPath),
+    request_body = TagsBody,
+    responses(
+        (status = 200, description = "Tags of the restaurant after the edit", body = [String]),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/restaurants/{id}/tags")]
+pub(super) async fn restaurant_tags(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<RestaurantPath>,
+    body: web::Json<TagsBody>,
+) -> Result<HttpResponse, ApiError> {
+    let kind = db_api::EntityKind::Restaurant;
+    edit_tags(&data, &caller, kind, path.id, body.into_inner()).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/dishes/{id}/tags",
+    tag = "tags",
+    params(DishesPath),
+    request_body = TagsBody,
+    responses(
+        (status = 200, description = "Tags of the dish after the edit", body = [String]),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/dishes/{id}/tags")]
+pub(super) async fn dish_tags(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<DishesPath>,
+    body: web::Json<TagsBody>,
+) -> Result<HttpResponse, ApiError> {
+    let kind = db_api::EntityKind::Dish;
+    edit_tags(&data, &caller, kind, path.id, body.into_inner()).await
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct AuditQuery {
+    entity: Option<EntityKind>,
+    entity_id: Option<i64>,
+    actor: Option<i64>,
+    /// Id of the last entry in previous page
+    before: Option<i64>,
+    limit: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (
+            status = 200,
+            description = "Entries matching the query, the latest first",
+            body = [AuditEntry],
+        ),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::get("/api/v1/audit")]
+pub(super) async fn audit_log(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    let query = query.into_inner();
+    let mut props = db_api::AuditQueryBuilder::default();
+    if let Some(entity) = query.entity {
+        props.entity(entity);
+    }
+    if let Some(id) = query.entity_id {
+        props.entity_id(id);
+    }
+    if let Some(actor) = query.actor {
+        props.actor(actor);
+    }
+    if let Some(before) = query.before {
+        props.before_id(before);
+    }
+    if let Some(limit) = query.limit {
+        props.limit(limit.min(200));
+    }
+    let props = props
+        .build()
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let entries = db_api::get_audit_log(&data.db_pool, props).await?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct AuditPath {
+    /// Audit entry id
+    id: i64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/audit/{id}/revert",
+    tag = "audit",
+    params(AuditPath),
+    responses(
+        (status = 201, description = "Id of the entry recording the revert", body = CreatedResp),
+        (
+            status = 400,
+            description = "The entity changed again after the entry",
+            body = ErrJsonResp,
+        ),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/audit/{id}/revert")]
+pub(super) async fn revert_audit(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<AuditPath>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    // reverting fails when the entity changed again after the entry
+    let id = db_api::revert_audit(&data.db_pool, caller.actor(), path.id)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::Created().json(CreatedResp { id }))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewWebhookBody {
+    url: String,
+    /// Patterns like review.create, dish.* or *
+    events: Vec<String>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(super) struct WebhookCreatedResp {
+    id: i64,
+    /// Key of the `X-Webhook-Signature` HMAC, only shown once
+    secret: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhookBody,
+    responses(
+        (status = 201, body = WebhookCreatedResp),
+        (status = 400, body = ErrJsonResp),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::post("/api/v1/webhooks")]
+pub(super) async fn create_webhook(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    body: web::Json<NewWebhookBody>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    let (id, secret) = db_api::add_webhook(&data.db_pool, caller.actor(), &body.url, &body.events)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    Ok(HttpResponse::Created().json(WebhookCreatedResp { id, secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, body = [Webhook]),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::get("/api/v1/webhooks")]
+pub(super) async fn list_webhooks(
+    data: web::Data<ApiState>,
+    caller: Caller,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    let webhooks = db_api::list_webhooks(&data.db_pool).await?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct WebhookPath {
+    /// Webhook id
+    id: i64,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(WebhookPath),
+    responses(
+        (status = 204),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+        (status = 404, description = "No webhook with the id", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::delete("/api/v1/webhooks/{id}")]
+pub(super) async fn delete_webhook(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<WebhookPath>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    if !db_api::remove_webhook(&data.db_pool, caller.actor(), path.id).await? {
+        return Err(ApiError::NotFound(format!("webhook {} not found", path.id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct DeliveryQuery {
+    /// 50 by default, 200 at most
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(WebhookPath, DeliveryQuery),
+    responses(
+        (
+            status = 200,
+            description = "Deliveries of the webhook, the latest first",
+            body = [Delivery],
+        ),
+        (status = 401, description = "Missing or invalid API token", body = ErrJsonResp),
+        (status = 403, description = "The role of the caller isn't enough", body = ErrJsonResp),
+    ),
+    security(("token" = []))
+)]
+#[actix_web::get("/api/v1/webhooks/{id}/deliveries")]
+pub(super) async fn webhook_deliveries(
+    data: web::Data<ApiState>,
+    caller: Caller,
+    path: web::Path<WebhookPath>,
+    query: web::Query<DeliveryQuery>,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(Permission::Admin)?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let deliveries = db_api::delivery_log(&data.db_pool, path.id, limit).await?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct EventQuery {
+    /// Comma separated restaurant ids, only their events are sent
+    restaurant: Option<String>,
+}
+
+/// Live stream of the new restaurants, dishes, reviews and photos as Server-Sent Events
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "events",
+    params(EventQuery),
+    responses((
+        status = 200,
+        description = "Server-Sent Events, each `data:` line is a JSON event",
+        content_type = "text/event-stream",
+        body = Event,
+    ))
+)]
+#[actix_web::get("/api/v1/events")]
+pub(super) async fn events(query: web::Query<EventQuery>) -> HttpResponse {
+    let filter = query
+        .restaurant
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<i64>, _>>();
+    match filter {
+        Ok(ids) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(super::events::sse_stream(db_api::subscribe_events(), ids)),
+        Err(err) => HttpResponse::Ok().json(ErrJsonResp {
+            message: format!("invalid restaurant id: {err}"),
+        }),
+    }
+}
+
+#[actix_web::test]
+async fn test_mutating_routes_check_permission() {
+    use actix_web::test;
+
+    let db_pool = db_api::test_pool().await;
+    db_api::register_reviewer(&db_pool, 1, "reviewer")
+        .await
+        .unwrap();
+    let token = db_api::create_api_token(&db_pool, 1).await.unwrap();
+
+    let data = web::Data::new(ApiState::from_pool(db_pool.clone()));
+    let app = test::init_service(
+        actix_web::App::new()
+            .app_data(data.clone())
+            .service(create_restaurant)
+            .service(update_restaurant)
+            .service(delete_restaurant)
+            .service(create_dish)
+            .service(create_review),
+    )
+    .await;
+
+    let body = serde_json::json!({ "name": "KFC", "address": "WuHan" });
+    let req = test::TestRequest::post()
+        .uri("/api/v1/restaurants")
+        .set_json(&body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/restaurants")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(&body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // reviewer can't delete
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/restaurants/1")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    db_api::set_role(&data.db_pool, Actor::System, 1, Role::Admin)
+        .await
+        .unwrap();
+    // both fields in one change
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/restaurants/1")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(serde_json::json!({ "name": "KFC 光谷店", "address": "光谷" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let query = db_api::AuditQueryBuilder::default()
+        .entity(EntityKind::Restaurant)
+        .entity_id(1)
+        .build()
+        .unwrap();
+    let log = db_api::get_audit_log(&db_pool, query).await.unwrap();
+    assert_eq!(log.len(), 2);
+    let after = log[0].after.as_ref().unwrap();
+    assert_eq!(
+        (&after["name"], &after["address"]),
+        (&"KFC 光谷店".into(), &"光谷".into())
+    );
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/restaurants/1")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/restaurants/1")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(serde_json::json!({ "name": "KFC" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/restaurants/1")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // the parent of the new row is missing
+    let req = test::TestRequest::post()
+        .uri("/api/v1/restaurants/1/dishes")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(serde_json::json!({ "name": "汉堡" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/dishes/1/reviews")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(serde_json::json!({ "details": "", "score": 4 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(test)]
+fn read_app_state(db_pool: &db_api::Pool) -> web::Data<ApiState> {
+    web::Data::new(ApiState::from_pool(db_pool.clone()))
+}
+
+#[actix_web::test]
+async fn test_conditional_get() {
+    use actix_web::test;
+
+    let db_pool = db_api::test_pool().await;
+    let data = read_app_state(&db_pool);
+    let rid = db_api::add_restaurant(&data.db_pool, Actor::System, "KFC", "WuHan")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        actix_web::App::new()
+            .app_data(data.clone())
+            .service(restaurants)
+            .service(dishes),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/restaurants")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "no-cache");
+    let etag = resp.headers().get("etag").unwrap().clone();
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body[0]["name"], "KFC");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/restaurants")
+        .insert_header(("If-None-Match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers().get("etag").unwrap(), etag);
+
+    // served from the cache without rendering again
+    let req = test::TestRequest::get()
+        .uri("/api/v1/restaurants")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body[0]["name"], "KFC");
+    assert_eq!(data.cache.stats(), (2, 1));
+
+    // a write of another kind keeps the tag
+    db_api::add_dish(&data.db_pool, Actor::System, rid, "Burger", None)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/restaurants")
+        .insert_header(("If-None-Match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    db_api::add_restaurant(&data.db_pool, Actor::System, "McDonald", "WuHan")
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/restaurants")
+        .insert_header(("If-None-Match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_ne!(resp.headers().get("etag").unwrap(), etag);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert_eq!(data.cache.stats(), (3, 2));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/restaurants/{rid}"))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body[0]["name"], "Burger");
+}
+
+/// Compare the read routes rendered every time, served from the cache and answered with 304.
+///
+/// `cargo test --release --lib bench_read_cache -- --ignored --nocapture`
+#[actix_web::test]
+#[ignore]
+async fn bench_read_cache() {
+    use actix_web::test;
+    use std::time::Instant;
+
+    const REQUESTS: u32 = 500;
+
+    let db_pool = db_api::test_pool().await;
+    let data = read_app_state(&db_pool);
+    for i in 0..200 {
+        let rid = db_api::add_restaurant(
+            &data.db_pool,
+            Actor::System,
+            &format!("restaurant {i}"),
+            "WuHan",
+        )
+        .await
+        .unwrap();
+        for j in 0..5 {
+            db_api::add_dish(
+                &data.db_pool,
+                Actor::System,
+                rid,
+                &format!("dish {i}-{j}"),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+    }
+    let app = test::init_service(
+        actix_web::App::new()
+            .app_data(data.clone())
+            .service(restaurants),
+    )
+    .await;
+
+    // open_now bypasses the cache, every request queries the database
+    let start = Instant::now();
+    for _ in 0..REQUESTS {
+        let req = test::TestRequest::get()
+            .uri("/api/v1/restaurants?open_now=true")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+    println!("uncached: {:?}/request", start.elapsed() / REQUESTS);
+
+    let mut etag = None;
+    let start = Instant::now();
+    for _ in 0..REQUESTS {
+        let req = test::TestRequest::get()
+            .uri("/api/v1/restaurants")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        etag = Some(resp.headers().get("etag").unwrap().clone());
+    }
+    println!("cached: {:?}/request", start.elapsed() / REQUESTS);
+
+    let start = Instant::now();
+    for _ in 0..REQUESTS {
+        let req = test::TestRequest::get()
+            .uri("/api/v1/restaurants")
+            .insert_header(("If-None-Match", etag.clone().unwrap()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    }
+    println!("not modified: {:?}/request", start.elapsed() / REQUESTS);
+
+    let (hits, misses) = data.cache.stats();
+    println!("cache hits: {hits}, rendered from the database: {misses}");
+}
@@ -0,0 +1,54 @@
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// responses kept at most, the search queries make the keys unbounded
+const CAPACITY: usize = 1024;
+
+/// Rendered responses of the read routes by URI, with the ETag they are rendered at.
+///
+/// The ETag comes from the version counters of the data, which every write bumps, so a changed
+/// ETag replaces the stale body on the next read.
+#[derive(Default)]
+pub(super) struct ReadCache {
+    entries: Mutex<HashMap<String, (String, Bytes)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ReadCache {
+    /// Body of the URI if it's rendered at the ETag
+    pub(super) fn get(&self, uri: &str, etag: &str) -> Option<Bytes> {
+        let entries = self.entries.lock().unwrap();
+        let body = entries
+            .get(uri)
+            .filter(|(tag, _)| tag == etag)
+            .map(|(_, body)| body.clone());
+        drop(entries);
+        self.count(body.is_some());
+        body
+    }
+
+    pub(super) fn put(&self, uri: &str, etag: &str, body: Bytes) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= CAPACITY && !entries.contains_key(uri) {
+            entries.clear();
+        }
+        entries.insert(uri.to_string(), (etag.to_string(), body));
+    }
+
+    /// Count the request answered without rendering, or the rendered one
+    pub(super) fn count(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Requests answered from the cache or with 304, and the ones rendered from the database
+    pub(super) fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
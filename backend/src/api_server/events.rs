@@ -0,0 +1,76 @@
+use crate::db::Event;
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+// a comment is sent when nothing happens in this interval, so the proxies keep the connection
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// How often the database is checked for the rows added by the bot
+pub(super) const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Format the events as Server-Sent Events, only the ones in `restaurants` if it isn't empty.
+///
+/// Every event is a `data:` line of the JSON [`Event`]. When the client is too slow and some
+/// events are dropped, a `lagged` event tells how many, and the client should refetch.
+pub(super) fn sse_stream(
+    events: Receiver<Event>,
+    restaurants: Vec<i64>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    stream::unfold(
+        (events, restaurants),
+        |(mut events, restaurants)| async move {
+            let frame = loop {
+                match tokio::time::timeout(KEEP_ALIVE, events.recv()).await {
+                    Err(_) => break ": keep-alive\n\n".to_string(),
+                    Ok(Ok(event)) => {
+                        if restaurants.is_empty() || restaurants.contains(&event.restaurant()) {
+                            let data =
+                                serde_json::to_string(&event).expect("event is serializable");
+                            break format!("data: {data}\n\n");
+                        }
+                    }
+                    Ok(Err(RecvError::Lagged(missed))) => {
+                        break format!("event: lagged\ndata: {missed}\n\n")
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                }
+            };
+            Some((Ok(Bytes::from(frame)), (events, restaurants)))
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_sse_stream() {
+    use futures_util::StreamExt;
+    use tokio::sync::broadcast;
+
+    let (sender, receiver) = broadcast::channel(1);
+    let mut frames = Box::pin(sse_stream(receiver, vec![2]));
+    for restaurant in [1, 2] {
+        sender
+            .send(Event::DishAdded {
+                id: 10 + restaurant,
+                restaurant,
+                actor: None,
+            })
+            .unwrap();
+    }
+    // the capacity is 1, the first event is dropped
+    let frame = frames.next().await.unwrap().unwrap();
+    assert_eq!(frame, "event: lagged\ndata: 1\n\n");
+    let frame = frames.next().await.unwrap().unwrap();
+    assert_eq!(
+        frame,
+        r#"data: {"type":"dish_added","id":12,"restaurant":2,"actor":null}"#.to_string() + "\n\n"
+    );
+
+    // filtered out
+    sender
+        .send(Event::RestaurantAdded { id: 1, actor: None })
+        .unwrap();
+    drop(sender);
+    assert!(frames.next().await.is_none());
+}
@@ -0,0 +1,738 @@
+use super::api::{require_dish, require_restaurant, ApiError, ApiState, Caller};
+use crate::db::{self as db_api, Permission, Pool};
+use actix_web::{web, HttpResponse};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptySubscription, Enum, ErrorExtensions, InputObject, Object,
+    Schema, SimpleObject,
+};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// restaurant -> dishes -> reviews -> reviewer with some room for the wrapping fields
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 500;
+
+pub(super) type ApiSchema = Schema<Query, Mutation, EmptySubscription>;
+
+pub(super) fn schema() -> ApiSchema {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+#[actix_web::post("/graphql")]
+pub(super) async fn graphql(
+    schema: web::Data<ApiSchema>,
+    data: web::Data<ApiState>,
+    caller: Option<Caller>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut request = request
+        .into_inner()
+        .data(data.db_pool())
+        .data(DataLoader::new(BatchLoader(data.db_pool()), tokio::spawn));
+    // anonymous callers can still query, the mutations check the permission
+    if let Some(caller) = caller {
+        request = request.data(caller);
+    }
+    schema.execute(request).await.into()
+}
+
+#[actix_web::get("/graphql")]
+pub(super) async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+/// The error as the REST routes answer it, with the kind in the `code` extension. The details of
+/// the internal errors are only logged.
+fn api_error(err: ApiError) -> async_graphql::Error {
+    let code = match &err {
+        ApiError::Unauthorized => "UNAUTHORIZED",
+        ApiError::Forbidden(_) => "FORBIDDEN",
+        ApiError::BadRequest(_) => "BAD_REQUEST",
+        ApiError::NotFound(_) => "NOT_FOUND",
+        ApiError::TooManyRequests(_) => "TOO_MANY_REQUESTS",
+        ApiError::Internal(e) => {
+            tracing::error!("graphql request failed: {e:#}");
+            return async_graphql::Error::new("internal error")
+                .extend_with(|_, ext| ext.set("code", "INTERNAL"));
+        }
+    };
+    async_graphql::Error::new(err.to_string()).extend_with(|_, ext| ext.set("code", code))
+}
+
+fn internal(err: anyhow::Error) -> async_graphql::Error {
+    api_error(ApiError::Internal(err))
+}
+
+fn bad_request(err: impl std::fmt::Display) -> async_graphql::Error {
+    api_error(ApiError::BadRequest(format!("{err:#}")))
+}
+
+// the checks of the database functions refuse the input, a failed query is internal
+fn db_error(err: anyhow::Error) -> async_graphql::Error {
+    if err.chain().any(|e| e.is::<sqlx::Error>()) {
+        internal(err)
+    } else {
+        bad_request(err)
+    }
+}
+
+fn require<'a>(ctx: &Context<'a>, permission: Permission) -> async_graphql::Result<&'a Caller> {
+    let caller = ctx
+        .data_opt::<Caller>()
+        .ok_or_else(|| api_error(ApiError::Unauthorized))?;
+    caller.require(permission).map_err(api_error)?;
+    Ok(caller)
+}
+
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.map(db_api::split_tags).unwrap_or_default()
+}
+
+/// Load the rows of all the parents resolved together in one query
+pub(super) struct BatchLoader(Pool);
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct RestaurantId(i64);
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct DishId(i64);
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct ReviewerId(i64);
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct DishesOf(i64);
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct ReviewsOf(i64);
+
+type LoadResult<K, V> = Result<HashMap<K, V>, Arc<anyhow::Error>>;
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<RestaurantId> for BatchLoader {
+    type Value = db_api::Restaurant;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[RestaurantId]) -> LoadResult<RestaurantId, Self::Value> {
+        let ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let rows = db_api::get_restaurants(&self.0, &ids).await?;
+        Ok(rows.into_iter().map(|r| (RestaurantId(r.id), r)).collect())
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<DishId> for BatchLoader {
+    type Value = db_api::Dish;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[DishId]) -> LoadResult<DishId, Self::Value> {
+        let ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let rows = db_api::get_dishes(&self.0, &ids).await?;
+        Ok(rows.into_iter().map(|d| (DishId(d.id), d)).collect())
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<ReviewerId> for BatchLoader {
+    type Value = db_api::Reviewer;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[ReviewerId]) -> LoadResult<ReviewerId, Self::Value> {
+        let ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let rows = db_api::get_reviewers(&self.0, &ids).await?;
+        Ok(rows.into_iter().map(|r| (ReviewerId(r.id), r)).collect())
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<DishesOf> for BatchLoader {
+    type Value = Vec<db_api::Dish>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[DishesOf]) -> LoadResult<DishesOf, Self::Value> {
+        let ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let mut grouped = keys
+            .iter()
+            .map(|k| (*k, Vec::new()))
+            .collect::<HashMap<_, _>>();
+        for dish in db_api::get_dishes_of(&self.0, &ids).await? {
+            grouped.entry(DishesOf(dish.rid)).or_default().push(dish);
+        }
+        Ok(grouped)
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<ReviewsOf> for BatchLoader {
+    type Value = Vec<db_api::Review>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[ReviewsOf]) -> LoadResult<ReviewsOf, Self::Value> {
+        let ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let mut grouped = keys
+            .iter()
+            .map(|k| (*k, Vec::new()))
+            .collect::<HashMap<_, _>>();
+        for (dish, review) in db_api::get_reviews_of(&self.0, &ids).await? {
+            grouped.entry(ReviewsOf(dish)).or_default().push(review);
+        }
+        Ok(grouped)
+    }
+}
+
+fn loader<'a>(ctx: &Context<'a>) -> &'a DataLoader<BatchLoader> {
+    ctx.data_unchecked::<DataLoader<BatchLoader>>()
+}
+
+pub(super) struct Restaurant(db_api::Restaurant);
+
+#[Object]
+impl Restaurant {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn address(&self) -> &str {
+        &self.0.address
+    }
+
+    async fn phone(&self) -> Option<&str> {
+        self.0.phone.as_deref()
+    }
+
+    /// Weekly hours like `Mon-Fri 10:00-22:00`
+    async fn opening_hours(&self) -> Option<&str> {
+        self.0.opening_hours.as_deref()
+    }
+
+    async fn tags(&self) -> Vec<String> {
+        split_tags(self.0.tags.as_deref())
+    }
+
+    async fn latitude(&self) -> Option<f64> {
+        self.0.latitude
+    }
+
+    async fn longitude(&self) -> Option<f64> {
+        self.0.longitude
+    }
+
+    async fn dishes(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Dish>> {
+        let dishes = loader(ctx).load_one(DishesOf(self.0.id)).await?;
+        Ok(dishes.unwrap_or_default().into_iter().map(Dish).collect())
+    }
+}
+
+pub(super) struct Dish(db_api::Dish);
+
+#[Object]
+impl Dish {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn image(&self) -> Option<&str> {
+        self.0.image.as_deref()
+    }
+
+    /// Latest reported price
+    async fn price(&self) -> Option<f64> {
+        self.0.price
+    }
+
+    async fn currency(&self) -> Option<&str> {
+        self.0.currency.as_deref()
+    }
+
+    async fn tags(&self) -> Vec<String> {
+        split_tags(self.0.tags.as_deref())
+    }
+
+    async fn restaurant(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Restaurant>> {
+        let restaurant = loader(ctx).load_one(RestaurantId(self.0.rid)).await?;
+        Ok(restaurant.map(Restaurant))
+    }
+
+    async fn reviews(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Review>> {
+        let reviews = loader(ctx).load_one(ReviewsOf(self.0.id)).await?;
+        Ok(reviews
+            .unwrap_or_default()
+            .into_iter()
+            .map(Review)
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub(super) struct Rating {
+    dimension: String,
+    score: u8,
+}
+
+pub(super) struct Review(db_api::Review);
+
+#[Object]
+impl Review {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    /// Overall score in 0 - 5
+    async fn score(&self) -> u8 {
+        self.0.score
+    }
+
+    async fn details(&self) -> &str {
+        &self.0.details
+    }
+
+    async fn price(&self) -> Option<f64> {
+        self.0.price
+    }
+
+    /// Sub-scores, empty for the reviews with only the overall score
+    async fn ratings(&self) -> Vec<Rating> {
+        self.0
+            .ratings
+            .iter()
+            .map(|(dimension, score)| Rating {
+                dimension: dimension.to_string(),
+                score: *score,
+            })
+            .collect()
+    }
+
+    async fn reviewer(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Reviewer>> {
+        let reviewer = loader(ctx).load_one(ReviewerId(self.0.reviewer)).await?;
+        Ok(reviewer.map(Reviewer))
+    }
+}
+
+pub(super) struct Reviewer(db_api::Reviewer);
+
+#[Object]
+impl Reviewer {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    async fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    async fn role(&self) -> String {
+        self.0.role.to_string()
+    }
+}
+
+pub(super) struct Query;
+
+#[Object]
+impl Query {
+    /// Restaurants whose name contains `q` and that have every one of the comma separated `tags`
+    async fn restaurants(
+        &self,
+        ctx: &Context<'_>,
+        q: Option<String>,
+        tags: Option<String>,
+    ) -> async_graphql::Result<Vec<Restaurant>> {
+        let pool = ctx.data_unchecked::<Pool>();
+        let wanted = split_tags(tags.as_deref());
+        let keyword = q.as_deref().map(str::to_lowercase).unwrap_or_default();
+        let restaurants = db_api::get_restaurant(pool, db_api::RestaurantSearchProps::All)
+            .await
+            .map_err(internal)?
+            .into_iter()
+            .filter(|r| {
+                db_api::has_tags(r.tags.as_deref(), &wanted)
+                    && r.name.to_lowercase().contains(&keyword)
+            })
+            .map(Restaurant)
+            .collect();
+        Ok(restaurants)
+    }
+
+    async fn restaurant(
+        &self,
+        ctx: &Context<'_>,
+        id: i64,
+    ) -> async_graphql::Result<Option<Restaurant>> {
+        let restaurant = loader(ctx).load_one(RestaurantId(id)).await?;
+        Ok(restaurant.map(Restaurant))
+    }
+
+    async fn dish(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<Dish>> {
+        let dish = loader(ctx).load_one(DishId(id)).await?;
+        Ok(dish.map(Dish))
+    }
+
+    async fn reviewer(
+        &self,
+        ctx: &Context<'_>,
+        id: i64,
+    ) -> async_graphql::Result<Option<Reviewer>> {
+        let reviewer = loader(ctx).load_one(ReviewerId(id)).await?;
+        Ok(reviewer.map(Reviewer))
+    }
+}
+
+#[derive(InputObject)]
+pub(super) struct NewRestaurant {
+    name: String,
+    address: String,
+    phone: Option<String>,
+    opening_hours: Option<String>,
+    /// Comma separated tags
+    tags: Option<String>,
+}
+
+#[derive(InputObject)]
+pub(super) struct RatingInput {
+    /// taste, portion, value, speed or spiciness
+    dimension: String,
+    score: u8,
+}
+
+/// The rows that have tags
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Tagged {
+    Restaurant,
+    Dish,
+}
+
+pub(super) struct Mutation;
+
+/// The writes of restaurants, dishes, reviews, visits, prices and tags, with the same permissions
+/// as the REST routes and the caller from the `Authorization: Bearer <token>` header. Opening
+/// hours, the audit log and the webhooks are managed with the REST routes only. An error tells
+/// its kind, like `NOT_FOUND` or `BAD_REQUEST`, in the `code` extension.
+#[Object]
+impl Mutation {
+    async fn add_restaurant(
+        &self,
+        ctx: &Context<'_>,
+        restaurant: NewRestaurant,
+    ) -> async_graphql::Result<i64> {
+        let caller = require(ctx, Permission::Create)?;
+        let pool = ctx.data_unchecked::<Pool>();
+
+        let mut props = db_api::NewRestaurantPropsBuilder::default();
+        props.name(restaurant.name).address(restaurant.address);
+        if let Some(phone) = restaurant.phone {
+            props.phone(phone);
+        }
+        if let Some(hours) = restaurant.opening_hours {
+            hours.parse::<db_api::WeeklyHours>().map_err(bad_request)?;
+            props.opening_hours(hours);
+        }
+        if let Some(tags) = restaurant.tags {
+            props.tags(tags);
+        }
+        let props = props.build().map_err(bad_request)?;
+        let id = db_api::add_restaurant_detail(pool, caller.actor(), props)
+            .await
+            .map_err(db_error)?;
+        Ok(id)
+    }
+
+    /// Rename the restaurant or change its address
+    async fn update_restaurant(
+        &self,
+        ctx: &Context<'_>,
+        id: i64,
+        name: Option<String>,
+        address: Option<String>,
+    ) -> async_graphql::Result<bool> {
+        let caller = require(ctx, Permission::Edit)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        require_restaurant(pool, id).await.map_err(api_error)?;
+
+        if name.is_some() || address.is_some() {
+            let props = db_api::UpdateRestaurantProps::UpdateDetails { name, address };
+            db_api::update_restaurant(pool, caller.actor(), id, props)
+                .await
+                .map_err(db_error)?;
+        }
+        Ok(true)
+    }
+
+    async fn delete_restaurant(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<bool> {
+        let caller = require(ctx, Permission::Delete)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        require_restaurant(pool, id).await.map_err(api_error)?;
+
+        let props = db_api::UpdateRestaurantProps::Delete;
+        db_api::update_restaurant(pool, caller.actor(), id, props)
+            .await
+            .map_err(db_error)?;
+        Ok(true)
+    }
+
+    /// Add the dish, with the price it costs now
+    async fn add_dish(
+        &self,
+        ctx: &Context<'_>,
+        restaurant: i64,
+        name: String,
+        image: Option<String>,
+        price: Option<f64>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<i64> {
+        let caller = require(ctx, Permission::Create)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        require_restaurant(pool, restaurant)
+            .await
+            .map_err(api_error)?;
+
+        let price = price.map(|price| (price, currency.as_deref()));
+        let id = db_api::add_dish_with_price(pool, caller.actor(), restaurant, &name, image, price)
+            .await
+            .map_err(db_error)?;
+        Ok(id)
+    }
+
+    /// Report the price paid for the dish, in CNY if the currency is not given
+    async fn report_price(
+        &self,
+        ctx: &Context<'_>,
+        dish: i64,
+        amount: f64,
+        currency: Option<String>,
+    ) -> async_graphql::Result<bool> {
+        let caller = require(ctx, Permission::Review)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        db_api::check_price(amount).map_err(bad_request)?;
+        require_dish(pool, dish).await.map_err(api_error)?;
+
+        db_api::report_price(pool, caller.actor(), dish, amount, currency.as_deref())
+            .await
+            .map_err(db_error)?;
+        Ok(true)
+    }
+
+    /// Add and remove the tags of a restaurant or a dish, return the tags after the edit
+    async fn edit_tags(
+        &self,
+        ctx: &Context<'_>,
+        of: Tagged,
+        id: i64,
+        #[graphql(default)] add: Vec<String>,
+        #[graphql(default)] remove: Vec<String>,
+    ) -> async_graphql::Result<Vec<String>> {
+        let caller = require(ctx, Permission::Edit)?;
+        let pool = ctx.data_unchecked::<Pool>();
+
+        let kind = match of {
+            Tagged::Restaurant => db_api::EntityKind::Restaurant,
+            Tagged::Dish => db_api::EntityKind::Dish,
+        };
+        match of {
+            Tagged::Restaurant => require_restaurant(pool, id).await,
+            Tagged::Dish => require_dish(pool, id).await,
+        }
+        .map_err(api_error)?;
+        let tags = db_api::edit_tags(pool, caller.actor(), kind, id, &add, &remove)
+            .await
+            .map_err(db_error)?;
+        Ok(tags)
+    }
+
+    /// Log a visit of the caller to the restaurant, on `date` like 2023-02-19 or today
+    async fn add_visit(
+        &self,
+        ctx: &Context<'_>,
+        restaurant: i64,
+        date: Option<String>,
+        party_size: Option<u32>,
+        spend: Option<f64>,
+    ) -> async_graphql::Result<i64> {
+        let caller = require(ctx, Permission::Review)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        require_restaurant(pool, restaurant)
+            .await
+            .map_err(api_error)?;
+
+        let mut props = db_api::NewVisitPropsBuilder::default();
+        props
+            .reviewer(caller.id)
+            .restaurant(db_api::RestaurantProp::Id(restaurant));
+        if let Some(date) = date {
+            props.date(date.parse::<chrono::NaiveDate>().map_err(bad_request)?);
+        }
+        if let Some(party_size) = party_size {
+            props.party_size(party_size);
+        }
+        if let Some(spend) = spend {
+            props.spend(spend);
+        }
+        let props = props.build().map_err(bad_request)?;
+        let id = db_api::add_visit(pool, props).await.map_err(db_error)?;
+        Ok(id)
+    }
+
+    /// Review the dish as the caller
+    async fn add_review(
+        &self,
+        ctx: &Context<'_>,
+        dish: i64,
+        score: u8,
+        #[graphql(default)] details: String,
+        #[graphql(default)] ratings: Vec<RatingInput>,
+        price: Option<f64>,
+    ) -> async_graphql::Result<bool> {
+        let caller = require(ctx, Permission::Review)?;
+        let pool = ctx.data_unchecked::<Pool>();
+        require_dish(pool, dish).await.map_err(api_error)?;
+
+        let ratings = ratings
+            .into_iter()
+            .map(|r| Ok((r.dimension.parse()?, r.score)))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(bad_request)?;
+        let mut prop = db_api::NewReviewPropsBuilder::default();
+        prop.dish(db_api::DishProp::Id(dish))
+            .reviewer(db_api::ReviewerProp::Id(caller.id))
+            .details(details)
+            .score(score)
+            .ratings(ratings);
+        if let Some(price) = price {
+            prop.price(price);
+        }
+        let prop = prop.build().map_err(bad_request)?;
+        db_api::add_new_review(pool, prop).await.map_err(db_error)?;
+        Ok(true)
+    }
+}
+
+#[actix_web::test]
+async fn test_graphql() {
+    use actix_web::test;
+    use db_api::Actor;
+    use serde_json::{json, Value};
+
+    let db_pool = db_api::test_pool().await;
+    db_api::register_reviewer(&db_pool, 1, "alice")
+        .await
+        .unwrap();
+    let token = db_api::create_api_token(&db_pool, 1).await.unwrap();
+    for name in ["KFC", "McDonald"] {
+        let id = db_api::add_restaurant(&db_pool, Actor::System, name, "光谷")
+            .await
+            .unwrap();
+        for dish in ["汉堡", "薯条"] {
+            db_api::add_dish(&db_pool, Actor::System, id, dish, None)
+                .await
+                .unwrap();
+        }
+    }
+
+    let app = test::init_service(
+        actix_web::App::new()
+            .app_data(web::Data::new(ApiState::from_pool(db_pool.clone())))
+            .app_data(web::Data::new(schema()))
+            .service(graphql),
+    )
+    .await;
+    let call = |query: &str, token: Option<&str>| {
+        let mut req = test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": query }));
+        if let Some(token) = token {
+            req = req.insert_header(("Authorization", format!("Bearer {token}")));
+        }
+        test::call_and_read_body_json::<_, _, Value>(&app, req.to_request())
+    };
+
+    let resp = call(
+        r#"mutation { addReview(dish: 1, score: 4, ratings: [{dimension: "taste", score: 5}]) }"#,
+        None,
+    )
+    .await;
+    assert_eq!(resp["errors"][0]["message"], "missing or invalid API token");
+    let resp = call(
+        r#"mutation { addReview(dish: 1, score: 4, ratings: [{dimension: "taste", score: 5}]) }"#,
+        Some(&token),
+    )
+    .await;
+    assert_eq!(resp["data"]["addReview"], true);
+    let resp = call(r#"mutation { deleteRestaurant(id: 1) }"#, Some(&token)).await;
+    assert!(resp["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("admin"));
+    let resp = call(
+        r#"mutation { reportPrice(dish: 2, amount: 12.5) addVisit(restaurant: 1, date: "2023-02-19") }"#,
+        Some(&token),
+    )
+    .await;
+    assert_eq!(resp["data"]["reportPrice"], true);
+    assert!(resp["data"]["addVisit"].is_i64());
+    // tagging needs an editor
+    let resp = call(
+        r#"mutation { editTags(of: DISH, id: 1, add: ["辣"]) }"#,
+        Some(&token),
+    )
+    .await;
+    assert!(resp["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("editor"));
+    assert_eq!(resp["errors"][0]["extensions"]["code"], "FORBIDDEN");
+    // the input is refused with its reason, the missing rows are told apart
+    let resp = call(r#"mutation { addReview(dish: 1, score: 9) }"#, Some(&token)).await;
+    assert_eq!(resp["errors"][0]["extensions"]["code"], "BAD_REQUEST");
+    assert_eq!(
+        resp["errors"][0]["message"],
+        "score should be in range 0 - 5"
+    );
+    let resp = call(
+        r#"mutation { addDish(restaurant: 100, name: "汉堡") }"#,
+        Some(&token),
+    )
+    .await;
+    assert_eq!(resp["errors"][0]["extensions"]["code"], "NOT_FOUND");
+    assert_eq!(resp["errors"][0]["message"], "restaurant 100 not found");
+
+    let resp = call(
+        r#"{ restaurants(q: "kfc") { name dishes { name reviews {
+            score ratings { dimension score } reviewer { name } } } } }"#,
+        None,
+    )
+    .await;
+    assert_eq!(
+        resp["data"]["restaurants"],
+        json!([{
+            "name": "KFC",
+            "dishes": [
+                {
+                    "name": "汉堡",
+                    "reviews": [{
+                        "score": 4,
+                        "ratings": [{ "dimension": "taste", "score": 5 }],
+                        "reviewer": { "name": "alice" },
+                    }],
+                },
+                { "name": "薯条", "reviews": [] },
+            ],
+        }])
+    );
+
+    let resp = call(
+        r#"{ dish(id: 1) { restaurant { dishes { restaurant { dishes {
+            restaurant { dishes { restaurant { name } } } } } } } } }"#,
+        None,
+    )
+    .await;
+    assert_eq!(resp["errors"][0]["message"], "Query is nested too deep.");
+}
@@ -0,0 +1,84 @@
+use actix_web::dev::ServiceResponse;
+use prometheus::{register_histogram_vec, HistogramVec};
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+fn request_duration() -> &'static HistogramVec {
+    static METRIC: OnceLock<HistogramVec> = OnceLock::new();
+    METRIC.get_or_init(|| {
+        register_histogram_vec!(
+            "http_request_duration_seconds",
+            "Latency of the HTTP requests by route and status",
+            &["method", "route", "status"]
+        )
+        .expect("fail to register metric")
+    })
+}
+
+fn query_duration() -> &'static HistogramVec {
+    static METRIC: OnceLock<HistogramVec> = OnceLock::new();
+    METRIC.get_or_init(|| {
+        register_histogram_vec!(
+            "db_query_duration_seconds",
+            "Time spent in the database queries",
+            &["query"]
+        )
+        .expect("fail to register metric")
+    })
+}
+
+/// Record the latency of the request started at `start`, labelled by the route pattern so the
+/// ids in the path don't make a series each
+pub(super) fn observe<B>(start: Instant, resp: &ServiceResponse<B>) {
+    let req = resp.request();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    request_duration()
+        .with_label_values(&[req.method().as_str(), &route, resp.status().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+/// Await the database work and record its time under `query`
+pub(super) async fn time_query<T>(query: &str, work: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let result = work.await;
+    query_duration()
+        .with_label_values(&[query])
+        .observe(start.elapsed().as_secs_f64());
+    result
+}
+
+#[actix_web::test]
+async fn test_request_metrics() {
+    use actix_web::{dev::Service, test, web, App, HttpResponse};
+
+    let app = test::init_service(
+        App::new()
+            .wrap_fn(|req, srv| {
+                let start = Instant::now();
+                let resp = srv.call(req);
+                async move {
+                    let resp = resp.await?;
+                    observe(start, &resp);
+                    Ok(resp)
+                }
+            })
+            .route(
+                "/items/{id}",
+                web::get().to(|| time_query("item", async { HttpResponse::Ok().finish() })),
+            ),
+    )
+    .await;
+    for uri in ["/items/1", "/items/2", "/missing"] {
+        test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    }
+
+    let count = |metric: &HistogramVec, labels: &[&str]| {
+        metric.with_label_values(labels).get_sample_count()
+    };
+    assert_eq!(count(request_duration(), &["GET", "/items/{id}", "200"]), 2);
+    assert_eq!(count(request_duration(), &["GET", "unmatched", "404"]), 1);
+    assert_eq!(count(query_duration(), &["item"]), 2);
+}
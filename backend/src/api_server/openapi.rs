@@ -0,0 +1,227 @@
+use super::api;
+use crate::db;
+use actix_web::web;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// Where the generated document is served
+pub(super) const SPEC_PATH: &str = "/api/v1/openapi.json";
+
+// The document and the registration come from the same list, so a route can't be served without
+// being documented. The paths are checked against the routing in the tests.
+macro_rules! routes {
+    ($($handler:ident),* $(,)?) => {
+        /// OpenAPI document of the REST routes
+        #[derive(OpenApi)]
+        #[openapi(
+            info(
+                title = "meal-review",
+                description = "Restaurants, dishes and reviews of the review bot. The GET routes \
+                    answer the failures with status 200 and an `ErrJsonResp` body."
+            ),
+            paths($(api::$handler),*),
+            components(schemas(
+                api::ErrJsonResp,
+                api::CreatedResp,
+                api::HoursResp,
+                api::WebhookCreatedResp,
+                api::NewRestaurantBody,
+                api::UpdateRestaurantBody,
+                api::HoursBody,
+                api::HoursExceptionBody,
+                api::NewDishBody,
+                api::NewVisitBody,
+                api::NewPriceBody,
+                api::NewReviewBody,
+                api::TagsBody,
+                api::NewWebhookBody,
+                db::Restaurant,
+                db::Dish,
+                db::Review,
+                db::RatingDimension,
+                db::RatingSummary,
+                db::DishPrice,
+                db::PricePoint,
+                db::PriceTrend,
+                db::OpeningHours,
+                db::WeeklyHours,
+                db::HoursException,
+                db::OpenStatus,
+                db::VisitStats,
+                db::VisitCount,
+                db::Visit,
+                db::TagCount,
+                db::ReviewerProfile,
+                db::CuisineCount,
+                db::ReviewCount,
+                db::RatingTendency,
+                db::Role,
+                db::AuditEntry,
+                db::AuditAction,
+                db::EntityKind,
+                db::Webhook,
+                db::Delivery,
+                db::Event,
+            )),
+            modifiers(&BearerToken)
+        )]
+        pub(super) struct ApiDoc;
+
+        /// Register the documented routes
+        pub(super) fn configure(cfg: &mut web::ServiceConfig) {
+            $(cfg.service(api::$handler);)*
+        }
+    };
+}
+
+routes!(
+    restaurants,
+    dishes,
+    reviewes,
+    restaurant_rating,
+    dish_rating,
+    dish_price,
+    tag_cloud,
+    opening_hours,
+    restaurant_visits,
+    reviewer_profile,
+    reviewer_visits,
+    create_restaurant,
+    update_restaurant,
+    delete_restaurant,
+    set_opening_hours,
+    add_hours_exception,
+    remove_hours_exception,
+    create_dish,
+    create_review,
+    create_visit,
+    report_price,
+    restaurant_tags,
+    dish_tags,
+    audit_log,
+    revert_audit,
+    create_webhook,
+    list_webhooks,
+    delete_webhook,
+    webhook_deliveries,
+    events,
+);
+
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let scheme = HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .description(Some("Token sent by the /token command of the bot"))
+            .build();
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme("token", SecurityScheme::Http(scheme));
+    }
+}
+
+/// Docs UI at `/api/v1/docs/`, also serving the document
+pub(super) fn docs() -> SwaggerUi {
+    SwaggerUi::new("/api/v1/docs/{_:.*}").url(SPEC_PATH, ApiDoc::openapi())
+}
+
+#[actix_web::test]
+async fn test_documented_routes_are_served() {
+    use actix_web::{http::Method, test};
+    use utoipa::openapi::PathItemType;
+
+    let db_pool = db::test_pool().await;
+    let app = test::init_service(
+        actix_web::App::new()
+            .app_data(web::Data::new(api::ApiState::from_pool(db_pool.clone())))
+            .configure(configure)
+            .service(docs()),
+    )
+    .await;
+
+    let spec = ApiDoc::openapi();
+    let mut operations = 0;
+    for (path, item) in &spec.paths.paths {
+        // every path parameter is an id
+        let uri = path
+            .split('/')
+            .map(|s| if s.starts_with('{') { "1" } else { s })
+            .collect::<Vec<_>>()
+            .join("/");
+        for (kind, operation) in &item.operations {
+            let method = match kind {
+                PathItemType::Get => Method::GET,
+                PathItemType::Post => Method::POST,
+                PathItemType::Put => Method::PUT,
+                PathItemType::Patch => Method::PATCH,
+                PathItemType::Delete => Method::DELETE,
+                _ => panic!("unexpected method of {path}"),
+            };
+            let req = test::TestRequest::default()
+                .method(method.clone())
+                .uri(&uri)
+                .to_request();
+            let status = test::call_service(&app, req).await.status();
+            if operation.security.is_some() {
+                // refused before the handler, which may answer 404 for a missing id
+                assert_eq!(
+                    status,
+                    actix_web::http::StatusCode::UNAUTHORIZED,
+                    "{method} {path} is documented as secured but not routed or not secured"
+                );
+            } else {
+                // the public handlers don't answer 404 for id 1 on the empty database, so a 404
+                // means the route doesn't match
+                assert_ne!(
+                    status,
+                    actix_web::http::StatusCode::NOT_FOUND,
+                    "{method} {path} is documented but not routed"
+                );
+            }
+            operations += 1;
+        }
+    }
+    assert_eq!(operations, 30);
+
+    let req = test::TestRequest::get().uri(SPEC_PATH).to_request();
+    let served: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(served, serde_json::to_value(&spec).unwrap());
+    let req = test::TestRequest::get().uri("/api/v1/docs/").to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+}
+
+#[test]
+fn test_schema_refs_resolve() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let schemas = spec["components"]["schemas"].as_object().unwrap();
+    let text = spec.to_string();
+    for reference in text.split(r#""$ref":""#).skip(1) {
+        let name = reference
+            .split('"')
+            .next()
+            .unwrap()
+            .trim_start_matches("#/components/schemas/");
+        assert!(
+            schemas.contains_key(name),
+            "schema {name} is not registered"
+        );
+    }
+}
+
+/// The frontend reads the committed document, regenerate it with `UPDATE_OPENAPI=1 cargo test`
+#[test]
+fn test_committed_spec_is_up_to_date() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../frontend/openapi.json");
+    let spec = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+    if std::env::var_os("UPDATE_OPENAPI").is_some() {
+        std::fs::write(path, &spec).unwrap();
+    }
+    let committed = std::fs::read_to_string(path).unwrap_or_default();
+    assert!(
+        committed == spec,
+        "{path} is out of date, run `UPDATE_OPENAPI=1 cargo test` to regenerate it"
+    );
+}
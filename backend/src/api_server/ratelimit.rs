@@ -0,0 +1,184 @@
+use super::api::{ApiError, ApiState};
+use crate::db;
+use crate::ratelimit::{Limit, RateLimiter, RequestClass};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::web;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+const READ_LIMIT: Limit = Limit::new(120, Duration::from_secs(60));
+const WRITE_LIMIT: Limit = Limit::new(30, Duration::from_secs(60));
+
+/// Middleware answering 429 to the clients over their budget, the GET routes share the read
+/// budget and the others share the write budget.
+///
+/// Reads are counted by the peer address. Writes are counted by the owner of the API token when
+/// the token is valid, so the users behind the same address don't share their budget, and by the
+/// peer address otherwise, so made-up tokens don't get a fresh budget each.
+#[derive(Clone)]
+pub(super) struct RateLimit(Arc<RateLimiter<String>>);
+
+impl RateLimit {
+    /// Limits from `API_READ_LIMIT` and `API_WRITE_LIMIT`, eg. `120/min`
+    pub(super) fn from_env() -> Self {
+        Self(Arc::new(RateLimiter::from_env(
+            "API",
+            READ_LIMIT,
+            WRITE_LIMIT,
+        )))
+    }
+
+    #[cfg(test)]
+    fn new(read: Limit, write: Limit) -> Self {
+        Self(Arc::new(RateLimiter::new(read, write)))
+    }
+}
+
+async fn client_of(req: &ServiceRequest, class: RequestClass) -> String {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let state = req.app_data::<web::Data<ApiState>>();
+    if let (RequestClass::Write, Some(token), Some(state)) = (class, token, state) {
+        match db::token_owner(&state.db_pool(), token.trim()).await {
+            Ok(Some((id, _))) => return format!("user:{id}"),
+            Ok(None) => (),
+            Err(e) => tracing::warn!("fail to check API token for rate limiting: {e:#}"),
+        }
+    }
+    match req.peer_addr() {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.0.clone(),
+        }))
+    }
+}
+
+pub(super) struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter<String>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let class = match *req.method() {
+            Method::GET | Method::HEAD | Method::OPTIONS => RequestClass::Read,
+            _ => RequestClass::Write,
+        };
+        let (service, limiter) = (self.service.clone(), self.limiter.clone());
+        Box::pin(async move {
+            let client = client_of(&req, class).await;
+            if let Err(throttled) = limiter.check(client.clone(), class) {
+                tracing::info!(
+                    "throttle {client} on {} {}: {throttled:?}",
+                    req.method(),
+                    req.path()
+                );
+                let resp = req.error_response(ApiError::TooManyRequests(throttled));
+                return Ok(resp.map_into_right_body());
+            }
+            Ok(service.call(req).await?.map_into_left_body())
+        })
+    }
+}
+
+#[actix_web::test]
+async fn test_rate_limit() {
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    let limit = RateLimit::new(
+        Limit::new(2, Duration::from_secs(60)),
+        Limit::new(1, Duration::from_secs(60)),
+    );
+    let pool = db::test_pool().await;
+    let mut tokens = Vec::new();
+    for (id, name) in [(1, "alice"), (2, "bob")] {
+        db::register_reviewer(&pool, id, name).await.unwrap();
+        tokens.push(db::create_api_token(&pool, id).await.unwrap());
+    }
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ApiState::from_pool(pool.clone())))
+            .wrap(limit)
+            .route(
+                "/",
+                web::route().to(|| async { HttpResponse::NoContent().finish() }),
+            ),
+    )
+    .await;
+    let peer = |n: u8| std::net::SocketAddr::from(([10, 0, 0, n], 4000));
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get().peer_addr(peer(1)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+    let req = test::TestRequest::get().peer_addr(peer(1)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "30");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["message"],
+        "too many requests, please retry in 30 seconds"
+    );
+    // other peers have their own budget
+    let req = test::TestRequest::get().peer_addr(peer(2)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let post = |peer, token: &str| {
+        test::TestRequest::post()
+            .peer_addr(peer)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+    // writes are counted by the owner of the token behind the same address
+    for token in &tokens {
+        let resp = test::call_service(&app, post(peer(1), token)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+    let resp = test::call_service(&app, post(peer(1), &tokens[0])).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "60");
+
+    // made-up tokens from one address share its budget
+    let resp = test::call_service(&app, post(peer(3), "made-up-1")).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = test::call_service(&app, post(peer(3), "made-up-2")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,143 @@
+use crate::db::{self as db_api, PendingDelivery, Pool};
+use crate::ops::Shutdown;
+use std::time::Duration;
+
+// how often the queue is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// deliveries sent in one round
+const BATCH: i64 = 20;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Post the payload, return the response status or the error of the request
+async fn send(client: &reqwest::Client, delivery: &PendingDelivery) -> Result<u16, String> {
+    let resp = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(db_api::EVENT_HEADER, &delivery.event)
+        .header(db_api::DELIVERY_HEADER, delivery.id)
+        .header(
+            db_api::SIGNATURE_HEADER,
+            db_api::sign_payload(&delivery.secret, &delivery.payload),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(resp.status().as_u16())
+}
+
+/// Send the due deliveries once, return how many are attempted
+pub(super) async fn deliver_due(client: &reqwest::Client, pool: &Pool) -> anyhow::Result<usize> {
+    let due = db_api::due_deliveries(pool, BATCH).await?;
+    for delivery in &due {
+        let outcome = send(client, delivery).await;
+        if let Err(e) = &outcome {
+            tracing::warn!("fail to deliver webhook {}: {e}", delivery.id);
+        }
+        db_api::finish_delivery(pool, delivery.id, outcome).await?;
+    }
+    Ok(due.len())
+}
+
+/// Keep delivering the queued webhook events until the shutdown
+pub(super) async fn run(pool: Pool, mut shutdown: Shutdown) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("fail to build the webhook client");
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => (),
+            _ = shutdown.wait() => break,
+        }
+        if let Err(e) = deliver_due(&client, &pool).await {
+            tracing::error!("fail to deliver webhooks: {e:#}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_deliver_due() {
+    use crate::db::Actor;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    // a stand-in receiver that records the requests, failing the first one
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Arc::new(Mutex::new(Vec::<(String, String)>::new()));
+    let recorder = received.clone();
+    std::thread::spawn(move || {
+        for (i, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut signature, mut length) = (String::new(), 0);
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(": ") {
+                    match name.to_lowercase().as_str() {
+                        "x-webhook-signature" => signature = value.to_string(),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => (),
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            recorder
+                .lock()
+                .unwrap()
+                .push((signature, String::from_utf8(body).unwrap()));
+            let status = if i == 0 {
+                "503 Service Unavailable"
+            } else {
+                "200 OK"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        }
+    });
+
+    let pool = db_api::test_pool().await;
+    let url = format!("http://{addr}/hook");
+    let (webhook, secret) = db_api::add_webhook(&pool, Actor::System, &url, &["*".into()])
+        .await
+        .unwrap();
+    db_api::add_restaurant(&pool, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    assert_eq!(deliver_due(&client, &pool).await.unwrap(), 1);
+    let log = db_api::delivery_log(&pool, webhook, 10).await.unwrap();
+    assert_eq!(log[0].status, "pending");
+    assert_eq!(log[0].response_status, Some(503));
+
+    // retry now instead of waiting for the backoff
+    sqlx::query("UPDATE webhook_delivery SET next_attempt_at = '2000-01-01 00:00:00'")
+        .execute(&*pool)
+        .await
+        .unwrap();
+    assert_eq!(deliver_due(&client, &pool).await.unwrap(), 1);
+    let log = db_api::delivery_log(&pool, webhook, 10).await.unwrap();
+    assert_eq!(log[0].status, "delivered");
+    assert_eq!(log[0].attempts, 2);
+    assert_eq!(deliver_due(&client, &pool).await.unwrap(), 0);
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    let (signature, body) = &received[1];
+    assert_eq!(signature, &db_api::sign_payload(&secret, body));
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["event"], "restaurant.create");
+    assert_eq!(payload["after"]["name"], "KFC");
+}
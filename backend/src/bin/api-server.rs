@@ -0,0 +1,35 @@
+use meal_review::{api_server, db, ops};
+
+/// Database of the API when `DATABASE_URL` is not set
+const DATABASE_URL: &str = "sqlite://review.db";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(true)
+        .with_file(false)
+        .pretty()
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("fail to setup logging");
+    let url = std::env::var("DATABASE_URL").unwrap_or_else(|_| DATABASE_URL.to_string());
+    let pool = db::connect(&url).await.expect("fail to open database");
+    db::migrate(&pool)
+        .await
+        .expect("fail to migrate the database");
+
+    let mut tasks = ops::Tasks::default();
+    let server = api_server::start(pool.clone(), &mut tasks, |_| ()).await?;
+    let handle = server.handle();
+    tokio::spawn(async move {
+        ops::shutdown_signal().await;
+        handle.stop(true).await;
+    });
+    server.await?;
+    // after the requests, whose changes may still be delivered
+    tasks.shutdown().await;
+    pool.close().await;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! The API server and the bot in one process, sharing the database pool and the event bus
+
+use meal_review::{api_server, ops, tgbot};
+use teloxide::Bot;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::INFO)
+        .with_ansi(true)
+        .with_file(false)
+        .pretty()
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("fail to setup logging");
+
+    let pool = meal_review::db::connect(
+        &std::env::var("DATABASE_URL").expect("DATABASE_URL env not found"),
+    )
+    .await
+    .expect("fail to connect to the database");
+    meal_review::db::migrate(&pool)
+        .await
+        .expect("fail to migrate the database");
+
+    // the webhook of the bot is served with the API
+    let webhook = tgbot::webhook::Webhook::from_env().map(|webhook| {
+        let (route, listener) = webhook.channel();
+        (route, (webhook, listener))
+    });
+    let (route, webhook) = webhook.unzip();
+    let mut tasks = ops::Tasks::default();
+    let server = api_server::start(pool.clone(), &mut tasks, move |cfg| {
+        if let Some(route) = &route {
+            route.configure(cfg)
+        }
+    })
+    .await?;
+    let bot = Bot::new(std::env::var("TGBOT_TOKEN").expect("TGBOT_TOKEN env not found"));
+    let mut dispatcher = tgbot::start(bot.clone(), pool.clone(), &mut tasks);
+    let (handle, shutdown) = (server.handle(), dispatcher.shutdown_token());
+    tokio::spawn(async move {
+        ops::shutdown_signal().await;
+        // finish the requests and the updates already taken
+        tokio::join!(handle.stop(true), tgbot::stop(&shutdown));
+    });
+
+    let dispatched = tgbot::dispatch(&mut dispatcher, &bot, webhook);
+    let (served, ()) = tokio::join!(server, dispatched);
+    // then the notifications and deliveries of what they changed
+    tasks.shutdown().await;
+    pool.close().await;
+    served?;
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use meal_review::ops;
+use meal_review::tgbot::{self, metrics, webhook};
+use teloxide::Bot;
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::INFO)
+        .with_ansi(true)
+        .with_file(false)
+        .pretty()
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("fail to setup logging");
+
+    let dbpool = meal_review::db::connect(
+        &std::env::var("DATABASE_URL").expect("DATABASE_URL env not found"),
+    )
+    .await
+    .expect("fail to connect to the database");
+    meal_review::db::migrate(&dbpool)
+        .await
+        .expect("fail to migrate the database");
+    let webhook = webhook::Webhook::from_env().map(|webhook| {
+        let (route, listener) = webhook.channel();
+        (route, (webhook, listener))
+    });
+    let (route, webhook) = webhook.unzip();
+    let addr = std::env::var("TGBOT_METRICS_ADDR").unwrap_or_else(|_| metrics::ADDR.to_string());
+    let ops_server =
+        metrics::serve(&addr, dbpool.clone(), route).expect("fail to serve the metrics");
+    tokio::spawn(ops_server);
+
+    let bot = Bot::new(std::env::var("TGBOT_TOKEN").expect("TGBOT_TOKEN env not found"));
+    let mut tasks = ops::Tasks::default();
+    let mut dispatcher = tgbot::start(bot.clone(), dbpool.clone(), &mut tasks);
+    let shutdown = dispatcher.shutdown_token();
+    tokio::spawn(async move {
+        ops::shutdown_signal().await;
+        tgbot::stop(&shutdown).await;
+    });
+    tgbot::dispatch(&mut dispatcher, &bot, webhook).await;
+    // after the updates, whose notifications are sent before exiting
+    tasks.shutdown().await;
+    dbpool.close().await;
+}
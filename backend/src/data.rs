@@ -11,7 +11,7 @@ pub struct Dish {
     pub name: String,
     /// path to image
     pub image: String,
-    pub review: Vec<Review>
+    pub review: Vec<Review>,
 }
 
 #[derive(Debug)]
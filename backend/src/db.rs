@@ -1,45 +1,281 @@
 use anyhow::Context;
 use derive_builder::Builder;
-use sqlx::{sqlite::SqlitePool, Row};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use sqlx::Row;
+use std::collections::BTreeMap;
 
-#[derive(Clone)]
+mod audit;
+mod auth;
+mod backend;
+mod digest;
+mod event;
+mod follow;
+mod hours;
+mod merge;
+mod price;
+mod profile;
+mod rating;
+mod tag;
+mod version;
+mod visit;
+mod webhook;
+pub use audit::*;
+pub use auth::*;
+pub use backend::*;
+pub use digest::*;
+pub use event::*;
+pub use follow::*;
+pub use hours::*;
+pub use merge::*;
+pub use price::*;
+pub use profile::*;
+pub use rating::*;
+pub use tag::*;
+pub use version::*;
+pub use visit::*;
+pub use webhook::*;
+
+/// Kinds of the rows that can be referred by id
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Restaurant,
+    Dish,
+    Review,
+    Reviewer,
+    HoursException,
+    Visit,
+    /// API tokens of a user, the id is the owner
+    Token,
+    /// Chats in the allowlist, the id is the chat
+    Chat,
+    /// Outbound webhooks, without their secrets in the log
+    Webhook,
+    /// Restaurants and reviewers followed by a user, the id is the user
+    Subscription,
+    /// Weekly digest schedules, the id is the chat
+    Digest,
+}
+
+impl EntityKind {
+    /// Table that stores this kind of rows
+    pub fn table(self) -> &'static str {
+        match self {
+            Self::Restaurant => "restaurant",
+            Self::Dish => "dish",
+            Self::Review => "review",
+            Self::Reviewer => "reviewer",
+            Self::HoursException => "hours_exception",
+            Self::Visit => "visit",
+            Self::Token => "api_token",
+            Self::Chat => "chat_allowlist",
+            Self::Webhook => "webhook",
+            Self::Subscription => "subscription",
+            Self::Digest => "digest_setting",
+        }
+    }
+}
+
+impl std::fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table())
+    }
+}
+
+impl std::str::FromStr for EntityKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restaurant" | "rest" => Ok(Self::Restaurant),
+            "dish" => Ok(Self::Dish),
+            "review" => Ok(Self::Review),
+            "reviewer" | "user" => Ok(Self::Reviewer),
+            "hours_exception" | "exception" => Ok(Self::HoursException),
+            "visit" => Ok(Self::Visit),
+            "api_token" | "token" => Ok(Self::Token),
+            "chat_allowlist" | "chat" => Ok(Self::Chat),
+            "webhook" => Ok(Self::Webhook),
+            "subscription" => Ok(Self::Subscription),
+            "digest_setting" | "digest" => Ok(Self::Digest),
+            _ => anyhow::bail!(
+                "unknown kind {s}, expect restaurant, dish, review, reviewer, hours_exception, \
+                visit, api_token, chat_allowlist, webhook, subscription or digest_setting"
+            ),
+        }
+    }
+}
+
+/// A row matched by name when resolving a name into id
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Candidate {
+    pub id: i64,
+    /// Human readable text to tell the candidates apart
+    pub description: String,
+}
+
+/// Error of resolving a name into a single row. Downcast the [`anyhow::Error`] to tell the
+/// ambiguous name apart from other errors.
+#[derive(Debug)]
+pub enum LookupError {
+    NotFound {
+        kind: &'static str,
+        name: String,
+    },
+    Ambiguous {
+        kind: &'static str,
+        name: String,
+        candidates: Vec<Candidate>,
+    },
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound { kind, name } => write!(f, "no {kind} named {name}"),
+            Self::Ambiguous {
+                kind,
+                name,
+                candidates,
+            } => {
+                write!(f, "{} {kind}s are named {name}:", candidates.len())?;
+                for c in candidates {
+                    write!(f, "\n{}. {}", c.id, c.description)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+impl LookupError {
+    fn check(kind: &'static str, name: &str, mut candidates: Vec<Candidate>) -> Result<i64, Self> {
+        match candidates.len() {
+            0 => Err(Self::NotFound {
+                kind,
+                name: name.to_string(),
+            }),
+            1 => Ok(candidates.remove(0).id),
+            _ => Err(Self::Ambiguous {
+                kind,
+                name: name.to_string(),
+                candidates,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum ReviewerProp {
     Name(String),
     Id(i64),
 }
 
 impl ReviewerProp {
-    async fn get_db_id(&self, db_conn: &SqlitePool) -> anyhow::Result<i64> {
+    /// Resolve into the reviewer id, fail with [`LookupError`] when the name doesn't point to
+    /// exactly one reviewer.
+    pub async fn resolve(&self, db_conn: &Pool) -> anyhow::Result<i64> {
         let id: i64 = match self {
             Self::Id(id) => *id,
             Self::Name(name) => {
-                let row = sqlx::query("SELECT id FROM reviewer WHERE name = ?")
-                    .bind(name)
-                    .fetch_one(db_conn)
-                    .await?;
-                row.get("id")
+                let candidates =
+                    sqlx::query("SELECT id, name FROM reviewer WHERE name = $1 ORDER BY id")
+                        .bind(name.trim())
+                        .fetch_all(db_conn)
+                        .await?
+                        .into_iter()
+                        .map(|row| Candidate {
+                            id: row.get("id"),
+                            description: row.get("name"),
+                        })
+                        .collect();
+                LookupError::check("reviewer", name, candidates)?
             }
         };
         Ok(id)
     }
 }
 
-#[derive(Clone)]
-pub enum DishProp {
+#[derive(Clone, Debug)]
+pub enum RestaurantProp {
     Id(i64),
     Name(String),
 }
 
-impl DishProp {
-    async fn get_dish_id(&self, db_conn: &SqlitePool) -> anyhow::Result<i64> {
+impl RestaurantProp {
+    /// Resolve into the restaurant id, fail with [`LookupError`] when the name doesn't point to
+    /// exactly one restaurant.
+    pub async fn resolve(&self, db_conn: &Pool) -> anyhow::Result<i64> {
         let id: i64 = match self {
             Self::Id(id) => *id,
             Self::Name(name) => {
-                let row = sqlx::query("SELECT id FROM dish WHERE name = ?")
-                    .bind(name)
-                    .fetch_one(db_conn)
-                    .await?;
-                row.get("id")
+                let candidates = sqlx::query(
+                    "SELECT id, name, address FROM restaurant WHERE name = $1 ORDER BY id",
+                )
+                .bind(name.trim())
+                .fetch_all(db_conn)
+                .await?
+                .into_iter()
+                .map(|row| Candidate {
+                    id: row.get("id"),
+                    description: format!(
+                        "{} {}",
+                        row.get::<String, _>("name"),
+                        row.get::<String, _>("address")
+                    ),
+                })
+                .collect();
+                LookupError::check("restaurant", name, candidates)?
+            }
+        };
+        Ok(id)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DishProp {
+    Id(i64),
+    /// Dish name, optionally scoped in the given restaurant
+    Name {
+        name: String,
+        restaurant: Option<i64>,
+    },
+}
+
+impl DishProp {
+    /// Resolve into the dish id, fail with [`LookupError`] when the name doesn't point to exactly
+    /// one dish in the restaurant scope.
+    pub async fn resolve(&self, db_conn: &Pool) -> anyhow::Result<i64> {
+        let id: i64 = match self {
+            Self::Id(id) => *id,
+            Self::Name { name, restaurant } => {
+                let candidates = sqlx::query(
+                    r#"
+SELECT dish.id, restaurant.name AS restaurant, restaurant.address
+FROM dish LEFT JOIN restaurant ON dish.restaurant = restaurant.id
+WHERE dish.name = $1 AND ($2 IS NULL OR dish.restaurant = $2)
+ORDER BY dish.id"#,
+                )
+                .bind(name.trim())
+                .bind(restaurant)
+                .fetch_all(db_conn)
+                .await?
+                .into_iter()
+                .map(|row| Candidate {
+                    id: row.get("id"),
+                    description: format!(
+                        "{name} @ {} {}",
+                        row.get::<Option<String>, _>("restaurant")
+                            .unwrap_or_default(),
+                        row.get::<Option<String>, _>("address").unwrap_or_default()
+                    ),
+                })
+                .collect();
+                LookupError::check("dish", name, candidates)?
             }
         };
         Ok(id)
@@ -52,54 +288,208 @@ pub struct NewReviewProps {
     dish: DishProp,
     details: String,
     score: u8,
+    /// Optional sub-scores, each in range 0 - 5
+    #[builder(default)]
+    ratings: Vec<(RatingDimension, u8)>,
+    /// Price paid for the dish
+    #[builder(setter(strip_option), default)]
+    price: Option<f64>,
 }
 
-pub async fn add_new_user(db_conn: &SqlitePool, user: (i64, &str)) -> anyhow::Result<()> {
-    sqlx::query("INSERT INTO reviewer (id, name) VALUES (?, ?)")
+pub async fn add_new_user(db_conn: &Pool, user: (i64, &str)) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    sqlx::query("INSERT INTO reviewer (id, name) VALUES ($1, $2)")
         .bind(user.0)
         .bind(user.1)
-        .execute(db_conn)
+        .execute(&mut tx)
         .await
         .with_context(|| format!("fail to add new user {}", user.1))?;
+    record_create(&mut tx, Actor::User(user.0), EntityKind::Reviewer, user.0).await?;
+    tx.commit().await?;
     Ok(())
 }
 
-pub async fn add_restaurant(db_conn: &SqlitePool, name: &str, addr: &str) -> anyhow::Result<i64> {
-    let id = sqlx::query("INSERT INTO restaurant (name, address) VALUES (?, ?)")
-        .bind(name)
-        .bind(addr)
-        .execute(db_conn)
-        .await
-        .with_context(|| format!("fail to add new restaurant {name}"))?
-        .last_insert_rowid();
+pub async fn add_restaurant(
+    db_conn: &Pool,
+    actor: Actor,
+    name: &str,
+    addr: &str,
+) -> anyhow::Result<i64> {
+    let props = NewRestaurantPropsBuilder::default()
+        .name(name)
+        .address(addr)
+        .build()
+        .unwrap();
+    add_restaurant_detail(db_conn, actor, props).await
+}
+
+#[derive(Builder, Debug, Clone)]
+pub struct NewRestaurantProps {
+    #[builder(setter(into))]
+    name: String,
+    #[builder(setter(into))]
+    address: String,
+    #[builder(setter(into, strip_option), default)]
+    phone: Option<String>,
+    /// Weekly hours in the format of [`WeeklyHours`]
+    #[builder(setter(into, strip_option), default)]
+    opening_hours: Option<String>,
+    /// Comma separated tags
+    #[builder(setter(into, strip_option), default)]
+    tags: Option<String>,
+    /// Latitude and longitude
+    #[builder(setter(strip_option), default)]
+    location: Option<(f64, f64)>,
+}
+
+pub async fn add_restaurant_detail(
+    db_conn: &Pool,
+    actor: Actor,
+    props: NewRestaurantProps,
+) -> anyhow::Result<i64> {
+    let NewRestaurantProps {
+        name,
+        address,
+        phone,
+        opening_hours,
+        tags,
+        location,
+    } = props;
+    // keep the hours in the format that can be parsed back
+    let opening_hours = opening_hours.as_deref().map(normalize_hours).transpose()?;
+
+    let mut tx = db_conn.begin().await?;
+    let id = sqlx::query(
+        r#"
+INSERT INTO restaurant
+    (name, address, phone, opening_hours, latitude, longitude)
+VALUES
+    ($1, $2, $3, $4, $5, $6)
+RETURNING id"#,
+    )
+    .bind(&name)
+    .bind(address)
+    .bind(phone)
+    .bind(opening_hours)
+    .bind(location.map(|l| l.0))
+    .bind(location.map(|l| l.1))
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("fail to add new restaurant {name}"))?
+    .get("id");
+    if let Some(tags) = tags {
+        replace_tags(&mut tx, EntityKind::Restaurant, id, &split_tags(&tags)).await?;
+    }
+    record_create(&mut tx, actor, EntityKind::Restaurant, id).await?;
+    tx.commit().await?;
+
+    emit(Event::RestaurantAdded {
+        id,
+        actor: actor.db_id(),
+    });
     Ok(id)
 }
 
 pub async fn add_dish(
-    db_conn: &SqlitePool,
+    db_conn: &Pool,
+    actor: Actor,
     restaurant: i64,
     name: &str,
     image: Option<String>,
 ) -> anyhow::Result<i64> {
+    add_dish_with_price(db_conn, actor, restaurant, name, image, None).await
+}
+
+/// Add the dish together with its first price and currency, if any
+pub async fn add_dish_with_price(
+    db_conn: &Pool,
+    actor: Actor,
+    restaurant: i64,
+    name: &str,
+    image: Option<String>,
+    price: Option<(f64, Option<&str>)>,
+) -> anyhow::Result<i64> {
+    if let Some((amount, _)) = price {
+        check_price(amount)?;
+    }
+    let with_image = image.is_some();
+    let mut tx = db_conn.begin().await?;
     let row = if let Some(image) = image {
-        sqlx::query("INSERT INTO dish (restaurant, name, image) VALUES (?, ?, ?)")
+        sqlx::query("INSERT INTO dish (restaurant, name, image) VALUES ($1, $2, $3) RETURNING id")
             .bind(restaurant)
             .bind(name)
             .bind(image)
-            .execute(db_conn)
+            .fetch_one(&mut tx)
             .await?
     } else {
-        sqlx::query("INSERT INTO dish (restaurant, name) VALUES (?, ?)")
+        sqlx::query("INSERT INTO dish (restaurant, name) VALUES ($1, $2) RETURNING id")
             .bind(restaurant)
             .bind(name)
-            .execute(db_conn)
+            .fetch_one(&mut tx)
             .await?
     };
+    let id = row.get("id");
+    record_create(&mut tx, actor, EntityKind::Dish, id).await?;
+    if let Some((amount, currency)) = price {
+        record_price(&mut tx, actor, id, amount, currency).await?;
+    }
+    tx.commit().await?;
+
+    emit(Event::DishAdded {
+        id,
+        restaurant,
+        actor: actor.db_id(),
+    });
+    if with_image {
+        emit(Event::PhotoAdded {
+            dish: id,
+            restaurant,
+            actor: actor.db_id(),
+        });
+    }
+    Ok(id)
+}
+
+/// Set the photo of the dish, `image` is the telegram file id
+pub async fn set_dish_image(
+    db_conn: &Pool,
+    actor: Actor,
+    id: i64,
+    image: &str,
+) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, EntityKind::Dish, id)
+        .await?
+        .with_context(|| format!("dish {id} not found"))?;
+    let restaurant: i64 = sqlx::query("UPDATE dish SET image=$1 WHERE id=$2 RETURNING restaurant")
+        .bind(image)
+        .bind(id)
+        .fetch_one(&mut tx)
+        .await
+        .with_context(|| format!("fail to set the photo of dish {id}"))?
+        .get("restaurant");
+    let after = snapshot(&mut tx, EntityKind::Dish, id).await?;
+    record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        EntityKind::Dish,
+        id,
+        Some(before),
+        after,
+    )
+    .await?;
+    tx.commit().await?;
 
-    Ok(row.last_insert_rowid())
+    emit(Event::PhotoAdded {
+        dish: id,
+        restaurant,
+        actor: actor.db_id(),
+    });
+    Ok(())
 }
 
-#[derive(sqlx::FromRow, serde::Serialize)]
+#[derive(sqlx::FromRow, serde::Serialize, Clone, utoipa::ToSchema)]
 pub struct Dish {
     pub id: i64,
     #[sqlx(rename = "restaurant")]
@@ -107,17 +497,49 @@ pub struct Dish {
     pub name: String,
     #[sqlx(default)]
     pub image: Option<String>,
+    /// Latest reported price
+    #[sqlx(default)]
+    pub price: Option<f64>,
+    #[sqlx(default)]
+    pub currency: Option<String>,
+    /// Comma separated tags
+    #[sqlx(default)]
+    pub tags: Option<String>,
+}
+
+// `tags` column is collected from the tag tables
+macro_rules! select_dish {
+    ($filter:literal) => {
+        concat!(
+            "SELECT dish.*, (SELECT group_concat(tag.name, ',') FROM dish_tag ",
+            "JOIN tag ON dish_tag.tag = tag.id WHERE dish_tag.dish = dish.id) AS tags ",
+            "FROM dish ",
+            $filter
+        )
+    };
+}
+
+macro_rules! select_restaurant {
+    ($filter:literal) => {
+        concat!(
+            "SELECT restaurant.*, (SELECT group_concat(tag.name, ',') FROM restaurant_tag ",
+            "JOIN tag ON restaurant_tag.tag = tag.id ",
+            "WHERE restaurant_tag.restaurant = restaurant.id) AS tags ",
+            "FROM restaurant ",
+            $filter
+        )
+    };
 }
 
 pub async fn get_dish(
-    db_conn: &SqlitePool,
+    db_conn: &Pool,
     restaurant: i64,
     dish_id: Option<i64>,
 ) -> anyhow::Result<Vec<Dish>> {
     let query = if let Some(dish_id) = dish_id {
-        sqlx::query_as("SELECT * FROM dish WHERE id=?").bind(dish_id)
+        sqlx::query_as(select_dish!("WHERE id=$1")).bind(dish_id)
     } else {
-        sqlx::query_as("SELECT * FROM dish WHERE restaurant=?").bind(restaurant)
+        sqlx::query_as(select_dish!("WHERE restaurant=$1")).bind(restaurant)
     };
 
     let dishes = query.fetch_all(db_conn).await?;
@@ -125,34 +547,74 @@ pub async fn get_dish(
     Ok(dishes)
 }
 
-pub async fn add_new_review(db_conn: &SqlitePool, prop: NewReviewProps) -> anyhow::Result<()> {
+pub async fn add_new_review(db_conn: &Pool, prop: NewReviewProps) -> anyhow::Result<()> {
     let NewReviewProps {
         reviewer,
         dish,
         details,
         score,
+        ratings,
+        price,
     } = prop;
 
-    let reviewer_id = reviewer.get_db_id(db_conn).await?;
-    let dish_id = dish.get_dish_id(db_conn).await?;
+    anyhow::ensure!(score <= 5, "score should be in range 0 - 5");
+    if let Some((dimension, _)) = ratings.iter().find(|(_, s)| *s > 5) {
+        anyhow::bail!("{dimension} rating should be in range 0 - 5");
+    }
+
+    let reviewer_id = reviewer.resolve(db_conn).await?;
+    let dish_id = dish.resolve(db_conn).await?;
 
-    sqlx::query(
+    let mut tx = db_conn.begin().await?;
+    let id = sqlx::query(
         r#"
 INSERT INTO review
-    (reviewer, dish, details, score)
+    (reviewer, dish, details, score, price)
 VALUES
-    (?, ?, ?, ?)"#,
+    ($1, $2, $3, $4, $5)
+RETURNING id"#,
     )
     .bind(reviewer_id)
     .bind(dish_id)
     .bind(details)
-    .bind(score)
-    .execute(db_conn)
-    .await?;
+    .bind(i64::from(score))
+    .bind(price)
+    .fetch_one(&mut tx)
+    .await?
+    .get("id");
+    insert_ratings(&mut tx, id, &ratings).await?;
+    if let Some(price) = price {
+        record_price(&mut tx, Actor::User(reviewer_id), dish_id, price, None).await?;
+    }
+    record_create(&mut tx, Actor::User(reviewer_id), EntityKind::Review, id).await?;
+    let restaurant = sqlx::query("SELECT restaurant FROM dish WHERE id=$1")
+        .bind(dish_id)
+        .fetch_one(&mut tx)
+        .await?
+        .get("restaurant");
+    tx.commit().await?;
 
+    emit(Event::ReviewAdded {
+        id,
+        dish: dish_id,
+        restaurant,
+        reviewer: reviewer_id,
+    });
     Ok(())
 }
 
+// `reviewer_name` column is joined from the reviewer table
+macro_rules! select_review {
+    ($filter:literal) => {
+        concat!(
+            "SELECT review.id, review.reviewer, reviewer.name AS reviewer_name, ",
+            "review.dish, review.details, review.score, review.price ",
+            "FROM review LEFT JOIN reviewer ON review.reviewer = reviewer.id ",
+            $filter
+        )
+    };
+}
+
 #[derive(Builder)]
 pub struct GetReviewProps {
     #[builder(setter(into, strip_option), default)]
@@ -161,21 +623,25 @@ pub struct GetReviewProps {
     dish_id: Option<i64>,
 }
 
-#[derive(sqlx::FromRow, serde::Serialize)]
+#[derive(serde::Serialize, Clone, utoipa::ToSchema)]
 pub struct Review {
+    pub id: i64,
     pub reviewer: i64,
+    /// Display name of the reviewer
+    pub reviewer_name: Option<String>,
     pub score: u8,
     pub details: String,
+    pub price: Option<f64>,
+    /// Sub-scores, empty for the reviews with only the overall score
+    pub ratings: BTreeMap<RatingDimension, u8>,
 }
 
-pub async fn get_review(db_conn: &SqlitePool, props: GetReviewProps) -> anyhow::Result<Review> {
+pub async fn get_review(db_conn: &Pool, props: GetReviewProps) -> anyhow::Result<Review> {
     let GetReviewProps { id, dish_id } = props;
     let query = if let Some(id) = id {
-        sqlx::query_as::<_, Review>("SELECT reviewer, details, score FROM review WHERE id=?")
-            .bind(id)
+        sqlx::query(select_review!("WHERE review.id=$1")).bind(id)
     } else if let Some(id) = dish_id {
-        sqlx::query_as::<_, Review>("SELECT reviewer, details, score FROM review WHERE dish=?")
-            .bind(id)
+        sqlx::query(select_review!("WHERE review.dish=$1")).bind(id)
     } else {
         // XXX
         panic!()
@@ -185,15 +651,137 @@ pub async fn get_review(db_conn: &SqlitePool, props: GetReviewProps) -> anyhow::
         .fetch_one(db_conn)
         .await
         .with_context(|| "fail to get review")?;
+    let id = row.get("id");
 
-    Ok(row)
+    Ok(Review {
+        id,
+        reviewer: row.get("reviewer"),
+        reviewer_name: row.get("reviewer_name"),
+        score: row.get::<i64, _>("score") as u8,
+        details: row.get("details"),
+        price: row.get("price"),
+        ratings: get_ratings(db_conn, id).await?,
+    })
 }
 
-#[derive(sqlx::FromRow, serde::Serialize)]
+/// Dishes of the given restaurants in one query
+pub async fn get_dishes_of(db_conn: &Pool, restaurants: &[i64]) -> anyhow::Result<Vec<Dish>> {
+    if restaurants.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        select_dish!("WHERE restaurant IN ({}) ORDER BY id"),
+        placeholders(restaurants.len())
+    );
+    let mut query = sqlx::query_as(&sql);
+    for id in restaurants {
+        query = query.bind(id);
+    }
+    Ok(query.fetch_all(db_conn).await?)
+}
+
+/// Dishes with the given ids in one query, the missing ones are skipped
+pub async fn get_dishes(db_conn: &Pool, ids: &[i64]) -> anyhow::Result<Vec<Dish>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(select_dish!("WHERE id IN ({})"), placeholders(ids.len()));
+    let mut query = sqlx::query_as(&sql);
+    for id in ids {
+        query = query.bind(id);
+    }
+    Ok(query.fetch_all(db_conn).await?)
+}
+
+/// Reviews of the given dishes in one query, paired with the dish id
+pub async fn get_reviews_of(db_conn: &Pool, dishes: &[i64]) -> anyhow::Result<Vec<(i64, Review)>> {
+    if dishes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        select_review!("WHERE review.dish IN ({}) ORDER BY review.id"),
+        placeholders(dishes.len())
+    );
+    let mut query = sqlx::query(&sql);
+    for id in dishes {
+        query = query.bind(id);
+    }
+    let rows = query.fetch_all(db_conn).await?;
+    let ids = rows.iter().map(|row| row.get("id")).collect::<Vec<i64>>();
+    let mut ratings = get_ratings_of(db_conn, &ids).await?;
+
+    let reviews = rows
+        .into_iter()
+        .map(|row| {
+            let id = row.get("id");
+            let review = Review {
+                id,
+                reviewer: row.get("reviewer"),
+                reviewer_name: row.get("reviewer_name"),
+                score: row.get::<i64, _>("score") as u8,
+                details: row.get("details"),
+                price: row.get("price"),
+                ratings: ratings.remove(&id).unwrap_or_default(),
+            };
+            (row.get("dish"), review)
+        })
+        .collect();
+    Ok(reviews)
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct Reviewer {
+    pub id: i64,
+    pub name: Option<String>,
+    pub role: Role,
+}
+
+/// Reviewers with the given ids in one query, the missing ones are skipped
+pub async fn get_reviewers(db_conn: &Pool, ids: &[i64]) -> anyhow::Result<Vec<Reviewer>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT id, name, role FROM reviewer WHERE id IN ({})",
+        placeholders(ids.len())
+    );
+    let mut query = sqlx::query(&sql);
+    for id in ids {
+        query = query.bind(id);
+    }
+    query
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(Reviewer {
+                id: row.get("id"),
+                name: row.get("name"),
+                role: row.get::<String, _>("role").parse()?,
+            })
+        })
+        .collect()
+}
+
+// `$1, $2, $3` for binding a batch of ids, `IN ()` is invalid in PostgreSQL so skip empty batches
+fn placeholders(n: usize) -> String {
+    (1..=n)
+        .map(|i| format!("${i}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, Clone, utoipa::ToSchema)]
 pub struct Restaurant {
     pub name: String,
     pub id: i64,
     pub address: String,
+    pub phone: Option<String>,
+    pub opening_hours: Option<String>,
+    /// Comma separated tags
+    pub tags: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
 }
 
 pub enum RestaurantSearchProps {
@@ -202,30 +790,45 @@ pub enum RestaurantSearchProps {
     All,
 }
 
-// for future migration, maybe someday I don't want sqlite
-type DB = sqlx::Sqlite;
-type DBArg<'q> = sqlx::sqlite::SqliteArguments<'q>;
+type DB = sqlx::Any;
+type DBArg<'q> = sqlx::any::AnyArguments<'q>;
 
-type SqliteQueryAs<'q, O> = sqlx::query::QueryAs<'q, DB, O, DBArg<'q>>;
+type QueryAs<'q, O> = sqlx::query::QueryAs<'q, DB, O, DBArg<'q>>;
 
 impl RestaurantSearchProps {
-    pub fn into_query_as<'q>(self) -> SqliteQueryAs<'q, Restaurant> {
+    pub fn into_query_as<'q>(self) -> QueryAs<'q, Restaurant> {
         match self {
             Self::Range(s, e) => {
-                sqlx::query_as::<_, Restaurant>("SELECT * FROM restaurant WHERE id BETWEEN ? AND ?")
+                sqlx::query_as::<_, Restaurant>(select_restaurant!("WHERE id BETWEEN $1 AND $2"))
                     .bind(s)
                     .bind(e)
             }
             Self::Id(id) => {
-                sqlx::query_as::<_, Restaurant>("SELECT * FROM restaurant WHERE id=?").bind(id)
+                sqlx::query_as::<_, Restaurant>(select_restaurant!("WHERE id=$1")).bind(id)
             }
-            Self::All => sqlx::query_as::<_, Restaurant>("SELECT * FROM restaurant"),
+            Self::All => sqlx::query_as::<_, Restaurant>(select_restaurant!("")),
         }
     }
 }
 
+/// Restaurants with the given ids in one query, the missing ones are skipped
+pub async fn get_restaurants(db_conn: &Pool, ids: &[i64]) -> anyhow::Result<Vec<Restaurant>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        select_restaurant!("WHERE id IN ({})"),
+        placeholders(ids.len())
+    );
+    let mut query = sqlx::query_as(&sql);
+    for id in ids {
+        query = query.bind(id);
+    }
+    Ok(query.fetch_all(db_conn).await?)
+}
+
 pub async fn get_restaurant(
-    db_conn: &SqlitePool,
+    db_conn: &Pool,
     props: RestaurantSearchProps,
 ) -> anyhow::Result<Vec<Restaurant>> {
     let sql = props.into_query_as();
@@ -238,49 +841,121 @@ pub async fn get_restaurant(
     Ok(rsts)
 }
 
+/// Fuzzy search restaurants by name and address
+pub async fn search_restaurant(db_conn: &Pool, pattern: &str) -> anyhow::Result<Vec<Restaurant>> {
+    let matcher = SkimMatcherV2::default();
+    let mut scored = get_restaurant(db_conn, RestaurantSearchProps::All)
+        .await?
+        .into_iter()
+        .filter_map(|rst| {
+            let text = format!("{} {}", rst.name, rst.address);
+            matcher
+                .fuzzy_match(&text, pattern)
+                .map(|score| (score, rst))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    Ok(scored.into_iter().map(|(_, rst)| rst).collect())
+}
+
+/// Find the restaurants whose name looks like the given one, for warning about duplication before
+/// adding a new restaurant.
+pub async fn similar_restaurant(db_conn: &Pool, name: &str) -> anyhow::Result<Vec<Restaurant>> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let name = name.trim();
+    let rsts = get_restaurant(db_conn, RestaurantSearchProps::All)
+        .await?
+        .into_iter()
+        .filter(|rst| {
+            let exist = rst.name.trim();
+            // match in both direction, so "KFC" and "KFC WuHan" are similar to each other
+            exist.eq_ignore_ascii_case(name)
+                || matcher.fuzzy_match(exist, name).is_some()
+                || matcher.fuzzy_match(name, exist).is_some()
+        })
+        .collect();
+
+    Ok(rsts)
+}
+
 pub enum UpdateRestaurantProps {
     UpdateName(String),
     UpdateAddr(String),
+    /// Change the name and the address together, None keeps the old one
+    UpdateDetails {
+        name: Option<String>,
+        address: Option<String>,
+    },
     Delete,
 }
 
 impl UpdateRestaurantProps {
     fn into_query<'q>(self, id: i64) -> sqlx::query::Query<'q, DB, DBArg<'q>> {
         match self {
-            Self::UpdateName(name) => sqlx::query("UPDATE restaurant SET name=? WHERE id=?")
+            Self::UpdateName(name) => sqlx::query("UPDATE restaurant SET name=$1 WHERE id=$2")
                 .bind(name)
                 .bind(id),
-            Self::UpdateAddr(addr) => sqlx::query("UPDATE restaurant SET address=? WHERE id=?")
+            Self::UpdateAddr(addr) => sqlx::query("UPDATE restaurant SET address=$1 WHERE id=$2")
                 .bind(addr)
                 .bind(id),
-            Self::Delete => sqlx::query("DELETE FROM restaurant WHERE id=?").bind(id),
+            Self::UpdateDetails { name, address } => sqlx::query(
+                "UPDATE restaurant SET name=COALESCE($1, name), address=COALESCE($2, address) \
+                 WHERE id=$3",
+            )
+            .bind(name)
+            .bind(address)
+            .bind(id),
+            Self::Delete => sqlx::query("DELETE FROM restaurant WHERE id=$1").bind(id),
         }
     }
 }
 
 pub async fn update_restaurant(
-    db_conn: &SqlitePool,
+    db_conn: &Pool,
+    actor: Actor,
     id: i64,
     props: UpdateRestaurantProps,
 ) -> anyhow::Result<()> {
+    let action = match props {
+        UpdateRestaurantProps::Delete => AuditAction::Delete,
+        _ => AuditAction::Update,
+    };
+
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, EntityKind::Restaurant, id)
+        .await?
+        .with_context(|| format!("restaurant {id} not found"))?;
     props
         .into_query(id)
-        .execute(db_conn)
+        .execute(&mut tx)
         .await
         .with_context(|| "fail to update restaurant")?;
+    let after = snapshot(&mut tx, EntityKind::Restaurant, id).await?;
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Restaurant,
+        id,
+        Some(before),
+        after,
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
 #[tokio::test]
 async fn test_add_new_review() {
-    let db = sqlx::sqlite::SqlitePool::connect("sqlite:review.db")
-        .await
-        .unwrap();
+    let db = test_pool().await;
 
     add_new_user(&db, (649191333, "Avimitin")).await.unwrap();
     let expect = "KFC";
-    let rid = add_restaurant(&db, expect, "WuHan").await.unwrap();
+    let rid = add_restaurant(&db, Actor::System, expect, "WuHan")
+        .await
+        .unwrap();
     let restaurant = get_restaurant(&db, RestaurantSearchProps::All)
         .await
         .unwrap();
@@ -288,7 +963,7 @@ async fn test_add_new_review() {
     assert_eq!(restaurant[0].id, 1);
     assert_eq!(restaurant[0].name, expect);
 
-    let did = add_dish(&db, rid, "", None).await.unwrap();
+    let did = add_dish(&db, Actor::System, rid, "", None).await.unwrap();
 
     let comment = "Very good chicken, love from WuHan";
     let prop = NewReviewPropsBuilder::default()
@@ -312,3 +987,87 @@ async fn test_add_new_review() {
 
     assert_eq!(review.details, comment);
 }
+
+#[tokio::test]
+async fn test_resolve_ambiguous_name() {
+    let db = test_pool().await;
+    add_new_user(&db, (1, "Avimitin")).await.unwrap();
+    let first = add_restaurant(&db, Actor::System, "老乡鸡", "光谷")
+        .await
+        .unwrap();
+    let second = add_restaurant(&db, Actor::System, "湘菜馆", "街道口")
+        .await
+        .unwrap();
+    let d1 = add_dish(&db, Actor::System, first, "宫保鸡丁", None)
+        .await
+        .unwrap();
+    let d2 = add_dish(&db, Actor::System, second, "宫保鸡丁", None)
+        .await
+        .unwrap();
+
+    let unscoped = DishProp::Name {
+        name: "宫保鸡丁".to_string(),
+        restaurant: None,
+    };
+    let err = unscoped.resolve(&db).await.unwrap_err();
+    match err.downcast_ref::<LookupError>() {
+        Some(LookupError::Ambiguous { candidates, .. }) => {
+            let ids = candidates.iter().map(|c| c.id).collect::<Vec<_>>();
+            assert_eq!(ids, vec![d1, d2]);
+        }
+        _ => panic!("expect ambiguous error, got {err}"),
+    }
+
+    let prop = NewReviewPropsBuilder::default()
+        .dish(unscoped)
+        .reviewer(ReviewerProp::Name("Avimitin".to_string()))
+        .details("好恰".to_string())
+        .score(4)
+        .build()
+        .unwrap();
+    assert!(add_new_review(&db, prop).await.is_err());
+
+    let scoped = DishProp::Name {
+        name: "宫保鸡丁".to_string(),
+        restaurant: Some(second),
+    };
+    assert_eq!(scoped.resolve(&db).await.unwrap(), d2);
+
+    let missing = DishProp::Name {
+        name: "宫保鸡丁".to_string(),
+        restaurant: Some(second + 1),
+    };
+    let err = missing.resolve(&db).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<LookupError>(),
+        Some(LookupError::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_similar_restaurant() {
+    let db = test_pool().await;
+
+    let props = NewRestaurantPropsBuilder::default()
+        .name("KFC Guanggu")
+        .address("WuHan")
+        .phone("027-12345678")
+        .location((30.5, 114.4))
+        .build()
+        .unwrap();
+    let id = add_restaurant_detail(&db, Actor::System, props)
+        .await
+        .unwrap();
+    add_restaurant(&db, Actor::System, "麦当劳", "WuHan")
+        .await
+        .unwrap();
+
+    let similar = similar_restaurant(&db, "kfc").await.unwrap();
+    assert_eq!(similar.len(), 1);
+    assert_eq!(similar[0].id, id);
+    assert_eq!(similar[0].phone.as_deref(), Some("027-12345678"));
+    assert_eq!(similar[0].latitude, Some(30.5));
+
+    assert!(similar_restaurant(&db, "汉堡王").await.unwrap().is_empty());
+    assert_eq!(search_restaurant(&db, "麦").await.unwrap().len(), 1);
+}
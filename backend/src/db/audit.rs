@@ -0,0 +1,592 @@
+use super::{
+    enqueue_deliveries, insert_ratings, replace_tags, split_tags, tags_of, EntityKind, Pool, DB,
+};
+use anyhow::Context;
+use derive_builder::Builder;
+use serde_json::Value;
+use sqlx::{any::AnyRow, Column, Row, Transaction};
+
+/// Who made the change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    User(i64),
+    /// Maintenance operations from the command line
+    System,
+}
+
+impl Actor {
+    pub(super) fn db_id(self) -> Option<i64> {
+        match self {
+            Self::User(id) => Some(id),
+            Self::System => None,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    /// The row is deleted after everything in it is moved into another row
+    Merge,
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Merge => "merge",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            "merge" => Ok(Self::Merge),
+            _ => anyhow::bail!("unknown audit action {s}"),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// User id, or None for system operations
+    pub actor: Option<i64>,
+    pub action: AuditAction,
+    pub entity: EntityKind,
+    pub entity_id: i64,
+    /// The row before and after the change, as JSON object
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<Value>,
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<Value>,
+    pub created_at: String,
+    /// The entry that this entry reverts
+    pub reverts: Option<i64>,
+    /// The entry that reverts this entry
+    pub reverted_by: Option<i64>,
+}
+
+impl TryFrom<AnyRow> for AuditEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(row: AnyRow) -> Result<Self, Self::Error> {
+        let json = |col: &str| -> anyhow::Result<Option<Value>> {
+            row.get::<Option<String>, _>(col)
+                .map(|s| serde_json::from_str(&s))
+                .transpose()
+                .with_context(|| format!("invalid json in audit log column {col}"))
+        };
+        Ok(Self {
+            id: row.get("id"),
+            actor: row.get("actor"),
+            action: row.get::<String, _>("action").parse()?,
+            entity: row.get::<String, _>("entity").parse()?,
+            entity_id: row.get("entity_id"),
+            before: json("before")?,
+            after: json("after")?,
+            created_at: row.get("created_at"),
+            reverts: row.get("reverts"),
+            reverted_by: row.get("reverted_by"),
+        })
+    }
+}
+
+// convert every column of the row into JSON object
+fn row_to_json(row: &AnyRow) -> Value {
+    let mut obj = serde_json::Map::new();
+    for (i, col) in row.columns().iter().enumerate() {
+        let value = if let Ok(v) = row.try_get::<Option<i64>, _>(i) {
+            v.map(Value::from)
+        } else if let Ok(v) = row.try_get::<Option<f64>, _>(i) {
+            v.map(Value::from)
+        } else {
+            row.try_get::<Option<String>, _>(i)
+                .ok()
+                .flatten()
+                .map(Value::from)
+        };
+        obj.insert(col.name().to_string(), value.unwrap_or(Value::Null));
+    }
+    Value::Object(obj)
+}
+
+/// Read the current row as JSON object, or None if the row doesn't exist
+pub(super) async fn snapshot(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<Option<Value>> {
+    let sql = format!("SELECT * FROM {} WHERE id=$1", kind.table());
+    let row = sqlx::query(&sql).bind(id).fetch_optional(&mut *tx).await?;
+    let Some(mut row) = row.as_ref().map(row_to_json) else {
+        return Ok(None);
+    };
+    // tags live in their own tables, keep them with the row so they are restored together
+    if let Some(tags) = tags_of(tx, kind, id).await? {
+        let tags = (!tags.is_empty()).then(|| tags.join(","));
+        row[TAGS] = tags.map(Value::from).unwrap_or(Value::Null);
+    }
+    // and the sub-scores of a review, which are deleted with it
+    if kind == EntityKind::Review {
+        let ratings = sqlx::query(
+            "SELECT dimension, score FROM review_rating WHERE review=$1 ORDER BY dimension",
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|r| (r.get("dimension"), Value::from(r.get::<i64, _>("score"))))
+        .collect::<serde_json::Map<_, _>>();
+        row[RATINGS] = Value::Object(ratings);
+    }
+    Ok(Some(row))
+}
+
+// columns of the snapshot that are not in the table
+const TAGS: &str = "tags";
+const RATINGS: &str = "ratings";
+
+// restore the tags and the ratings kept in the snapshot
+async fn restore_links(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    id: i64,
+    row: &Value,
+) -> anyhow::Result<()> {
+    if let Some(tags) = row.get(TAGS) {
+        let tags = tags.as_str().map(split_tags).unwrap_or_default();
+        replace_tags(tx, kind, id, &tags).await?;
+    }
+    if let Some(ratings) = row.get(RATINGS).and_then(Value::as_object) {
+        sqlx::query("DELETE FROM review_rating WHERE review=$1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let ratings = ratings
+            .iter()
+            .map(|(dimension, score)| {
+                let score = score.as_u64().context("invalid rating in audit snapshot")?;
+                Ok((dimension.parse()?, u8::try_from(score)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        insert_ratings(tx, id, &ratings).await?;
+    }
+    Ok(())
+}
+
+/// Write an audit entry in the transaction of the change
+pub(super) async fn record(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    action: AuditAction,
+    kind: EntityKind,
+    id: i64,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> anyhow::Result<i64> {
+    let entry = sqlx::query(
+        r#"
+INSERT INTO audit_log
+    (actor, action, entity, entity_id, before, after)
+VALUES
+    ($1, $2, $3, $4, $5, $6)
+RETURNING id"#,
+    )
+    .bind(actor.db_id())
+    .bind(action.to_string())
+    .bind(kind.to_string())
+    .bind(id)
+    .bind(before.as_ref().map(|v| v.to_string()))
+    .bind(after.as_ref().map(|v| v.to_string()))
+    .fetch_one(&mut *tx)
+    .await
+    .with_context(|| format!("fail to write audit log for {kind} {id}"))?
+    .get("id");
+    let (before, after) = (before.as_ref(), after.as_ref());
+    enqueue_deliveries(tx, entry, actor, action, kind, id, before, after).await?;
+    Ok(entry)
+}
+
+/// Record a new row after it is inserted
+pub(super) async fn record_create(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<i64> {
+    let after = snapshot(tx, kind, id).await?;
+    record(tx, actor, AuditAction::Create, kind, id, None, after).await
+}
+
+#[derive(Builder, Default)]
+pub struct AuditQuery {
+    #[builder(setter(strip_option), default)]
+    entity: Option<EntityKind>,
+    #[builder(setter(strip_option), default)]
+    entity_id: Option<i64>,
+    #[builder(setter(strip_option), default)]
+    actor: Option<i64>,
+    /// Only return the entries older than this entry, for pagination
+    #[builder(setter(strip_option), default)]
+    before_id: Option<i64>,
+    #[builder(default = "50")]
+    limit: u32,
+}
+
+/// Get audit entries from the newest to the oldest
+pub async fn get_audit_log(db_conn: &Pool, query: AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+    let AuditQuery {
+        entity,
+        entity_id,
+        actor,
+        before_id,
+        limit,
+    } = query;
+
+    sqlx::query(
+        r#"
+SELECT * FROM audit_log
+WHERE ($1 IS NULL OR entity = $1)
+  AND ($2 IS NULL OR entity_id = $2)
+  AND ($3 IS NULL OR actor = $3)
+  AND ($4 IS NULL OR id < $4)
+ORDER BY id DESC
+LIMIT $5"#,
+    )
+    .bind(entity.map(|e| e.to_string()))
+    .bind(entity_id)
+    .bind(actor)
+    .bind(before_id)
+    .bind(i64::from(limit))
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to get audit log")?
+    .into_iter()
+    .map(AuditEntry::try_from)
+    .collect()
+}
+
+// nulls are written into the SQL as `NULL` instead, PostgreSQL rejects a null of another type
+fn bind_json<'q>(
+    query: sqlx::query::Query<'q, DB, super::DBArg<'q>>,
+    value: &Value,
+) -> sqlx::query::Query<'q, DB, super::DBArg<'q>> {
+    match value {
+        Value::Null => query,
+        Value::Bool(b) => query.bind(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => query.bind(i),
+            None => query.bind(n.as_f64()),
+        },
+        Value::String(s) => query.bind(s.clone()),
+        other => query.bind(other.to_string()),
+    }
+}
+
+// Column names from the snapshot, validate them before putting them into SQL
+fn columns(row: &Value) -> anyhow::Result<Vec<(&String, &Value)>> {
+    let obj = row.as_object().context("audit snapshot is not an object")?;
+    for key in obj.keys() {
+        anyhow::ensure!(
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+            "invalid column name {key} in audit snapshot"
+        );
+    }
+    Ok(obj
+        .iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "id" | TAGS | RATINGS))
+        .collect())
+}
+
+// `$n` placeholders for the values of the columns starting from `first`, or `NULL`
+fn params(cols: &[(&String, &Value)], first: usize) -> Vec<String> {
+    let mut n = first;
+    cols.iter()
+        .map(|(_, v)| {
+            if v.is_null() {
+                return "NULL".to_string();
+            }
+            n += 1;
+            format!("${}", n - 1)
+        })
+        .collect()
+}
+
+/// Undo the change of an audit entry. The undo is recorded as a new entry, and its id is returned.
+pub async fn revert_audit(db_conn: &Pool, actor: Actor, entry: i64) -> anyhow::Result<i64> {
+    let mut tx = db_conn.begin().await?;
+
+    let entry: AuditEntry = sqlx::query("SELECT * FROM audit_log WHERE id=$1")
+        .bind(entry)
+        .fetch_optional(&mut *tx)
+        .await?
+        .with_context(|| format!("audit entry {entry} not found"))?
+        .try_into()?;
+    if let Some(by) = entry.reverted_by {
+        anyhow::bail!("audit entry {} is already reverted by {by}", entry.id);
+    }
+
+    let (kind, id) = (entry.entity, entry.entity_id);
+    // the token hashes and webhook secrets are not kept, and the allowlist, the subscriptions and
+    // the digests are changed with their own commands
+    anyhow::ensure!(
+        !matches!(
+            kind,
+            EntityKind::Token
+                | EntityKind::Chat
+                | EntityKind::Webhook
+                | EntityKind::Subscription
+                | EntityKind::Digest
+        ),
+        "changes of {kind} can not be reverted"
+    );
+    let table = kind.table();
+    let current = snapshot(&mut tx, kind, id).await?;
+    // the later changes would be lost, they have to be reverted first
+    let changed_again = || {
+        anyhow::anyhow!(
+            "{kind} {id} changed after audit entry {}, revert the later entries first",
+            entry.id
+        )
+    };
+    let (action, after) = match entry.action {
+        AuditAction::Create => {
+            anyhow::ensure!(current.is_some(), "{kind} {id} is already deleted");
+            if current != entry.after {
+                return Err(changed_again());
+            }
+            sqlx::query(&format!("DELETE FROM {table} WHERE id=$1"))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            (AuditAction::Delete, None)
+        }
+        AuditAction::Update => {
+            anyhow::ensure!(current.is_some(), "{kind} {id} is deleted");
+            if current != entry.after {
+                return Err(changed_again());
+            }
+            let before = entry.before.context("update entry without old value")?;
+            let cols = columns(&before)?;
+            if !cols.is_empty() {
+                let values = params(&cols, 1);
+                let set = cols
+                    .iter()
+                    .zip(values)
+                    .map(|((k, _), v)| format!("{k}={v}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let n = cols.iter().filter(|(_, v)| !v.is_null()).count();
+                let sql = format!("UPDATE {table} SET {set} WHERE id=${}", n + 1);
+                let mut query = sqlx::query(&sql);
+                for (_, v) in &cols {
+                    query = bind_json(query, v);
+                }
+                query.bind(id).execute(&mut *tx).await?;
+            }
+            restore_links(&mut tx, kind, id, &before).await?;
+            (AuditAction::Update, snapshot(&mut tx, kind, id).await?)
+        }
+        AuditAction::Delete | AuditAction::Merge => {
+            anyhow::ensure!(current.is_none(), "{kind} {id} already exists");
+            let before = entry.before.context("delete entry without old value")?;
+            let cols = columns(&before)?;
+            let names = cols.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+            let sql = format!(
+                "INSERT INTO {table} (id, {}) VALUES ($1, {})",
+                names.join(", "),
+                params(&cols, 2).join(", ")
+            );
+            let mut query = sqlx::query(&sql).bind(id);
+            for (_, v) in &cols {
+                query = bind_json(query, v);
+            }
+            query.execute(&mut *tx).await?;
+            restore_links(&mut tx, kind, id, &before).await?;
+            (AuditAction::Create, snapshot(&mut tx, kind, id).await?)
+        }
+    };
+
+    let revert = record(&mut tx, actor, action, kind, id, current, after).await?;
+    sqlx::query("UPDATE audit_log SET reverts=$1 WHERE id=$2")
+        .bind(entry.id)
+        .bind(revert)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE audit_log SET reverted_by=$1 WHERE id=$2")
+        .bind(revert)
+        .bind(entry.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit()
+        .await
+        .with_context(|| format!("fail to revert audit entry {}", entry.id))?;
+
+    Ok(revert)
+}
+
+/// Describe the changed fields of an entry in one line, like `name: KFC -> 肯德基`
+pub fn describe_change(entry: &AuditEntry) -> String {
+    let show = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    match (&entry.before, &entry.after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => before
+            .iter()
+            .filter(|(k, v)| after.get(*k) != Some(*v))
+            .map(|(k, v)| {
+                let new = after.get(k).map(show).unwrap_or_default();
+                format!("{k}: {} -> {new}", show(v))
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        (None, Some(row)) | (Some(row), None) => row
+            .get("name")
+            .or_else(|| row.get("details"))
+            .map(show)
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_audit_and_revert() {
+    use super::*;
+
+    let db = test_pool().await;
+    let actor = Actor::User(42);
+    let rid = add_restaurant(&db, actor, "KFC", "WuHan").await.unwrap();
+    let props = UpdateRestaurantProps::UpdateName("肯德基".to_string());
+    update_restaurant(&db, Actor::System, rid, props)
+        .await
+        .unwrap();
+
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Restaurant)
+        .entity_id(rid)
+        .build()
+        .unwrap();
+    let log = get_audit_log(&db, query).await.unwrap();
+    assert_eq!(log.len(), 2);
+    let (update, create) = (&log[0], &log[1]);
+    assert_eq!(create.action, AuditAction::Create);
+    assert_eq!(create.actor, Some(42));
+    assert_eq!(update.action, AuditAction::Update);
+    assert_eq!(update.actor, None);
+    assert_eq!(describe_change(update), "name: KFC -> 肯德基");
+
+    // undo the rename
+    revert_audit(&db, actor, update.id).await.unwrap();
+    let rst = get_restaurant(&db, RestaurantSearchProps::Id(rid))
+        .await
+        .unwrap();
+    assert_eq!(rst[0].name, "KFC");
+    assert!(revert_audit(&db, actor, update.id).await.is_err());
+
+    // undo the delete
+    update_restaurant(&db, actor, rid, UpdateRestaurantProps::Delete)
+        .await
+        .unwrap();
+    let query = AuditQueryBuilder::default().limit(1).build().unwrap();
+    let delete = get_audit_log(&db, query).await.unwrap().remove(0);
+    assert_eq!(delete.action, AuditAction::Delete);
+    revert_audit(&db, actor, delete.id).await.unwrap();
+    let rst = get_restaurant(&db, RestaurantSearchProps::Id(rid))
+        .await
+        .unwrap();
+    assert_eq!(rst[0].address, "WuHan");
+}
+
+#[tokio::test]
+async fn test_revert_after_later_changes() {
+    use super::*;
+
+    let db = test_pool().await;
+    let actor = Actor::System;
+    let rid = add_restaurant(&db, actor, "KFC", "WuHan").await.unwrap();
+    for name in ["肯德基", "开封菜"] {
+        let props = UpdateRestaurantProps::UpdateName(name.to_string());
+        update_restaurant(&db, actor, rid, props).await.unwrap();
+    }
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Restaurant)
+        .entity_id(rid)
+        .build()
+        .unwrap();
+    let log = get_audit_log(&db, query).await.unwrap();
+    let (second, first, create) = (&log[0], &log[1], &log[2]);
+
+    // the second rename would be overwritten
+    let err = revert_audit(&db, actor, first.id).await.unwrap_err();
+    assert!(err.to_string().contains("changed after"), "{err}");
+    assert!(revert_audit(&db, actor, create.id).await.is_err());
+    let rst = get_restaurant(&db, RestaurantSearchProps::Id(rid))
+        .await
+        .unwrap();
+    assert_eq!(rst[0].name, "开封菜");
+
+    // undo from the latest one
+    revert_audit(&db, actor, second.id).await.unwrap();
+    revert_audit(&db, actor, first.id).await.unwrap();
+    let rst = get_restaurant(&db, RestaurantSearchProps::Id(rid))
+        .await
+        .unwrap();
+    assert_eq!(rst[0].name, "KFC");
+}
+
+#[tokio::test]
+async fn test_revert_review_delete_with_ratings() {
+    use super::*;
+
+    let db = test_pool().await;
+    add_new_user(&db, (1, "alice")).await.unwrap();
+    let rid = add_restaurant(&db, Actor::System, "KFC", "WuHan")
+        .await
+        .unwrap();
+    let did = add_dish(&db, Actor::System, rid, "汉堡", None)
+        .await
+        .unwrap();
+    let props = NewReviewPropsBuilder::default()
+        .dish(DishProp::Id(did))
+        .reviewer(ReviewerProp::Id(1))
+        .details("好恰".to_string())
+        .score(4)
+        .ratings(vec![
+            (RatingDimension::Taste, 5),
+            (RatingDimension::Value, 2),
+        ])
+        .build()
+        .unwrap();
+    add_new_review(&db, props).await.unwrap();
+
+    // undo the review, then undo the undo
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Review)
+        .build()
+        .unwrap();
+    let create = get_audit_log(&db, query).await.unwrap().remove(0);
+    let id = create.entity_id;
+    let delete = revert_audit(&db, Actor::System, create.id).await.unwrap();
+    assert!(get_ratings(&db, id).await.unwrap().is_empty());
+
+    revert_audit(&db, Actor::System, delete).await.unwrap();
+    let ratings = get_ratings(&db, id).await.unwrap();
+    assert_eq!(ratings.get(&RatingDimension::Taste), Some(&5));
+    assert_eq!(ratings.get(&RatingDimension::Value), Some(&2));
+}
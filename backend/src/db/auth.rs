@@ -0,0 +1,400 @@
+use super::{record, snapshot, Actor, AuditAction, EntityKind, Pool};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+
+/// Role of a user, a higher role can do everything a lower role can do
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Banned,
+    Reviewer,
+    Editor,
+    Admin,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Banned => "banned",
+            Self::Reviewer => "reviewer",
+            Self::Editor => "editor",
+            Self::Admin => "admin",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "banned" => Ok(Self::Banned),
+            "reviewer" => Ok(Self::Reviewer),
+            "editor" => Ok(Self::Editor),
+            "admin" => Ok(Self::Admin),
+            _ => anyhow::bail!("unknown role {s}, expect admin, editor, reviewer or banned"),
+        }
+    }
+}
+
+/// Operations that need permission
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Review,
+    /// Add new restaurants and dishes
+    Create,
+    /// Update existing restaurants and dishes
+    Edit,
+    Delete,
+    /// Manage roles, merge duplication and other maintenance operations
+    Admin,
+}
+
+impl Permission {
+    pub fn required_role(self) -> Role {
+        match self {
+            Self::Read | Self::Review | Self::Create => Role::Reviewer,
+            Self::Edit => Role::Editor,
+            Self::Delete | Self::Admin => Role::Admin,
+        }
+    }
+}
+
+impl Role {
+    pub fn can(self, permission: Permission) -> bool {
+        self >= permission.required_role()
+    }
+
+    /// Return [`PermissionDenied`] error when this role can't do the operation
+    pub fn check(self, permission: Permission) -> Result<(), PermissionDenied> {
+        if self.can(permission) {
+            Ok(())
+        } else {
+            Err(PermissionDenied {
+                role: self,
+                permission,
+            })
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PermissionDenied {
+    pub role: Role,
+    pub permission: Permission,
+}
+
+impl std::fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "permission denied: {:?} requires {} role, but you are {}",
+            self.permission,
+            self.permission.required_role(),
+            self.role
+        )
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+/// Insert the user or update its name, and return its role. Nothing is written when the name
+/// doesn't change, as it is called on every message to the bot.
+pub async fn register_reviewer(db_conn: &Pool, id: i64, name: &str) -> anyhow::Result<Role> {
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, EntityKind::Reviewer, id).await?;
+    let row = match before {
+        Some(row) if row["name"].as_str() == Some(name) => row,
+        before => {
+            sqlx::query(
+                "INSERT INTO reviewer (id, name) VALUES ($1, $2) ON CONFLICT(id) DO UPDATE SET name=excluded.name",
+            )
+            .bind(id)
+            .bind(name)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("fail to register user {name}"))?;
+            let after = snapshot(&mut tx, EntityKind::Reviewer, id)
+                .await?
+                .with_context(|| format!("user {id} not found"))?;
+            let action = match before {
+                Some(_) => AuditAction::Update,
+                None => AuditAction::Create,
+            };
+            let reviewer = EntityKind::Reviewer;
+            record(
+                &mut tx,
+                Actor::User(id),
+                action,
+                reviewer,
+                id,
+                before,
+                Some(after.clone()),
+            )
+            .await?;
+            after
+        }
+    };
+    tx.commit().await?;
+
+    row["role"].as_str().unwrap_or_default().parse()
+}
+
+/// Get the role of the user, or None if the user never use the service
+pub async fn get_role(db_conn: &Pool, id: i64) -> anyhow::Result<Option<Role>> {
+    let row = sqlx::query("SELECT role FROM reviewer WHERE id=$1")
+        .bind(id)
+        .fetch_optional(db_conn)
+        .await?;
+    row.map(|r| r.get::<String, _>("role").parse()).transpose()
+}
+
+pub async fn set_role(db_conn: &Pool, actor: Actor, id: i64, role: Role) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, EntityKind::Reviewer, id)
+        .await?
+        .with_context(|| format!("user {id} not found"))?;
+    sqlx::query("UPDATE reviewer SET role=$1 WHERE id=$2")
+        .bind(role.to_string())
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("fail to set role of user {id}"))?;
+    let after = snapshot(&mut tx, EntityKind::Reviewer, id).await?;
+    let action = AuditAction::Update;
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Reviewer,
+        id,
+        Some(before),
+        after,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Create a new API token for the user. The token is only returned here, the database only keeps
+/// its hash.
+pub async fn create_api_token(db_conn: &Pool, reviewer: i64) -> anyhow::Result<String> {
+    let token = hex::encode(rand::random::<[u8; 24]>());
+    let mut tx = db_conn.begin().await?;
+    sqlx::query("INSERT INTO api_token (hash, reviewer) VALUES ($1, $2)")
+        .bind(hash_token(&token))
+        .bind(reviewer)
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("fail to create token for user {reviewer}"))?;
+    // tokens are audited by their owner, the hash is left out of the log
+    let after = serde_json::json!({ "reviewer": reviewer });
+    let (actor, action) = (Actor::User(reviewer), AuditAction::Create);
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Token,
+        reviewer,
+        None,
+        Some(after),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(token)
+}
+
+/// Find the user id and role of the API token
+pub async fn token_owner(db_conn: &Pool, token: &str) -> anyhow::Result<Option<(i64, Role)>> {
+    let row = sqlx::query(
+        r#"
+SELECT reviewer.id, reviewer.role
+FROM api_token JOIN reviewer ON api_token.reviewer = reviewer.id
+WHERE api_token.hash = $1"#,
+    )
+    .bind(hash_token(token))
+    .fetch_optional(db_conn)
+    .await?;
+
+    row.map(|r| Ok((r.get("id"), r.get::<String, _>("role").parse()?)))
+        .transpose()
+}
+
+/// Remove every API token of the user
+pub async fn revoke_api_tokens(db_conn: &Pool, actor: Actor, reviewer: i64) -> anyhow::Result<u64> {
+    let mut tx = db_conn.begin().await?;
+    let removed = sqlx::query("DELETE FROM api_token WHERE reviewer=$1")
+        .bind(reviewer)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    if removed > 0 {
+        let before = serde_json::json!({ "reviewer": reviewer, "tokens": removed });
+        let action = AuditAction::Delete;
+        record(
+            &mut tx,
+            actor,
+            action,
+            EntityKind::Token,
+            reviewer,
+            Some(before),
+            None,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(removed)
+}
+
+pub async fn allow_chat(db_conn: &Pool, actor: Actor, chat: i64) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    let added = sqlx::query("INSERT INTO chat_allowlist (chat) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(chat)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    if added > 0 {
+        let after = serde_json::json!({ "chat": chat });
+        let action = AuditAction::Create;
+        record(
+            &mut tx,
+            actor,
+            action,
+            EntityKind::Chat,
+            chat,
+            None,
+            Some(after),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn disallow_chat(db_conn: &Pool, actor: Actor, chat: i64) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    let removed = sqlx::query("DELETE FROM chat_allowlist WHERE chat=$1")
+        .bind(chat)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    if removed > 0 {
+        let before = serde_json::json!({ "chat": chat });
+        let action = AuditAction::Delete;
+        record(
+            &mut tx,
+            actor,
+            action,
+            EntityKind::Chat,
+            chat,
+            Some(before),
+            None,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn is_chat_allowed(db_conn: &Pool, chat: i64) -> anyhow::Result<bool> {
+    let row = sqlx::query("SELECT chat FROM chat_allowlist WHERE chat=$1")
+        .bind(chat)
+        .fetch_optional(db_conn)
+        .await?;
+    Ok(row.is_some())
+}
+
+#[test]
+fn test_role_permission() {
+    assert!(Role::Admin.can(Permission::Delete));
+    assert!(Role::Editor.can(Permission::Edit));
+    assert!(!Role::Editor.can(Permission::Admin));
+    assert!(Role::Reviewer.can(Permission::Create));
+    assert!(Role::Reviewer.check(Permission::Edit).is_err());
+    assert!(!Role::Banned.can(Permission::Read));
+    assert_eq!("editor".parse::<Role>().unwrap(), Role::Editor);
+}
+
+#[tokio::test]
+async fn test_roles_and_tokens() {
+    let db = super::test_pool().await;
+
+    assert_eq!(get_role(&db, 1).await.unwrap(), None);
+    assert_eq!(
+        register_reviewer(&db, 1, "Avimitin").await.unwrap(),
+        Role::Reviewer
+    );
+    set_role(&db, Actor::System, 1, Role::Editor).await.unwrap();
+    // register again only update the name
+    assert_eq!(
+        register_reviewer(&db, 1, "Avi").await.unwrap(),
+        Role::Editor
+    );
+    assert!(set_role(&db, Actor::System, 2, Role::Admin).await.is_err());
+    // the same name writes nothing
+    register_reviewer(&db, 1, "Avi").await.unwrap();
+    let log = |kind| {
+        let query = super::AuditQueryBuilder::default()
+            .entity(kind)
+            .build()
+            .unwrap();
+        super::get_audit_log(&db, query)
+    };
+    let actions = log(EntityKind::Reviewer)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|e| e.action)
+        .collect::<Vec<_>>();
+    use AuditAction::{Create, Delete, Update};
+    assert_eq!(actions, [Update, Update, Create]);
+
+    let token = create_api_token(&db, 1).await.unwrap();
+    assert_eq!(
+        token_owner(&db, &token).await.unwrap(),
+        Some((1, Role::Editor))
+    );
+    assert_eq!(token_owner(&db, "guess").await.unwrap(), None);
+    assert_eq!(revoke_api_tokens(&db, Actor::System, 1).await.unwrap(), 1);
+    assert_eq!(token_owner(&db, &token).await.unwrap(), None);
+    let tokens = log(EntityKind::Token).await.unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert_eq!((tokens[0].action, tokens[0].entity_id), (Delete, 1));
+    assert_eq!(tokens[1].actor, Some(1));
+    assert!(!tokens[1]
+        .after
+        .as_ref()
+        .unwrap()
+        .to_string()
+        .contains(&token));
+
+    assert!(!is_chat_allowed(&db, -100).await.unwrap());
+    allow_chat(&db, Actor::System, -100).await.unwrap();
+    allow_chat(&db, Actor::System, -100).await.unwrap();
+    assert!(is_chat_allowed(&db, -100).await.unwrap());
+    disallow_chat(&db, Actor::System, -100).await.unwrap();
+    assert!(!is_chat_allowed(&db, -100).await.unwrap());
+    let chats = log(EntityKind::Chat).await.unwrap();
+    assert_eq!(chats.len(), 2);
+    assert!(super::revert_audit(&db, Actor::System, chats[0].id)
+        .await
+        .is_err());
+}
@@ -0,0 +1,522 @@
+use super::{record, Actor, AuditAction, EntityKind, Pool, DEFAULT_UTC_OFFSET};
+use anyhow::Context;
+use chrono::{Datelike, Duration, NaiveDateTime, Weekday};
+use sqlx::{any::AnyRow, Row};
+
+// how many rows each section of the digest lists
+const SECTION_LIMIT: i64 = 5;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// When a chat gets the weekly digest, in local time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestSetting {
+    pub chat: i64,
+    pub weekday: Weekday,
+    pub hour: u32,
+    pub last_sent: NaiveDateTime,
+}
+
+impl DigestSetting {
+    /// The latest scheduled time that is not after `now`
+    fn scheduled_before(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let days =
+            (7 + now.weekday().num_days_from_monday() - self.weekday.num_days_from_monday()) % 7;
+        let time = (now.date() - Duration::days(days.into()))
+            .and_hms_opt(self.hour, 0, 0)
+            .unwrap_or(now);
+        if time > now {
+            time - Duration::days(7)
+        } else {
+            time
+        }
+    }
+
+    /// Whether a digest is scheduled after the last post and not later than `now`
+    pub fn is_due(&self, now: NaiveDateTime) -> bool {
+        self.scheduled_before(now) > self.last_sent
+    }
+}
+
+impl std::fmt::Display for DigestSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "every {} at {:02}:00, last sent at {}",
+            self.weekday, self.hour, self.last_sent
+        )
+    }
+}
+
+impl TryFrom<AnyRow> for DigestSetting {
+    type Error = anyhow::Error;
+
+    fn try_from(row: AnyRow) -> Result<Self, Self::Error> {
+        let weekday: i64 = row.get("weekday");
+        anyhow::ensure!(weekday < 7, "invalid weekday {weekday}");
+        let last_sent: String = row.get("last_sent");
+        Ok(Self {
+            chat: row.get("chat"),
+            weekday: (0..weekday).fold(Weekday::Mon, |day, _| day.succ()),
+            hour: row.get::<i64, _>("hour").try_into()?,
+            last_sent: NaiveDateTime::parse_from_str(&last_sent, TIME_FORMAT)?,
+        })
+    }
+}
+
+/// Post the weekly digest to the chat, starting from the next scheduled time after `now`
+pub async fn set_digest(
+    db_conn: &Pool,
+    actor: Actor,
+    chat: i64,
+    weekday: Weekday,
+    hour: u32,
+    now: NaiveDateTime,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(hour < 24, "hour should be in range 0 - 23");
+    let mut tx = db_conn.begin().await?;
+    let before = sqlx::query("SELECT * FROM digest_setting WHERE chat=$1")
+        .bind(chat)
+        .fetch_optional(&mut tx)
+        .await?
+        .map(DigestSetting::try_from)
+        .transpose()?;
+    sqlx::query(
+        r#"
+INSERT INTO digest_setting (chat, weekday, hour, last_sent) VALUES ($1, $2, $3, $4)
+ON CONFLICT(chat) DO UPDATE
+    SET weekday=excluded.weekday, hour=excluded.hour, last_sent=excluded.last_sent"#,
+    )
+    .bind(chat)
+    .bind(i64::from(weekday.num_days_from_monday()))
+    .bind(i64::from(hour))
+    .bind(now.format(TIME_FORMAT).to_string())
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("fail to set the digest of chat {chat}"))?;
+    let action = match before {
+        Some(_) => AuditAction::Update,
+        None => AuditAction::Create,
+    };
+    let before = before.map(|s| schedule_json(s.weekday, s.hour));
+    let after = schedule_json(weekday, hour);
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Digest,
+        chat,
+        before,
+        Some(after),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// the schedule in the audit log, without the time of the last post
+fn schedule_json(weekday: Weekday, hour: u32) -> serde_json::Value {
+    serde_json::json!({ "weekday": weekday.to_string(), "hour": hour })
+}
+
+/// Stop posting the digest to the chat, return false if it wasn't set
+pub async fn remove_digest(db_conn: &Pool, actor: Actor, chat: i64) -> anyhow::Result<bool> {
+    let mut tx = db_conn.begin().await?;
+    let Some(before) = sqlx::query("DELETE FROM digest_setting WHERE chat=$1 RETURNING *")
+        .bind(chat)
+        .fetch_optional(&mut tx)
+        .await?
+        .map(DigestSetting::try_from)
+        .transpose()?
+    else {
+        return Ok(false);
+    };
+    let before = schedule_json(before.weekday, before.hour);
+    let action = AuditAction::Delete;
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Digest,
+        chat,
+        Some(before),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn digest_setting(db_conn: &Pool, chat: i64) -> anyhow::Result<Option<DigestSetting>> {
+    sqlx::query("SELECT * FROM digest_setting WHERE chat=$1")
+        .bind(chat)
+        .fetch_optional(db_conn)
+        .await?
+        .map(DigestSetting::try_from)
+        .transpose()
+}
+
+/// Chats that should get the digest at `now`
+pub async fn due_digests(db_conn: &Pool, now: NaiveDateTime) -> anyhow::Result<Vec<DigestSetting>> {
+    let settings = sqlx::query("SELECT * FROM digest_setting ORDER BY chat")
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(DigestSetting::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(settings.into_iter().filter(|s| s.is_due(now)).collect())
+}
+
+pub async fn mark_digest_sent(db_conn: &Pool, chat: i64, at: NaiveDateTime) -> anyhow::Result<()> {
+    sqlx::query("UPDATE digest_setting SET last_sent=$1 WHERE chat=$2")
+        .bind(at.format(TIME_FORMAT).to_string())
+        .bind(chat)
+        .execute(db_conn)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DigestItem {
+    pub id: i64,
+    pub name: String,
+    /// Average score of the reviews, 0 when there is no review
+    pub value: f64,
+    pub reviews: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RatingMover {
+    pub id: i64,
+    pub name: String,
+    /// Average score before the week, and after the reviews of the week
+    pub before: f64,
+    pub after: f64,
+}
+
+/// What happened in the week before `until`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Digest {
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    pub new_restaurants: Vec<DigestItem>,
+    /// Dishes added in the week, the best rated first
+    pub top_new_dishes: Vec<DigestItem>,
+    pub active_reviewers: Vec<DigestItem>,
+    /// Restaurants whose average score changed the most
+    pub rating_movers: Vec<RatingMover>,
+}
+
+// reviews, restaurants and dishes don't keep the creation time, so the new rows are found by the
+// create entries in the audit log, whose time is in UTC
+macro_rules! with_created {
+    ($sql:literal) => {
+        concat!(
+            "WITH created AS (SELECT entity, entity_id AS id FROM audit_log ",
+            "WHERE action = 'create' AND created_at >= $1 AND created_at < $2) ",
+            $sql
+        )
+    };
+}
+
+/// Summarize the week before `until`, in local time
+pub async fn weekly_digest(db_conn: &Pool, until: NaiveDateTime) -> anyhow::Result<Digest> {
+    let since = until - Duration::days(7);
+    let utc = |time: NaiveDateTime| {
+        (time - Duration::seconds(DEFAULT_UTC_OFFSET))
+            .format(TIME_FORMAT)
+            .to_string()
+    };
+    let (start, end) = (utc(since), utc(until));
+    let items = |rows: Vec<AnyRow>| {
+        rows.into_iter()
+            .map(|row| DigestItem {
+                id: row.get("id"),
+                name: row.get("name"),
+                value: row.get("value"),
+                reviews: row.get("reviews"),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let new_restaurants = sqlx::query(with_created!(
+        r#"
+SELECT restaurant.id, restaurant.name, CAST(COALESCE(AVG(review.score), 0) AS DOUBLE PRECISION) AS value,
+    COUNT(review.id) AS reviews
+FROM restaurant
+    LEFT JOIN dish ON dish.restaurant = restaurant.id
+    LEFT JOIN review ON review.dish = dish.id
+WHERE restaurant.id IN (SELECT id FROM created WHERE entity = 'restaurant')
+GROUP BY restaurant.id
+ORDER BY restaurant.id
+LIMIT $3"#
+    ))
+    .bind(&start)
+    .bind(&end)
+    .bind(SECTION_LIMIT)
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to find the new restaurants")?;
+
+    let top_new_dishes = sqlx::query(with_created!(
+        r#"
+SELECT dish.id, dish.name || ' @ ' || restaurant.name AS name,
+    CAST(AVG(review.score) AS DOUBLE PRECISION) AS value, COUNT(review.id) AS reviews
+FROM dish
+    JOIN restaurant ON dish.restaurant = restaurant.id
+    JOIN review ON review.dish = dish.id
+WHERE dish.id IN (SELECT id FROM created WHERE entity = 'dish')
+GROUP BY dish.id, restaurant.id
+ORDER BY value DESC, reviews DESC, dish.id
+LIMIT $3"#
+    ))
+    .bind(&start)
+    .bind(&end)
+    .bind(SECTION_LIMIT)
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to find the top new dishes")?;
+
+    let active_reviewers = sqlx::query(with_created!(
+        r#"
+SELECT reviewer.id, COALESCE(reviewer.name, 'user ' || reviewer.id) AS name,
+    CAST(AVG(review.score) AS DOUBLE PRECISION) AS value, COUNT(review.id) AS reviews
+FROM review JOIN reviewer ON review.reviewer = reviewer.id
+WHERE review.id IN (SELECT id FROM created WHERE entity = 'review')
+GROUP BY reviewer.id
+ORDER BY reviews DESC, reviewer.id
+LIMIT $3"#
+    ))
+    .bind(&start)
+    .bind(&end)
+    .bind(SECTION_LIMIT)
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to find the active reviewers")?;
+
+    let rating_movers = sqlx::query(with_created!(
+        r#"
+SELECT * FROM (
+    SELECT restaurant.id, restaurant.name,
+        CAST(AVG(CASE WHEN review.id IN (SELECT id FROM created WHERE entity = 'review')
+            THEN NULL ELSE review.score END) AS DOUBLE PRECISION) AS before,
+        CAST(AVG(review.score) AS DOUBLE PRECISION) AS after
+    FROM restaurant
+        JOIN dish ON dish.restaurant = restaurant.id
+        JOIN review ON review.dish = dish.id
+    GROUP BY restaurant.id
+) AS average
+WHERE before IS NOT NULL AND after != before
+ORDER BY ABS(after - before) DESC, id
+LIMIT $3"#
+    ))
+    .bind(&start)
+    .bind(&end)
+    .bind(SECTION_LIMIT)
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to find the rating movers")?
+    .into_iter()
+    .map(|row| RatingMover {
+        id: row.get("id"),
+        name: row.get("name"),
+        before: row.get("before"),
+        after: row.get("after"),
+    })
+    .collect();
+
+    Ok(Digest {
+        since,
+        until,
+        new_restaurants: items(new_restaurants),
+        top_new_dishes: items(top_new_dishes),
+        active_reviewers: items(active_reviewers),
+        rating_movers,
+    })
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Weekly digest {} - {}",
+            self.since.date(),
+            (self.until - Duration::days(1)).date()
+        )?;
+        if self.new_restaurants.is_empty()
+            && self.top_new_dishes.is_empty()
+            && self.active_reviewers.is_empty()
+            && self.rating_movers.is_empty()
+        {
+            return write!(f, "\nNothing new this week");
+        }
+
+        if !self.new_restaurants.is_empty() {
+            write!(f, "\n\nNew restaurants:")?;
+            for item in &self.new_restaurants {
+                write!(f, "\n* {}", item.name)?;
+            }
+        }
+        if !self.top_new_dishes.is_empty() {
+            write!(f, "\n\nBest new dishes:")?;
+            for item in &self.top_new_dishes {
+                write!(
+                    f,
+                    "\n* {}: {:.1} / 5 by {} review(s)",
+                    item.name, item.value, item.reviews
+                )?;
+            }
+        }
+        if !self.active_reviewers.is_empty() {
+            write!(f, "\n\nMost active reviewers:")?;
+            for item in &self.active_reviewers {
+                write!(f, "\n* {}: {} review(s)", item.name, item.reviews)?;
+            }
+        }
+        if !self.rating_movers.is_empty() {
+            write!(f, "\n\nBiggest rating changes:")?;
+            for mover in &self.rating_movers {
+                write!(
+                    f,
+                    "\n* {}: {:.1} -> {:.1}",
+                    mover.name, mover.before, mover.after
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_digest() {
+    use super::*;
+
+    let db = test_pool().await;
+    let at = |s: &str| NaiveDateTime::parse_from_str(s, TIME_FORMAT).unwrap();
+
+    // every monday at 9, set on sunday
+    set_digest(
+        &db,
+        Actor::User(1),
+        -100,
+        Weekday::Mon,
+        9,
+        at("2023-01-22 10:00:00"),
+    )
+    .await
+    .unwrap();
+    assert!(due_digests(&db, at("2023-01-23 08:59:00"))
+        .await
+        .unwrap()
+        .is_empty());
+    let due = due_digests(&db, at("2023-01-23 09:00:00")).await.unwrap();
+    assert_eq!(due.len(), 1);
+    mark_digest_sent(&db, -100, at("2023-01-23 09:01:00"))
+        .await
+        .unwrap();
+    assert!(due_digests(&db, at("2023-01-24 09:00:00"))
+        .await
+        .unwrap()
+        .is_empty());
+    // the bot was down on monday, post it on tuesday
+    assert_eq!(
+        due_digests(&db, at("2023-01-31 12:00:00")).await.unwrap(),
+        vec![DigestSetting {
+            chat: -100,
+            weekday: Weekday::Mon,
+            hour: 9,
+            last_sent: at("2023-01-23 09:01:00"),
+        }]
+    );
+    assert!(set_digest(
+        &db,
+        Actor::User(1),
+        -100,
+        Weekday::Mon,
+        24,
+        at("2023-01-22 10:00:00")
+    )
+    .await
+    .is_err());
+    assert!(remove_digest(&db, Actor::User(1), -100).await.unwrap());
+    assert!(!remove_digest(&db, Actor::User(1), -100).await.unwrap());
+    assert!(digest_setting(&db, -100).await.unwrap().is_none());
+    // who turned the digest on and off, not the bookkeeping of the posts
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Digest)
+        .build()
+        .unwrap();
+    let log = get_audit_log(&db, query).await.unwrap();
+    assert_eq!(log.len(), 2);
+    assert_eq!(
+        (log[0].action, log[0].entity_id),
+        (AuditAction::Delete, -100)
+    );
+    assert_eq!(
+        log[1].after,
+        Some(serde_json::json!({ "weekday": "Mon", "hour": 9 }))
+    );
+
+    register_reviewer(&db, 1, "alice").await.unwrap();
+    register_reviewer(&db, 2, "bob").await.unwrap();
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    let burger = add_dish(&db, Actor::System, kfc, "汉堡", None)
+        .await
+        .unwrap();
+    let review = |reviewer, dish, score| {
+        NewReviewPropsBuilder::default()
+            .reviewer(ReviewerProp::Id(reviewer))
+            .dish(DishProp::Id(dish))
+            .score(score)
+            .details(String::new())
+            .build()
+            .unwrap()
+    };
+    add_new_review(&db, review(1, burger, 2)).await.unwrap();
+    // move everything so far to the last week
+    let last_week =
+        crate::db::local_now() - Duration::days(10) - Duration::seconds(DEFAULT_UTC_OFFSET);
+    sqlx::query("UPDATE audit_log SET created_at = $1")
+        .bind(last_week.format(TIME_FORMAT).to_string())
+        .execute(&*db)
+        .await
+        .unwrap();
+
+    let mcd = add_restaurant(&db, Actor::System, "McDonald", "光谷")
+        .await
+        .unwrap();
+    let fries = add_dish(&db, Actor::System, mcd, "薯条", None)
+        .await
+        .unwrap();
+    add_new_review(&db, review(1, fries, 5)).await.unwrap();
+    add_new_review(&db, review(2, fries, 4)).await.unwrap();
+    add_new_review(&db, review(2, burger, 4)).await.unwrap();
+
+    // one minute later in local time
+    let until = crate::db::local_now() + Duration::minutes(1);
+    let digest = weekly_digest(&db, until).await.unwrap();
+    assert_eq!(digest.new_restaurants.len(), 1);
+    assert_eq!(digest.new_restaurants[0].id, mcd);
+    assert_eq!(digest.top_new_dishes[0].name, "薯条 @ McDonald");
+    assert_eq!(digest.top_new_dishes[0].value, 4.5);
+    assert_eq!(digest.active_reviewers[0].name, "bob");
+    assert_eq!(digest.active_reviewers[0].reviews, 2);
+    assert_eq!(digest.rating_movers.len(), 1);
+    assert_eq!(digest.rating_movers[0].id, kfc);
+    assert_eq!(
+        (
+            digest.rating_movers[0].before,
+            digest.rating_movers[0].after
+        ),
+        (2.0, 3.0)
+    );
+    let text = digest.to_string();
+    assert!(text.contains("Best new dishes:\n* 薯条 @ McDonald: 4.5 / 5 by 2 review(s)"));
+    assert!(text.contains("KFC: 2.0 -> 3.0"));
+
+    let empty = weekly_digest(&db, until - Duration::days(30))
+        .await
+        .unwrap();
+    assert!(empty.to_string().ends_with("Nothing new this week"));
+}
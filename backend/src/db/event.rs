@@ -0,0 +1,404 @@
+use super::Pool;
+use sqlx::Row;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+// events kept for the slow receivers, the older ones are dropped with a lag error
+const CAPACITY: usize = 256;
+
+/// Something new added to the database, emitted after the transaction is committed.
+///
+/// The bus lives in the process, so a receiver only sees the mutations done by the same
+/// process, unless a [`ChangeWatcher`] publishes the rows added by the others.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    RestaurantAdded {
+        id: i64,
+        actor: Option<i64>,
+    },
+    ReviewAdded {
+        id: i64,
+        dish: i64,
+        restaurant: i64,
+        reviewer: i64,
+    },
+    DishAdded {
+        id: i64,
+        restaurant: i64,
+        actor: Option<i64>,
+    },
+    PhotoAdded {
+        dish: i64,
+        restaurant: i64,
+        actor: Option<i64>,
+    },
+}
+
+impl Event {
+    /// Restaurant the event happened in
+    pub fn restaurant(&self) -> i64 {
+        match self {
+            Self::RestaurantAdded { id, .. } => *id,
+            Self::ReviewAdded { restaurant, .. }
+            | Self::DishAdded { restaurant, .. }
+            | Self::PhotoAdded { restaurant, .. } => *restaurant,
+        }
+    }
+
+    /// User who made the change, None for system operations
+    pub fn actor(&self) -> Option<i64> {
+        match self {
+            Self::ReviewAdded { reviewer, .. } => Some(*reviewer),
+            Self::RestaurantAdded { actor, .. }
+            | Self::DishAdded { actor, .. }
+            | Self::PhotoAdded { actor, .. } => *actor,
+        }
+    }
+}
+
+/// The events of a process, published once whether the writer or a [`ChangeWatcher`] is the
+/// first to publish them
+struct Bus {
+    sender: broadcast::Sender<Event>,
+    // rows a watcher published before the writer in this process emitted them, the writer's
+    // events are dropped. The rows of the other processes never get one, so only the latest are
+    // kept.
+    polled: Mutex<VecDeque<(&'static str, i64)>>,
+}
+
+impl Bus {
+    fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            polled: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        // sent with the lock held, so a watcher sees the event before deciding to publish a row
+        let mut polled = self.polled.lock().unwrap();
+        if let Some(row) = polled_row(&event) {
+            if let Some(index) = polled.iter().position(|r| *r == row) {
+                polled.remove(index);
+                return;
+            }
+        }
+        // nobody is listening when it fails, which is fine
+        let _ = self.sender.send(event);
+    }
+}
+
+fn bus() -> &'static Bus {
+    static BUS: OnceLock<Bus> = OnceLock::new();
+    BUS.get_or_init(Bus::new)
+}
+
+/// Receive the events emitted after this call
+pub fn subscribe_events() -> broadcast::Receiver<Event> {
+    bus().sender.subscribe()
+}
+
+pub(super) fn emit(event: Event) {
+    bus().emit(event)
+}
+
+/// One line summary of the event for the notifications
+pub async fn describe_event(db_conn: &Pool, event: &Event) -> anyhow::Result<String> {
+    let restaurant: String = sqlx::query("SELECT name FROM restaurant WHERE id=$1")
+        .bind(event.restaurant())
+        .fetch_optional(db_conn)
+        .await?
+        .map(|row| row.get("name"))
+        .unwrap_or_else(|| format!("restaurant {}", event.restaurant()));
+    let dish_name = |id: i64| async move {
+        let name: Option<String> = sqlx::query("SELECT name FROM dish WHERE id=$1")
+            .bind(id)
+            .fetch_optional(db_conn)
+            .await?
+            .map(|row| row.get("name"));
+        anyhow::Ok(name.unwrap_or_else(|| format!("dish {id}")))
+    };
+
+    let text = match event {
+        Event::RestaurantAdded { .. } => format!("New restaurant {restaurant}"),
+        Event::ReviewAdded {
+            id, dish, reviewer, ..
+        } => {
+            let row = sqlx::query(
+                r#"
+SELECT review.score, reviewer.name
+FROM review LEFT JOIN reviewer ON review.reviewer = reviewer.id
+WHERE review.id = $1"#,
+            )
+            .bind(id)
+            .fetch_optional(db_conn)
+            .await?;
+            let (score, name) = match row {
+                Some(row) => (row.get::<i64, _>("score"), row.get("name")),
+                None => (0, None),
+            };
+            let name: String = name.unwrap_or_else(|| format!("user {reviewer}"));
+            format!(
+                "{name} reviewed {} at {restaurant}: {score} / 5",
+                dish_name(*dish).await?
+            )
+        }
+        Event::DishAdded { id, .. } => {
+            format!("New dish {} at {restaurant}", dish_name(*id).await?)
+        }
+        Event::PhotoAdded { dish, .. } => {
+            format!("New photo of {} at {restaurant}", dish_name(*dish).await?)
+        }
+    };
+    Ok(text)
+}
+
+// id of the row an event is about, for the kinds the watcher polls
+fn polled_row(event: &Event) -> Option<(&'static str, i64)> {
+    match event {
+        Event::RestaurantAdded { id, .. } => Some(("restaurant", *id)),
+        Event::DishAdded { id, .. } => Some(("dish", *id)),
+        Event::ReviewAdded { id, .. } => Some(("review", *id)),
+        Event::PhotoAdded { .. } => None,
+    }
+}
+
+/// Publish the restaurants, dishes and reviews added by other processes sharing the database,
+/// like the bot, by polling the tables for new ids.
+///
+/// The rows this process added are published by their writers after the commit, either before
+/// the poll, then the watcher skips them, or after it, then the writer's event is dropped. New
+/// photos only update a column and are not polled.
+pub struct ChangeWatcher {
+    pool: Pool,
+    bus: &'static Bus,
+    local: broadcast::Receiver<Event>,
+    // the largest restaurant, dish and review id seen
+    last: [i64; 3],
+    // rows published by this process but not polled yet
+    published: HashSet<(&'static str, i64)>,
+}
+
+const POLLED_TABLES: [&str; 3] = ["restaurant", "dish", "review"];
+
+impl ChangeWatcher {
+    /// Start from the current rows
+    pub async fn new(pool: Pool) -> anyhow::Result<Self> {
+        Self::with_bus(pool, bus()).await
+    }
+
+    async fn with_bus(pool: Pool, bus: &'static Bus) -> anyhow::Result<Self> {
+        let local = bus.sender.subscribe();
+        let mut last = [0; 3];
+        for (table, last) in POLLED_TABLES.iter().zip(&mut last) {
+            *last = sqlx::query(&format!("SELECT COALESCE(MAX(id), 0) AS id FROM {table}"))
+                .fetch_one(&pool)
+                .await?
+                .get("id");
+        }
+        Ok(Self {
+            pool,
+            bus,
+            local,
+            last,
+            published: HashSet::new(),
+        })
+    }
+
+    // remember the rows published on the bus since the last call
+    fn receive_local(&mut self) {
+        loop {
+            match self.local.try_recv() {
+                Ok(event) => self.published.extend(polled_row(&event)),
+                Err(TryRecvError::Lagged(missed)) => {
+                    tracing::warn!("change watcher missed {missed} local events");
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Emit the events of the rows added since the last poll, return them
+    pub async fn poll(&mut self) -> anyhow::Result<Vec<Event>> {
+        // the creator is looked up in the audit log, absent for the rows added before it
+        let restaurants = sqlx::query(
+            r#"
+SELECT restaurant.id, audit_log.actor
+FROM restaurant
+LEFT JOIN audit_log ON audit_log.entity = 'restaurant' AND audit_log.entity_id = restaurant.id
+    AND audit_log.action = 'create'
+WHERE restaurant.id > $1
+ORDER BY restaurant.id"#,
+        )
+        .bind(self.last[0])
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Event::RestaurantAdded {
+            id: row.get("id"),
+            actor: row.get("actor"),
+        });
+        let dishes = sqlx::query(
+            r#"
+SELECT dish.id, dish.restaurant, audit_log.actor
+FROM dish
+LEFT JOIN audit_log ON audit_log.entity = 'dish' AND audit_log.entity_id = dish.id
+    AND audit_log.action = 'create'
+WHERE dish.id > $1
+ORDER BY dish.id"#,
+        )
+        .bind(self.last[1])
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Event::DishAdded {
+            id: row.get("id"),
+            restaurant: row.get("restaurant"),
+            actor: row.get("actor"),
+        });
+        let reviews = sqlx::query(
+            r#"
+SELECT review.id, review.dish, review.reviewer, dish.restaurant
+FROM review JOIN dish ON review.dish = dish.id
+WHERE review.id > $1
+ORDER BY review.id"#,
+        )
+        .bind(self.last[2])
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Event::ReviewAdded {
+            id: row.get("id"),
+            dish: row.get("dish"),
+            restaurant: row.get("restaurant"),
+            reviewer: row.get("reviewer"),
+        });
+
+        let polled = restaurants.chain(dishes).chain(reviews).collect::<Vec<_>>();
+        let mut events = Vec::new();
+        // the writers can't emit in between, so each row is published by one of them
+        let mut published = self.bus.polled.lock().unwrap();
+        self.receive_local();
+        for event in polled {
+            let Some(row) = polled_row(&event) else {
+                continue;
+            };
+            let index = POLLED_TABLES.iter().position(|t| *t == row.0).unwrap();
+            self.last[index] = self.last[index].max(row.1);
+            if !self.published.remove(&row) {
+                if published.len() == CAPACITY {
+                    published.pop_front();
+                }
+                published.push_back(row);
+                let _ = self.bus.sender.send(event.clone());
+                events.push(event);
+            }
+        }
+        drop(published);
+        // the rows rolled back or deleted before being polled
+        let last = self.last;
+        self.published.retain(|(table, id)| {
+            let index = POLLED_TABLES.iter().position(|t| t == table).unwrap();
+            *id > last[index]
+        });
+        Ok(events)
+    }
+
+    /// Keep polling the database until the shutdown
+    pub async fn run(mut self, interval: Duration, mut shutdown: crate::ops::Shutdown) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => (),
+                _ = shutdown.wait() => break,
+            }
+            if let Err(e) = self.poll().await {
+                tracing::error!("fail to poll the database changes: {e:#}");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_change_watcher() {
+    use super::*;
+
+    let db = test_pool().await;
+    add_new_user(&db, (1, "alice")).await.unwrap();
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+
+    // a private bus instead of the shared one, so the other tests don't interfere
+    let bus: &'static Bus = Box::leak(Box::new(Bus::new()));
+    let mut receiver = bus.sender.subscribe();
+    let mut watcher = ChangeWatcher::with_bus(db.clone(), bus).await.unwrap();
+    assert!(watcher.poll().await.unwrap().is_empty());
+
+    // added by this process, already on the bus
+    let burger = add_dish(&db, Actor::User(1), kfc, "汉堡", None)
+        .await
+        .unwrap();
+    bus.emit(Event::DishAdded {
+        id: burger,
+        restaurant: kfc,
+        actor: Some(1),
+    });
+    // added by another process
+    let fries = add_dish(&db, Actor::User(1), kfc, "薯条", None)
+        .await
+        .unwrap();
+    let prop = NewReviewPropsBuilder::default()
+        .dish(DishProp::Id(fries))
+        .reviewer(ReviewerProp::Id(1))
+        .details(String::new())
+        .score(4)
+        .build()
+        .unwrap();
+    add_new_review(&db, prop).await.unwrap();
+
+    let events = watcher.poll().await.unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        events[0],
+        Event::DishAdded {
+            id: fries,
+            restaurant: kfc,
+            actor: Some(1),
+        }
+    );
+    assert!(matches!(
+        events[1],
+        Event::ReviewAdded { dish, reviewer: 1, .. } if dish == fries
+    ));
+    assert!(watcher.poll().await.unwrap().is_empty());
+
+    // added by this process, but emitted after the poll
+    let nuggets = add_dish(&db, Actor::User(1), kfc, "鸡块", None)
+        .await
+        .unwrap();
+    let event = Event::DishAdded {
+        id: nuggets,
+        restaurant: kfc,
+        actor: Some(1),
+    };
+    assert_eq!(watcher.poll().await.unwrap(), vec![event.clone()]);
+    bus.emit(event);
+    // every row is on the bus once
+    let mut received = Vec::new();
+    while let Ok(event) = receiver.try_recv() {
+        received.extend(polled_row(&event));
+    }
+    assert_eq!(
+        received,
+        [
+            ("dish", burger),
+            ("dish", fries),
+            ("review", 1),
+            ("dish", nuggets)
+        ]
+    );
+}
@@ -0,0 +1,274 @@
+use super::{record, Actor, AuditAction, EntityKind, Event, Pool, DB};
+use anyhow::Context;
+use sqlx::{Row, Transaction};
+
+/// What a user can follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "lowercase")]
+pub enum FollowTarget {
+    Restaurant(i64),
+    Reviewer(i64),
+}
+
+impl FollowTarget {
+    pub fn new(kind: EntityKind, id: i64) -> anyhow::Result<Self> {
+        match kind {
+            EntityKind::Restaurant => Ok(Self::Restaurant(id)),
+            EntityKind::Reviewer => Ok(Self::Reviewer(id)),
+            _ => anyhow::bail!("only restaurant and reviewer can be followed, not {kind}"),
+        }
+    }
+
+    pub fn kind(self) -> EntityKind {
+        match self {
+            Self::Restaurant(_) => EntityKind::Restaurant,
+            Self::Reviewer(_) => EntityKind::Reviewer,
+        }
+    }
+
+    pub fn id(self) -> i64 {
+        match self {
+            Self::Restaurant(id) | Self::Reviewer(id) => id,
+        }
+    }
+}
+
+impl std::fmt::Display for FollowTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.kind(), self.id())
+    }
+}
+
+/// Follow the target, return false if it was already followed
+pub async fn follow(db_conn: &Pool, subscriber: i64, target: FollowTarget) -> anyhow::Result<bool> {
+    let kind = target.kind();
+    let exist = sqlx::query(&format!("SELECT 1 FROM {} WHERE id=$1", kind.table()))
+        .bind(target.id())
+        .fetch_optional(db_conn)
+        .await?;
+    anyhow::ensure!(exist.is_some(), "{target} not found");
+    anyhow::ensure!(
+        target != FollowTarget::Reviewer(subscriber),
+        "you can not follow yourself"
+    );
+
+    let mut tx = db_conn.begin().await?;
+    let added = sqlx::query(
+        r#"
+INSERT INTO subscription (subscriber, target_kind, target) VALUES ($1, $2, $3)
+ON CONFLICT DO NOTHING"#,
+    )
+    .bind(subscriber)
+    .bind(kind.to_string())
+    .bind(target.id())
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("fail to follow {target}"))?
+    .rows_affected()
+        > 0;
+    if added {
+        let after = serde_json::to_value(target)?;
+        let action = AuditAction::Create;
+        record(
+            &mut tx,
+            Actor::User(subscriber),
+            action,
+            EntityKind::Subscription,
+            subscriber,
+            None,
+            Some(after),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(added)
+}
+
+/// Stop following the target, return false if it wasn't followed
+pub async fn unfollow(
+    db_conn: &Pool,
+    subscriber: i64,
+    target: FollowTarget,
+) -> anyhow::Result<bool> {
+    let mut tx = db_conn.begin().await?;
+    let removed = sqlx::query(
+        "DELETE FROM subscription WHERE subscriber=$1 AND target_kind=$2 AND target=$3",
+    )
+    .bind(subscriber)
+    .bind(target.kind().to_string())
+    .bind(target.id())
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("fail to unfollow {target}"))?
+    .rows_affected()
+        > 0;
+    if removed {
+        let before = serde_json::to_value(target)?;
+        let action = AuditAction::Delete;
+        record(
+            &mut tx,
+            Actor::User(subscriber),
+            action,
+            EntityKind::Subscription,
+            subscriber,
+            Some(before),
+            None,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(removed)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Following {
+    pub target: FollowTarget,
+    /// None when the target is deleted
+    pub name: Option<String>,
+}
+
+/// Everything the user follows, the latest first
+pub async fn followings(db_conn: &Pool, subscriber: i64) -> anyhow::Result<Vec<Following>> {
+    sqlx::query(
+        r#"
+SELECT
+    target_kind,
+    target,
+    CASE target_kind
+        WHEN 'restaurant' THEN (SELECT name FROM restaurant WHERE id = target)
+        WHEN 'reviewer' THEN (SELECT name FROM reviewer WHERE id = target)
+    END AS name
+FROM subscription
+WHERE subscriber = $1
+ORDER BY created_at DESC, rowid DESC"#,
+    )
+    .bind(subscriber)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let kind = row.get::<String, _>("target_kind").parse()?;
+        Ok(Following {
+            target: FollowTarget::new(kind, row.get("target"))?,
+            name: row.get("name"),
+        })
+    })
+    .collect()
+}
+
+/// Users following the restaurant or the author of the event, except the author
+pub async fn subscribers(db_conn: &Pool, event: &Event) -> anyhow::Result<Vec<i64>> {
+    let actor = event.actor();
+    let ids = sqlx::query(
+        r#"
+SELECT DISTINCT subscriber FROM subscription
+WHERE (target_kind = 'restaurant' AND target = $1)
+    OR (target_kind = 'reviewer' AND target = $2)
+ORDER BY subscriber"#,
+    )
+    .bind(event.restaurant())
+    .bind(actor)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| row.get("subscriber"))
+    .filter(|id| Some(*id) != actor)
+    .collect();
+    Ok(ids)
+}
+
+// The followers of a merged restaurant follow the restaurant it is merged into
+pub(super) async fn move_subscriptions(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    from: i64,
+    into: i64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+INSERT INTO subscription (subscriber, target_kind, target, created_at)
+SELECT subscriber, target_kind, $1, created_at FROM subscription WHERE target_kind=$2 AND target=$3
+ON CONFLICT DO NOTHING"#,
+    )
+    .bind(into)
+    .bind(kind.to_string())
+    .bind(from)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM subscription WHERE target_kind=$1 AND target=$2")
+        .bind(kind.to_string())
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_follow() {
+    use super::*;
+
+    let db = test_pool().await;
+    register_reviewer(&db, 1, "alice").await.unwrap();
+    register_reviewer(&db, 2, "bob").await.unwrap();
+    register_reviewer(&db, 3, "carol").await.unwrap();
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    let mcd = add_restaurant(&db, Actor::System, "McDonald", "光谷")
+        .await
+        .unwrap();
+
+    assert!(follow(&db, 1, FollowTarget::Restaurant(kfc)).await.unwrap());
+    assert!(!follow(&db, 1, FollowTarget::Restaurant(kfc)).await.unwrap());
+    assert!(follow(&db, 1, FollowTarget::Reviewer(2)).await.unwrap());
+    assert!(follow(&db, 3, FollowTarget::Restaurant(mcd)).await.unwrap());
+    assert!(follow(&db, 1, FollowTarget::Reviewer(1)).await.is_err());
+    assert!(follow(&db, 1, FollowTarget::Restaurant(100)).await.is_err());
+    assert!(FollowTarget::new(EntityKind::Dish, 1).is_err());
+
+    let list = followings(&db, 1).await.unwrap();
+    assert_eq!(list.len(), 2);
+    assert!(list
+        .iter()
+        .any(|f| f.target == FollowTarget::Reviewer(2) && f.name.as_deref() == Some("bob")));
+
+    // bob reviews at McDonald: alice follows bob, carol follows McDonald
+    let event = Event::ReviewAdded {
+        id: 1,
+        dish: 1,
+        restaurant: mcd,
+        reviewer: 2,
+    };
+    assert_eq!(subscribers(&db, &event).await.unwrap(), vec![1, 3]);
+    // the dish added at KFC is not sent back to its author
+    let event = Event::DishAdded {
+        id: 1,
+        restaurant: kfc,
+        actor: Some(1),
+    };
+    assert!(subscribers(&db, &event).await.unwrap().is_empty());
+
+    merge_restaurant(&db, Actor::System, mcd, kfc)
+        .await
+        .unwrap();
+    let list = followings(&db, 3).await.unwrap();
+    assert_eq!(list[0].target, FollowTarget::Restaurant(kfc));
+
+    assert!(unfollow(&db, 1, FollowTarget::Reviewer(2)).await.unwrap());
+    assert!(!unfollow(&db, 1, FollowTarget::Reviewer(2)).await.unwrap());
+
+    // the changes of alice, not the ones that changed nothing
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Subscription)
+        .entity_id(1)
+        .build()
+        .unwrap();
+    let log = get_audit_log(&db, query).await.unwrap();
+    assert_eq!(log.len(), 3);
+    assert_eq!(log[0].action, AuditAction::Delete);
+    assert_eq!(
+        log[0].before,
+        Some(serde_json::json!({ "kind": "reviewer", "id": 2 }))
+    );
+    assert!(revert_audit(&db, Actor::System, log[0].id).await.is_err());
+}
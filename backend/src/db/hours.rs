@@ -0,0 +1,686 @@
+use super::{record, record_create, snapshot, Actor, AuditAction, EntityKind, Pool};
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use sqlx::{any::AnyRow, Row};
+use std::collections::HashMap;
+use utoipa::openapi::schema::{ArrayBuilder, ObjectBuilder, Schema, SchemaType};
+use utoipa::openapi::RefOr;
+
+/// Offset of the local time for telling whether a restaurant is open, in seconds. Restaurants are
+/// in China Standard Time.
+pub const DEFAULT_UTC_OFFSET: i64 = 8 * 3600;
+
+// minutes of a day
+const DAY: u16 = 24 * 60;
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Current local time by [`DEFAULT_UTC_OFFSET`]
+pub fn local_now() -> NaiveDateTime {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    NaiveDateTime::from_timestamp_opt(secs + DEFAULT_UTC_OFFSET, 0)
+        .expect("current time out of range")
+}
+
+/// Opening time range in minutes since midnight. It closes after midnight when `close` is greater
+/// than a day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub open: u16,
+    pub close: u16,
+}
+
+impl TimeRange {
+    const ALL_DAY: Self = Self {
+        open: 0,
+        close: DAY,
+    };
+}
+
+// parse time like 9, 9:30 or 21:00
+fn parse_time(s: &str) -> Option<u16> {
+    let (hour, minute) = s.split_once(':').unwrap_or((s, "0"));
+    let (hour, minute) = (hour.parse::<u16>().ok()?, minute.parse::<u16>().ok()?);
+    (minute < 60 && hour * 60 + minute <= DAY).then_some(hour * 60 + minute)
+}
+
+impl std::str::FromStr for TimeRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let range = s.split_once('-').and_then(|(open, close)| {
+            let (open, close) = (parse_time(open)?, parse_time(close)?);
+            (open < DAY).then_some((open, close))
+        });
+        let Some((open, close)) = range else {
+            anyhow::bail!("can not understand {s}, expect hours like 10:00-22:00");
+        };
+        let close = if close <= open { close + DAY } else { close };
+        Ok(Self { open, close })
+    }
+}
+
+impl std::fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let time = |m: u16| {
+            let m = if m > DAY { m - DAY } else { m };
+            format!("{:02}:{:02}", m / 60, m % 60)
+        };
+        write!(f, "{}-{}", time(self.open), time(self.close))
+    }
+}
+
+impl serde::Serialize for TimeRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Rewrite the Chinese and full width words into the English format, so the parser only handles one
+const ALIASES: &[(&str, &str)] = &[
+    ("星期一", " mon "),
+    ("星期二", " tue "),
+    ("星期三", " wed "),
+    ("星期四", " thu "),
+    ("星期五", " fri "),
+    ("星期六", " sat "),
+    ("星期日", " sun "),
+    ("星期天", " sun "),
+    ("周一", " mon "),
+    ("周二", " tue "),
+    ("周三", " wed "),
+    ("周四", " thu "),
+    ("周五", " fri "),
+    ("周六", " sat "),
+    ("周日", " sun "),
+    ("周天", " sun "),
+    ("工作日", " weekdays "),
+    ("周末", " weekends "),
+    ("每天", " daily "),
+    ("每日", " daily "),
+    ("24小时", " 24h "),
+    ("全天", " 24h "),
+    ("不营业", " closed "),
+    ("休息", " closed "),
+    ("店休", " closed "),
+    ("闭店", " closed "),
+    ("：", ":"),
+    ("，", ","),
+    ("；", ";"),
+    ("、", ","),
+    ("至", "-"),
+    ("到", "-"),
+    ("～", "-"),
+    ("~", "-"),
+    ("–", "-"),
+    ("—", "-"),
+];
+
+fn normalize(text: &str) -> String {
+    let mut text = text.to_lowercase();
+    for (from, to) in ALIASES {
+        text = text.replace(from, to);
+    }
+    // "10:00 - 22:00" is one word
+    while text.contains(" -") || text.contains("- ") {
+        text = text.replace(" -", "-").replace("- ", "-");
+    }
+    text
+}
+
+fn parse_weekday(word: &str) -> Option<usize> {
+    (word.len() >= 3)
+        .then(|| WEEKDAYS.iter().position(|d| d.starts_with(word)))
+        .flatten()
+}
+
+// parse day words like "mon", "fri-sun" or "daily"
+fn parse_days(word: &str) -> Option<Vec<usize>> {
+    match word {
+        "daily" | "everyday" => return Some((0..7).collect()),
+        "weekdays" | "weekday" => return Some((0..5).collect()),
+        "weekends" | "weekend" => return Some(vec![5, 6]),
+        _ => (),
+    }
+    match word.split_once('-') {
+        // the range may go across the weekend, like fri-mon
+        Some((start, end)) => {
+            let (start, end) = (parse_weekday(start)?, parse_weekday(end)?);
+            let len = (end + 7 - start) % 7 + 1;
+            Some((start..start + len).map(|d| d % 7).collect())
+        }
+        None => Some(vec![parse_weekday(word)?]),
+    }
+}
+
+// parse the hours words of a clause, where "closed" gives no range
+fn parse_range_word(word: &str, ranges: &mut Vec<TimeRange>) -> anyhow::Result<()> {
+    match word {
+        "closed" | "close" | "off" => (),
+        "24h" | "24/7" => ranges.push(TimeRange::ALL_DAY),
+        word => ranges.push(word.parse()?),
+    }
+    Ok(())
+}
+
+/// Parse time ranges like "10:00-14:00 17:00-20:00". "closed" gives an empty list.
+pub fn parse_ranges(text: &str) -> anyhow::Result<Vec<TimeRange>> {
+    let text = normalize(text);
+    let mut ranges = Vec::new();
+    for word in text.split(|c: char| c == ',' || c.is_whitespace()) {
+        if !word.is_empty() {
+            parse_range_word(word, &mut ranges)?;
+        }
+    }
+    ranges.sort_by_key(|r| r.open);
+    Ok(ranges)
+}
+
+fn ranges_text(ranges: &[TimeRange]) -> String {
+    match ranges {
+        [] => "closed".to_string(),
+        [TimeRange::ALL_DAY] => "24h".to_string(),
+        ranges => ranges
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Opening hours of each weekday, Monday first.
+///
+/// It is parsed from text like "10:00-22:00, Mon closed" or "周一至周五 9:00-21:00；周末休息". A
+/// clause with days replaces the hours of these days, and a clause without days adds more ranges to
+/// the days of the previous clause, or to every day if it is the first one. The [`Display`] output
+/// is the format stored in the database, which is parsed back to the same hours.
+///
+/// [`Display`]: std::fmt::Display
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeeklyHours {
+    days: [Vec<TimeRange>; 7],
+}
+
+impl WeeklyHours {
+    pub fn on(&self, weekday: chrono::Weekday) -> &[TimeRange] {
+        &self.days[weekday.num_days_from_monday() as usize]
+    }
+}
+
+impl std::str::FromStr for WeeklyHours {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = normalize(text);
+        let mut days: [Option<Vec<TimeRange>>; 7] = Default::default();
+        // days of the last clause with hours
+        let mut last: Vec<usize> = Vec::new();
+        // days waiting for their hours, like the "sat" in "sat, sun closed"
+        let mut pending: Vec<usize> = Vec::new();
+
+        for clause in text.split([',', ';', '\n']) {
+            let mut clause_days = std::mem::take(&mut pending);
+            let mut hours: Option<Vec<TimeRange>> = None;
+            for word in clause.split_whitespace() {
+                match parse_days(word) {
+                    Some(d) => clause_days.extend(d),
+                    None => parse_range_word(word, hours.get_or_insert_with(Vec::new))?,
+                }
+            }
+            let Some(hours) = hours else {
+                pending = clause_days;
+                continue;
+            };
+
+            if clause_days.is_empty() && !last.is_empty() {
+                for d in &last {
+                    days[*d].get_or_insert_with(Vec::new).extend(&hours);
+                }
+                continue;
+            }
+            if clause_days.is_empty() {
+                clause_days = (0..7).collect();
+            }
+            for d in &clause_days {
+                days[*d] = Some(hours.clone());
+            }
+            last = clause_days;
+        }
+        if let Some(d) = pending.first() {
+            anyhow::bail!("missing the hours of {}", WEEKDAYS[*d]);
+        }
+        anyhow::ensure!(!last.is_empty(), "no opening hours found in {text}");
+
+        // the days not mentioned are closed
+        Ok(Self {
+            days: days.map(|ranges| {
+                let mut ranges = ranges.unwrap_or_default();
+                ranges.sort_by_key(|r| r.open);
+                ranges
+            }),
+        })
+    }
+}
+
+impl std::fmt::Display for WeeklyHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let texts = self.days.iter().map(|r| ranges_text(r)).collect::<Vec<_>>();
+        if texts.iter().all(|t| *t == texts[0]) {
+            return write!(f, "daily {}", texts[0]);
+        }
+
+        let day = |d: usize| {
+            let name = &WEEKDAYS[d][..3];
+            name[..1].to_uppercase() + &name[1..]
+        };
+        let mut start = 0;
+        let mut groups = Vec::new();
+        for end in 0..7 {
+            if end == 6 || texts[end + 1] != texts[start] {
+                let days = if start == end {
+                    day(start)
+                } else {
+                    format!("{}-{}", day(start), day(end))
+                };
+                groups.push(format!("{days} {}", texts[start]));
+                start = end + 1;
+            }
+        }
+        write!(f, "{}", groups.join("; "))
+    }
+}
+
+impl serde::Serialize for WeeklyHours {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(7))?;
+        for (day, ranges) in WEEKDAYS.iter().zip(&self.days) {
+            map.serialize_entry(day, ranges)?;
+        }
+        map.end()
+    }
+}
+
+// the map of the weekdays, as serialized above
+impl<'s> utoipa::ToSchema<'s> for WeeklyHours {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let ranges = ArrayBuilder::new()
+            .items(
+                ObjectBuilder::new()
+                    .schema_type(SchemaType::String)
+                    .example(Some("10:00-14:00".into())),
+            )
+            .build();
+        let mut object = ObjectBuilder::new().description(Some(
+            "Time ranges of each weekday, empty for closed all day",
+        ));
+        for day in WEEKDAYS {
+            object = object.property(day, ranges.clone()).required(day);
+        }
+        ("WeeklyHours", object.into())
+    }
+}
+
+/// Days that don't follow the weekly hours, like holidays and temporary closure
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct HoursException {
+    pub id: i64,
+    pub restaurant: i64,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Empty for closed all day
+    #[schema(value_type = Vec<String>, example = json!(["10:00-14:00"]))]
+    pub hours: Vec<TimeRange>,
+    pub note: Option<String>,
+}
+
+impl TryFrom<AnyRow> for HoursException {
+    type Error = anyhow::Error;
+
+    fn try_from(row: AnyRow) -> Result<Self, Self::Error> {
+        let date = |col: &str| -> anyhow::Result<NaiveDate> {
+            let date: String = row.get(col);
+            date.parse()
+                .with_context(|| format!("invalid {col} {date} in hours exception"))
+        };
+        let hours: Option<String> = row.get("hours");
+        Ok(Self {
+            id: row.get("id"),
+            restaurant: row.get("restaurant"),
+            start_date: date("start_date")?,
+            end_date: date("end_date")?,
+            hours: hours
+                .as_deref()
+                .map(parse_ranges)
+                .transpose()?
+                .unwrap_or_default(),
+            note: row.get("note"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenStatus {
+    Open,
+    Closed,
+    /// The opening hours are not given, or not in a format we understand
+    Unknown,
+}
+
+impl std::fmt::Display for OpenStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "open now"),
+            Self::Closed => write!(f, "closed now"),
+            Self::Unknown => write!(f, "hours unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, utoipa::ToSchema)]
+pub struct OpeningHours {
+    pub weekly: Option<WeeklyHours>,
+    pub exceptions: Vec<HoursException>,
+}
+
+impl OpeningHours {
+    fn ranges_on(&self, date: NaiveDate) -> Option<&[TimeRange]> {
+        // the latest added exception wins
+        let exception = self
+            .exceptions
+            .iter()
+            .rev()
+            .find(|e| e.start_date <= date && date <= e.end_date);
+        match exception {
+            Some(e) => Some(&e.hours),
+            None => self.weekly.as_ref().map(|w| w.on(date.weekday())),
+        }
+    }
+
+    pub fn status_at(&self, at: NaiveDateTime) -> OpenStatus {
+        let minute = (at.hour() * 60 + at.minute()) as u16;
+        let Some(today) = self.ranges_on(at.date()) else {
+            return OpenStatus::Unknown;
+        };
+        // still open from the night before
+        let yesterday = at
+            .date()
+            .pred_opt()
+            .and_then(|d| self.ranges_on(d))
+            .unwrap_or_default();
+        let open = today.iter().any(|r| r.open <= minute && minute < r.close)
+            || yesterday.iter().any(|r| minute + DAY < r.close);
+        if open {
+            OpenStatus::Open
+        } else {
+            OpenStatus::Closed
+        }
+    }
+}
+
+/// Parse the opening hours into the format stored in the database
+pub(super) fn normalize_hours(text: &str) -> anyhow::Result<String> {
+    Ok(text.parse::<WeeklyHours>()?.to_string())
+}
+
+// exceptions that are not over before the day
+async fn exceptions_since(
+    db_conn: &Pool,
+    restaurant: Option<i64>,
+    since: NaiveDate,
+) -> anyhow::Result<Vec<HoursException>> {
+    let rows = match restaurant {
+        Some(id) => sqlx::query(
+            "SELECT * FROM hours_exception WHERE restaurant=$1 AND end_date>=$2 ORDER BY id",
+        )
+        .bind(id),
+        None => sqlx::query("SELECT * FROM hours_exception WHERE end_date>=$1 ORDER BY id"),
+    }
+    .bind(since.to_string())
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to read hours exceptions")?;
+    rows.into_iter().map(HoursException::try_from).collect()
+}
+
+/// Weekly hours and the current and upcoming exceptions of the restaurant
+pub async fn opening_hours(db_conn: &Pool, restaurant: i64) -> anyhow::Result<OpeningHours> {
+    let text: Option<String> = sqlx::query("SELECT opening_hours FROM restaurant WHERE id=$1")
+        .bind(restaurant)
+        .fetch_optional(db_conn)
+        .await?
+        .with_context(|| format!("restaurant {restaurant} not found"))?
+        .get("opening_hours");
+    let yesterday = local_now().date().pred_opt().unwrap_or(NaiveDate::MIN);
+    Ok(OpeningHours {
+        // hours written before the parser existed may not be understood
+        weekly: text.and_then(|t| t.parse().ok()),
+        exceptions: exceptions_since(db_conn, Some(restaurant), yesterday).await?,
+    })
+}
+
+/// Opening hours of every restaurant, for telling which of them are open in a listing
+pub async fn all_opening_hours(db_conn: &Pool) -> anyhow::Result<HashMap<i64, OpeningHours>> {
+    let mut all = sqlx::query("SELECT id, opening_hours FROM restaurant")
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let text: Option<String> = row.get("opening_hours");
+            let hours = OpeningHours {
+                weekly: text.and_then(|t| t.parse().ok()),
+                exceptions: Vec::new(),
+            };
+            (row.get("id"), hours)
+        })
+        .collect::<HashMap<i64, _>>();
+
+    let yesterday = local_now().date().pred_opt().unwrap_or(NaiveDate::MIN);
+    for exception in exceptions_since(db_conn, None, yesterday).await? {
+        if let Some(hours) = all.get_mut(&exception.restaurant) {
+            hours.exceptions.push(exception);
+        }
+    }
+    Ok(all)
+}
+
+/// Replace the weekly hours of the restaurant, or remove them with None. The parsed hours are
+/// returned.
+pub async fn set_opening_hours(
+    db_conn: &Pool,
+    actor: Actor,
+    restaurant: i64,
+    text: Option<&str>,
+) -> anyhow::Result<Option<WeeklyHours>> {
+    let hours = text.map(str::parse::<WeeklyHours>).transpose()?;
+
+    let kind = EntityKind::Restaurant;
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, kind, restaurant)
+        .await?
+        .with_context(|| format!("restaurant {restaurant} not found"))?;
+    sqlx::query("UPDATE restaurant SET opening_hours=$1 WHERE id=$2")
+        .bind(hours.as_ref().map(ToString::to_string))
+        .bind(restaurant)
+        .execute(&mut tx)
+        .await?;
+    let after = snapshot(&mut tx, kind, restaurant).await?;
+    if after.as_ref() != Some(&before) {
+        let action = AuditAction::Update;
+        record(
+            &mut tx,
+            actor,
+            action,
+            kind,
+            restaurant,
+            Some(before),
+            after,
+        )
+        .await?;
+    }
+    tx.commit()
+        .await
+        .with_context(|| format!("fail to update the hours of restaurant {restaurant}"))?;
+    Ok(hours)
+}
+
+/// Add an exception from `start` to `end` inclusively, `hours` like "10:00-14:00" or None for
+/// closed all day
+pub async fn add_hours_exception(
+    db_conn: &Pool,
+    actor: Actor,
+    restaurant: i64,
+    (start, end): (NaiveDate, NaiveDate),
+    hours: Option<&str>,
+    note: Option<&str>,
+) -> anyhow::Result<i64> {
+    anyhow::ensure!(start <= end, "{start} is after {end}");
+    let hours = hours.map(parse_ranges).transpose()?.unwrap_or_default();
+    let hours = (!hours.is_empty()).then(|| ranges_text(&hours));
+
+    let mut tx = db_conn.begin().await?;
+    let id = sqlx::query(
+        r#"
+INSERT INTO hours_exception
+    (restaurant, start_date, end_date, hours, note)
+VALUES
+    ($1, $2, $3, $4, $5)
+RETURNING id"#,
+    )
+    .bind(restaurant)
+    .bind(start.to_string())
+    .bind(end.to_string())
+    .bind(hours)
+    .bind(note)
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("fail to add hours exception to restaurant {restaurant}"))?
+    .get("id");
+    record_create(&mut tx, actor, EntityKind::HoursException, id).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn remove_hours_exception(
+    db_conn: &Pool,
+    actor: Actor,
+    restaurant: i64,
+    id: i64,
+) -> anyhow::Result<()> {
+    let kind = EntityKind::HoursException;
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, kind, id)
+        .await?
+        .filter(|row| row["restaurant"] == restaurant)
+        .with_context(|| format!("hours exception {id} of restaurant {restaurant} not found"))?;
+    sqlx::query("DELETE FROM hours_exception WHERE id=$1")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    record(
+        &mut tx,
+        actor,
+        AuditAction::Delete,
+        kind,
+        id,
+        Some(before),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_opening_hours() {
+    use super::*;
+
+    let weekly: WeeklyHours = "10:00-14:00, 17:00-22:00; Mon closed".parse().unwrap();
+    assert_eq!(
+        weekly.to_string(),
+        "Mon closed; Tue-Sun 10:00-14:00 17:00-22:00"
+    );
+    assert_eq!(weekly.to_string().parse::<WeeklyHours>().unwrap(), weekly);
+    let weekly: WeeklyHours = "周一至周五 9:00-2:00，周末休息".parse().unwrap();
+    assert_eq!(weekly.to_string(), "Mon-Fri 09:00-02:00; Sat-Sun closed");
+    assert_eq!(
+        "Sat, Sun 24h; Fri-Mon 8-20"
+            .parse::<WeeklyHours>()
+            .unwrap()
+            .to_string(),
+        "Mon 08:00-20:00; Tue-Thu closed; Fri-Sun 08:00-20:00"
+    );
+    assert_eq!(
+        "24小时".parse::<WeeklyHours>().unwrap().to_string(),
+        "daily 24h"
+    );
+    assert!("Mon".parse::<WeeklyHours>().is_err());
+    assert!("10:00-25:00".parse::<WeeklyHours>().is_err());
+    assert!("happy hour".parse::<WeeklyHours>().is_err());
+
+    let db = test_pool().await;
+    let actor = Actor::System;
+    let props = NewRestaurantPropsBuilder::default()
+        .name("夜宵")
+        .address("光谷")
+        .opening_hours("Mon-Fri 18:00-02:00")
+        .build()
+        .unwrap();
+    let rid = add_restaurant_detail(&db, actor, props).await.unwrap();
+
+    // 2022-12-30 is Friday
+    let at = |date: &str, time: &str| {
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M").unwrap()
+    };
+    let date = |d: &str| d.parse::<NaiveDate>().unwrap();
+    let mut hours = opening_hours(&db, rid).await.unwrap();
+    assert_eq!(
+        hours.status_at(at("2022-12-30", "17:00")),
+        OpenStatus::Closed
+    );
+    assert_eq!(hours.status_at(at("2022-12-30", "23:00")), OpenStatus::Open);
+    // friday night goes into saturday
+    assert_eq!(hours.status_at(at("2022-12-31", "01:00")), OpenStatus::Open);
+    assert_eq!(
+        hours.status_at(at("2022-12-31", "03:00")),
+        OpenStatus::Closed
+    );
+
+    let range = (date("2023-01-21"), date("2023-01-27"));
+    let id = add_hours_exception(&db, actor, rid, range, None, Some("春节"))
+        .await
+        .unwrap();
+    hours.exceptions = exceptions_since(&db, Some(rid), date("2023-01-01"))
+        .await
+        .unwrap();
+    assert_eq!(
+        hours.status_at(at("2023-01-23", "20:00")),
+        OpenStatus::Closed
+    );
+    assert_eq!(hours.status_at(at("2023-01-30", "20:00")), OpenStatus::Open);
+    assert!(remove_hours_exception(&db, actor, rid + 1, id)
+        .await
+        .is_err());
+    remove_hours_exception(&db, actor, rid, id).await.unwrap();
+
+    set_opening_hours(&db, actor, rid, None).await.unwrap();
+    let hours = opening_hours(&db, rid).await.unwrap();
+    assert_eq!(
+        hours.status_at(at("2022-12-30", "23:00")),
+        OpenStatus::Unknown
+    );
+    assert!(set_opening_hours(&db, actor, rid, Some("soon"))
+        .await
+        .is_err());
+}
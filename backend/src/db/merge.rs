@@ -0,0 +1,336 @@
+use super::{
+    copy_tags, move_subscriptions, record, snapshot, Actor, AuditAction, EntityKind, Pool, DB,
+};
+use anyhow::Context;
+use sqlx::{Row, Transaction};
+use std::collections::BTreeMap;
+
+/// Normalize a name or address for duplication comparing. Full width ASCII are converted to half
+/// width, letters are lowercased, whitespace and punctuation are removed.
+pub fn normalize_name(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            c => c,
+        })
+        .filter(|c| {
+            !(c.is_whitespace()
+                || c.is_ascii_punctuation()
+                || "·、。，（）【】《》「」『』～".contains(*c))
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A group of rows that are possibly the same thing
+#[derive(Debug, serde::Serialize)]
+pub struct DuplicateCandidates {
+    pub kind: EntityKind,
+    pub reason: String,
+    /// id and name of the rows
+    pub items: Vec<(i64, String)>,
+}
+
+/// Report restaurants with the same normalized name or address, and dishes with the same
+/// normalized name in one restaurant.
+pub async fn find_duplicates(db_conn: &Pool) -> anyhow::Result<Vec<DuplicateCandidates>> {
+    let mut report = Vec::new();
+
+    let restaurants = sqlx::query("SELECT id, name, address FROM restaurant")
+        .fetch_all(db_conn)
+        .await
+        .with_context(|| "fail to list restaurants")?;
+    let mut by_name: BTreeMap<String, Vec<(i64, String)>> = BTreeMap::new();
+    let mut by_addr: BTreeMap<String, Vec<(i64, String)>> = BTreeMap::new();
+    for row in &restaurants {
+        let id: i64 = row.get("id");
+        let name: String = row.get("name");
+        let addr: Option<String> = row.get("address");
+        by_name
+            .entry(normalize_name(&name))
+            .or_default()
+            .push((id, name.clone()));
+        if let Some(addr) = addr.map(|a| normalize_name(&a)).filter(|a| !a.is_empty()) {
+            by_addr.entry(addr).or_default().push((id, name));
+        }
+    }
+    for (reason, groups) in [("same name", by_name), ("same address", by_addr)] {
+        report.extend(groups.into_iter().filter(|(_, items)| items.len() > 1).map(
+            |(key, items)| DuplicateCandidates {
+                kind: EntityKind::Restaurant,
+                reason: format!("{reason}: {key}"),
+                items,
+            },
+        ));
+    }
+
+    let dishes = sqlx::query("SELECT id, restaurant, name FROM dish")
+        .fetch_all(db_conn)
+        .await
+        .with_context(|| "fail to list dishes")?;
+    let mut by_name: BTreeMap<(i64, String), Vec<(i64, String)>> = BTreeMap::new();
+    for row in dishes {
+        let name: String = row.get("name");
+        by_name
+            .entry((row.get("restaurant"), normalize_name(&name)))
+            .or_default()
+            .push((row.get("id"), name));
+    }
+    report.extend(
+        by_name
+            .into_iter()
+            .filter(|(_, items)| items.len() > 1)
+            .map(|((rid, key), items)| DuplicateCandidates {
+                kind: EntityKind::Dish,
+                reason: format!("same name in restaurant {rid}: {key}"),
+                items,
+            }),
+    );
+
+    Ok(report)
+}
+
+/// Statistic of a merge operation
+#[derive(Debug, Default, serde::Serialize)]
+pub struct MergeSummary {
+    pub dishes_moved: u64,
+    pub dishes_merged: u64,
+    pub reviews_moved: u64,
+}
+
+async fn ensure_exist(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<serde_json::Value> {
+    snapshot(tx, kind, id)
+        .await?
+        .with_context(|| format!("{kind} {id} not found"))
+}
+
+// Run an update of a single row and write it into audit log
+async fn audited_update(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+    sql: &str,
+    value: i64,
+) -> anyhow::Result<()> {
+    let before = snapshot(tx, kind, id).await?;
+    sqlx::query(sql)
+        .bind(value)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    let after = snapshot(tx, kind, id).await?;
+    record(tx, actor, AuditAction::Update, kind, id, before, after).await?;
+    Ok(())
+}
+
+// The merged row is deleted, record it with the row it is merged into
+async fn audited_merge_delete(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    kind: EntityKind,
+    from: i64,
+    into: i64,
+) -> anyhow::Result<()> {
+    let before = snapshot(tx, kind, from).await?;
+    sqlx::query(&format!("DELETE FROM {} WHERE id=$1", kind.table()))
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    let after = serde_json::json!({ "merged_into": into });
+    record(
+        tx,
+        actor,
+        AuditAction::Merge,
+        kind,
+        from,
+        before,
+        Some(after),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn merge_dish_in(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    from: i64,
+    into: i64,
+    summary: &mut MergeSummary,
+) -> anyhow::Result<()> {
+    let reviews = sqlx::query("SELECT id FROM review WHERE dish=$1")
+        .bind(from)
+        .fetch_all(&mut *tx)
+        .await?;
+    for row in reviews {
+        let id = row.get("id");
+        let sql = "UPDATE review SET dish=$1 WHERE id=$2";
+        audited_update(tx, actor, EntityKind::Review, id, sql, into).await?;
+        summary.reviews_moved += 1;
+    }
+    // keep the photo if the target dish doesn't have one
+    let image: Option<String> = sqlx::query("SELECT image FROM dish WHERE id=$1")
+        .bind(into)
+        .fetch_one(&mut *tx)
+        .await?
+        .get("image");
+    if image.is_none() {
+        let sql = "UPDATE dish SET image=(SELECT image FROM dish WHERE id=$1) WHERE id=$2";
+        audited_update(tx, actor, EntityKind::Dish, into, sql, from).await?;
+    }
+    copy_tags(tx, EntityKind::Dish, from, into).await?;
+    audited_merge_delete(tx, actor, EntityKind::Dish, from, into).await?;
+    summary.dishes_merged += 1;
+    Ok(())
+}
+
+/// Merge dish `from` into dish `into`. Reviews and photo are moved, then `from` is deleted.
+pub async fn merge_dish(
+    db_conn: &Pool,
+    actor: Actor,
+    from: i64,
+    into: i64,
+) -> anyhow::Result<MergeSummary> {
+    anyhow::ensure!(from != into, "can not merge dish {from} into itself");
+
+    let mut tx = db_conn.begin().await?;
+    ensure_exist(&mut tx, EntityKind::Dish, from).await?;
+    ensure_exist(&mut tx, EntityKind::Dish, into).await?;
+
+    let mut summary = MergeSummary::default();
+    merge_dish_in(&mut tx, actor, from, into, &mut summary).await?;
+    tx.commit()
+        .await
+        .with_context(|| format!("fail to merge dish {from} into {into}"))?;
+
+    Ok(summary)
+}
+
+/// Merge restaurant `from` into restaurant `into`. Dishes are moved with their reviews and photos,
+/// dishes with the same normalized name are merged, visits, tags and followers are moved, then
+/// `from` is deleted.
+pub async fn merge_restaurant(
+    db_conn: &Pool,
+    actor: Actor,
+    from: i64,
+    into: i64,
+) -> anyhow::Result<MergeSummary> {
+    anyhow::ensure!(from != into, "can not merge restaurant {from} into itself");
+
+    let mut tx = db_conn.begin().await?;
+    ensure_exist(&mut tx, EntityKind::Restaurant, from).await?;
+    ensure_exist(&mut tx, EntityKind::Restaurant, into).await?;
+
+    let mut summary = MergeSummary::default();
+    let existing = sqlx::query("SELECT id, name FROM dish WHERE restaurant=$1")
+        .bind(into)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (normalize_name(row.get("name")), row.get::<i64, _>("id")))
+        .collect::<BTreeMap<_, _>>();
+    let moving = sqlx::query("SELECT id, name FROM dish WHERE restaurant=$1")
+        .bind(from)
+        .fetch_all(&mut *tx)
+        .await?;
+    for row in moving {
+        let id: i64 = row.get("id");
+        let name: String = row.get("name");
+        if let Some(&same) = existing.get(&normalize_name(&name)) {
+            merge_dish_in(&mut tx, actor, id, same, &mut summary).await?;
+        } else {
+            let sql = "UPDATE dish SET restaurant=$1 WHERE id=$2";
+            audited_update(&mut tx, actor, EntityKind::Dish, id, sql, into).await?;
+            summary.dishes_moved += 1;
+        }
+    }
+
+    let visits = sqlx::query("SELECT id FROM visit WHERE restaurant=$1")
+        .bind(from)
+        .fetch_all(&mut *tx)
+        .await?;
+    for row in visits {
+        let sql = "UPDATE visit SET restaurant=$1 WHERE id=$2";
+        audited_update(&mut tx, actor, EntityKind::Visit, row.get("id"), sql, into).await?;
+    }
+    copy_tags(&mut tx, EntityKind::Restaurant, from, into).await?;
+    move_subscriptions(&mut tx, EntityKind::Restaurant, from, into).await?;
+    audited_merge_delete(&mut tx, actor, EntityKind::Restaurant, from, into).await?;
+    tx.commit()
+        .await
+        .with_context(|| format!("fail to merge restaurant {from} into {into}"))?;
+
+    Ok(summary)
+}
+
+#[test]
+fn test_normalize_name() {
+    assert_eq!(normalize_name(" KFC "), "kfc");
+    assert_eq!(normalize_name("ＫＦＣ（光谷店）"), "kfc光谷店");
+    assert_eq!(normalize_name("Mc-Donald's"), "mcdonalds");
+}
+
+#[tokio::test]
+async fn test_merge_restaurant() {
+    use super::*;
+
+    let db = test_pool().await;
+    add_new_user(&db, (1, "Admin")).await.unwrap();
+    let actor = Actor::System;
+    let kfc = add_restaurant(&db, actor, "KFC", "WuHan").await.unwrap();
+    let dup = add_restaurant(&db, actor, "ｋｆｃ", "Wuhan").await.unwrap();
+    let chicken = add_dish(&db, actor, kfc, "吮指原味鸡", None).await.unwrap();
+    let dup_chicken = add_dish(&db, actor, dup, "吮指原味鸡 ", Some("photo".to_string()))
+        .await
+        .unwrap();
+    let burger = add_dish(&db, actor, dup, "香辣鸡腿堡", None).await.unwrap();
+    for dish in [dup_chicken, burger] {
+        let prop = NewReviewPropsBuilder::default()
+            .dish(DishProp::Id(dish))
+            .reviewer(ReviewerProp::Id(1))
+            .details("好恰".to_string())
+            .score(4)
+            .build()
+            .unwrap();
+        add_new_review(&db, prop).await.unwrap();
+    }
+
+    let report = find_duplicates(&db).await.unwrap();
+    assert_eq!(report.len(), 2);
+    assert!(report
+        .iter()
+        .all(|c| c.kind == EntityKind::Restaurant && c.items.len() == 2));
+
+    let summary = merge_restaurant(&db, actor, dup, kfc).await.unwrap();
+    assert_eq!(summary.dishes_moved, 1);
+    assert_eq!(summary.dishes_merged, 1);
+    assert_eq!(summary.reviews_moved, 1);
+
+    assert!(get_restaurant(&db, RestaurantSearchProps::Id(dup))
+        .await
+        .unwrap()
+        .is_empty());
+    let dishes = get_dish(&db, kfc, None).await.unwrap();
+    assert_eq!(dishes.len(), 2);
+    let merged = dishes.iter().find(|d| d.id == chicken).unwrap();
+    assert_eq!(merged.image.as_deref(), Some("photo"));
+    let review = get_review(
+        &db,
+        GetReviewPropsBuilder::default()
+            .dish_id(chicken)
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(review.score, 4);
+
+    assert!(find_duplicates(&db).await.unwrap().is_empty());
+    assert!(merge_restaurant(&db, actor, dup, kfc).await.is_err());
+    assert!(merge_dish(&db, actor, chicken, chicken).await.is_err());
+}
@@ -0,0 +1,274 @@
+use super::{
+    dish_rating, record, snapshot, Actor, AuditAction, EntityKind, Pool, RatingDimension, DB,
+};
+use anyhow::Context;
+use sqlx::{Row, Transaction};
+
+/// Currency of the prices reported without one
+pub const DEFAULT_CURRENCY: &str = "CNY";
+
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct PricePoint {
+    pub amount: f64,
+    pub currency: String,
+    pub observed_at: String,
+    pub reporter: Option<i64>,
+}
+
+/// Check the amount before writing anything, free dishes cost 0
+pub fn check_price(amount: f64) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        amount.is_finite() && amount >= 0.0,
+        "price should be a number not less than 0"
+    );
+    Ok(())
+}
+
+/// Save a reported price into the history and make it the current price of the dish
+pub(super) async fn record_price(
+    tx: &mut Transaction<'_, DB>,
+    actor: Actor,
+    dish: i64,
+    amount: f64,
+    currency: Option<&str>,
+) -> anyhow::Result<()> {
+    check_price(amount)?;
+    let currency = currency
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+
+    sqlx::query(
+        "INSERT INTO dish_price (dish, amount, currency, reporter) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(dish)
+    .bind(amount)
+    .bind(&currency)
+    .bind(actor.db_id())
+    .execute(&mut *tx)
+    .await
+    .with_context(|| format!("fail to save the price of dish {dish}"))?;
+
+    let before = snapshot(tx, EntityKind::Dish, dish)
+        .await?
+        .with_context(|| format!("dish {dish} not found"))?;
+    sqlx::query("UPDATE dish SET price=$1, currency=$2 WHERE id=$3")
+        .bind(amount)
+        .bind(&currency)
+        .bind(dish)
+        .execute(&mut *tx)
+        .await?;
+    let after = snapshot(tx, EntityKind::Dish, dish).await?;
+    if after.as_ref() != Some(&before) {
+        let action = AuditAction::Update;
+        record(
+            tx,
+            actor,
+            action,
+            EntityKind::Dish,
+            dish,
+            Some(before),
+            after,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn report_price(
+    db_conn: &Pool,
+    actor: Actor,
+    dish: i64,
+    amount: f64,
+    currency: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+    record_price(&mut tx, actor, dish, amount, currency).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Prices of the dish from the newest to the oldest
+pub async fn price_history(
+    db_conn: &Pool,
+    dish: i64,
+    limit: u32,
+) -> anyhow::Result<Vec<PricePoint>> {
+    let history = sqlx::query_as(
+        r#"
+SELECT amount, currency, observed_at, reporter FROM dish_price
+WHERE dish = $1
+ORDER BY observed_at DESC, id DESC
+LIMIT $2"#,
+    )
+    .bind(dish)
+    .bind(i64::from(limit))
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| format!("fail to get the price history of dish {dish}"))?;
+    Ok(history)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceTrend {
+    Rising,
+    Falling,
+    Stable,
+    /// Less than two prices in the same currency
+    Unknown,
+}
+
+impl PriceTrend {
+    /// Compare the latest price with the oldest price of the same currency in the history
+    fn of(history: &[PricePoint]) -> (Self, Option<f64>) {
+        let Some(latest) = history.first() else {
+            return (Self::Unknown, None);
+        };
+        let Some(oldest) = history[1..]
+            .iter()
+            .rev()
+            .find(|p| p.currency == latest.currency)
+        else {
+            return (Self::Unknown, None);
+        };
+        if oldest.amount == 0.0 {
+            return (Self::Unknown, None);
+        }
+        let change = (latest.amount - oldest.amount) / oldest.amount * 100.0;
+        let trend = if change > 1.0 {
+            Self::Rising
+        } else if change < -1.0 {
+            Self::Falling
+        } else {
+            Self::Stable
+        };
+        (trend, Some(change))
+    }
+}
+
+impl std::fmt::Display for PriceTrend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Rising => "rising",
+            Self::Falling => "falling",
+            Self::Stable => "stable",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Current price of a dish with its trend and value for money
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct DishPrice {
+    pub dish: i64,
+    pub current: Option<PricePoint>,
+    pub trend: PriceTrend,
+    /// Change in percent from the oldest price in the history
+    pub change: Option<f64>,
+    /// 0 - 5. The average `value` rating, or the average score weighted by how cheap the dish is
+    /// compared with the other dishes of the restaurant.
+    pub value_for_money: Option<f64>,
+    pub history: Vec<PricePoint>,
+}
+
+pub async fn dish_price(db_conn: &Pool, dish: i64) -> anyhow::Result<DishPrice> {
+    let restaurant: i64 = sqlx::query("SELECT restaurant FROM dish WHERE id=$1")
+        .bind(dish)
+        .fetch_optional(db_conn)
+        .await?
+        .with_context(|| format!("dish {dish} not found"))?
+        .get("restaurant");
+
+    let history = price_history(db_conn, dish, 20).await?;
+    let (trend, change) = PriceTrend::of(&history);
+    let current = history.first().cloned();
+
+    let rating = dish_rating(db_conn, dish).await?;
+    let value_for_money = match rating.dimensions.get(&RatingDimension::Value) {
+        Some(value) => Some(*value),
+        None => {
+            let average: Option<f64> = sqlx::query(
+                "SELECT CAST(AVG(price) AS DOUBLE PRECISION) AS price FROM dish WHERE restaurant=$1 AND currency=$2",
+            )
+            .bind(restaurant)
+            .bind(current.as_ref().map(|p| p.currency.as_str()))
+            .fetch_one(db_conn)
+            .await?
+            .get("price");
+            match (rating.score, average, &current) {
+                (Some(score), Some(average), Some(current)) if current.amount > 0.0 => {
+                    Some((score * average / current.amount).clamp(0.0, 5.0))
+                }
+                _ => None,
+            }
+        }
+    };
+
+    Ok(DishPrice {
+        dish,
+        current,
+        trend,
+        change,
+        value_for_money,
+        history,
+    })
+}
+
+#[tokio::test]
+async fn test_price_history() {
+    use super::*;
+
+    let db = test_pool().await;
+    add_new_user(&db, (1, "Avimitin")).await.unwrap();
+    let actor = Actor::User(1);
+    let rid = add_restaurant(&db, actor, "KFC", "WuHan").await.unwrap();
+    let chicken = add_dish(&db, actor, rid, "吮指原味鸡", None).await.unwrap();
+    let burger = add_dish(&db, actor, rid, "香辣鸡腿堡", None).await.unwrap();
+
+    let price = dish_price(&db, chicken).await.unwrap();
+    assert!(price.current.is_none());
+    assert_eq!(price.trend, PriceTrend::Unknown);
+
+    report_price(&db, actor, chicken, 10.0, None).await.unwrap();
+    report_price(&db, actor, burger, 15.0, Some("cny"))
+        .await
+        .unwrap();
+    // the review price is a price report too
+    let props = NewReviewPropsBuilder::default()
+        .dish(DishProp::Id(chicken))
+        .reviewer(ReviewerProp::Id(1))
+        .details("涨价了".to_string())
+        .score(4)
+        .price(12.0)
+        .build()
+        .unwrap();
+    add_new_review(&db, props).await.unwrap();
+    assert!(report_price(&db, actor, chicken, -1.0, None).await.is_err());
+
+    let price = dish_price(&db, chicken).await.unwrap();
+    assert_eq!(price.history.len(), 2);
+    assert_eq!(price.current.unwrap().amount, 12.0);
+    assert_eq!(price.trend, PriceTrend::Rising);
+    assert_eq!(price.change, Some(20.0));
+    // score 4, average price (12 + 15) / 2 = 13.5
+    assert_eq!(price.value_for_money, Some(4.5));
+
+    let dish = get_dish(&db, rid, Some(burger)).await.unwrap().remove(0);
+    assert_eq!(dish.price, Some(15.0));
+    assert_eq!(dish.currency.as_deref(), Some("CNY"));
+
+    // a new dish comes with its price, or not at all
+    let free = add_dish_with_price(&db, actor, rid, "番茄酱", None, Some((0.0, None)))
+        .await
+        .unwrap();
+    assert_eq!(dish_price(&db, free).await.unwrap().history.len(), 1);
+    let dishes = get_dish(&db, rid, None).await.unwrap().len();
+    assert!(
+        add_dish_with_price(&db, actor, rid, "薯条", None, Some((-1.0, None)))
+            .await
+            .is_err()
+    );
+    assert_eq!(get_dish(&db, rid, None).await.unwrap().len(), dishes);
+}
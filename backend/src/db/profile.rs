@@ -0,0 +1,251 @@
+use super::{Pool, Role};
+use anyhow::Context;
+use sqlx::Row;
+
+// how many cuisines and restaurants are listed in the profile
+const TOP_LIMIT: i64 = 5;
+// how far the average gap to the other reviewers can be before counting as harsh or generous
+const TENDENCY_THRESHOLD: f64 = 0.5;
+
+/// Whether the reviewer scores lower or higher than the others on the same dishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RatingTendency {
+    Harsh,
+    Balanced,
+    Generous,
+}
+
+impl RatingTendency {
+    fn from_harshness(harshness: f64) -> Self {
+        if harshness > TENDENCY_THRESHOLD {
+            Self::Harsh
+        } else if harshness < -TENDENCY_THRESHOLD {
+            Self::Generous
+        } else {
+            Self::Balanced
+        }
+    }
+}
+
+impl std::fmt::Display for RatingTendency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Harsh => "harsh",
+            Self::Balanced => "balanced",
+            Self::Generous => "generous",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// Tag of the reviewed restaurants and dishes, counted by review
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct CuisineCount {
+    pub name: String,
+    pub reviews: i64,
+    /// Average score given to the reviews with this tag
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct ReviewCount {
+    pub id: i64,
+    pub name: String,
+    pub reviews: i64,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct ReviewerProfile {
+    pub id: i64,
+    pub name: Option<String>,
+    pub role: Role,
+    pub reviews: i64,
+    /// Average of the overall score given
+    pub mean_score: Option<f64>,
+    /// How much lower the reviewer scores than the others on the same dishes on average,
+    /// negative for the generous ones. None when nobody else reviewed the same dishes.
+    pub harshness: Option<f64>,
+    pub tendency: Option<RatingTendency>,
+    /// Tags of the reviewed restaurants and dishes, the most reviewed first
+    pub favorite_cuisines: Vec<CuisineCount>,
+    /// Restaurants with the most reviews of the reviewer
+    pub top_restaurants: Vec<ReviewCount>,
+    pub visits: i64,
+}
+
+pub async fn reviewer_profile(db_conn: &Pool, id: i64) -> anyhow::Result<ReviewerProfile> {
+    let reviewer = sqlx::query("SELECT name, role FROM reviewer WHERE id=$1")
+        .bind(id)
+        .fetch_optional(db_conn)
+        .await?
+        .with_context(|| format!("reviewer {id} not found"))?;
+
+    let row = sqlx::query(
+        r#"
+SELECT
+    COUNT(*) AS reviews,
+    CAST(AVG(score) AS DOUBLE PRECISION) AS mean_score,
+    CAST(AVG(
+        (SELECT AVG(other.score) FROM review AS other
+         WHERE other.dish = review.dish AND other.reviewer != review.reviewer)
+        - score
+    ) AS DOUBLE PRECISION) AS harshness,
+    (SELECT COUNT(*) FROM visit WHERE reviewer = $1) AS visits
+FROM review
+WHERE reviewer = $2"#,
+    )
+    .bind(id)
+    .bind(id)
+    .fetch_one(db_conn)
+    .await
+    .with_context(|| format!("fail to summarize the reviews of reviewer {id}"))?;
+    let harshness: Option<f64> = row.get("harshness");
+
+    let favorite_cuisines = sqlx::query(
+        r#"
+SELECT tag.name, COUNT(DISTINCT review.id) AS reviews,
+    CAST(AVG(review.score) AS DOUBLE PRECISION) AS score
+FROM review
+    JOIN dish ON review.dish = dish.id
+    JOIN tag ON tag.id IN (
+        SELECT tag FROM restaurant_tag WHERE restaurant = dish.restaurant
+        UNION SELECT tag FROM dish_tag WHERE dish = dish.id
+    )
+WHERE review.reviewer = $1
+GROUP BY tag.id
+ORDER BY reviews DESC, score DESC, tag.name
+LIMIT $2"#,
+    )
+    .bind(id)
+    .bind(TOP_LIMIT)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| CuisineCount {
+        name: row.get("name"),
+        reviews: row.get("reviews"),
+        score: row.get("score"),
+    })
+    .collect();
+
+    let top_restaurants = sqlx::query(
+        r#"
+SELECT restaurant.id, restaurant.name, COUNT(*) AS reviews,
+    CAST(AVG(review.score) AS DOUBLE PRECISION) AS score
+FROM review
+    JOIN dish ON review.dish = dish.id
+    JOIN restaurant ON dish.restaurant = restaurant.id
+WHERE review.reviewer = $1
+GROUP BY restaurant.id
+ORDER BY reviews DESC, restaurant.id
+LIMIT $2"#,
+    )
+    .bind(id)
+    .bind(TOP_LIMIT)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| ReviewCount {
+        id: row.get("id"),
+        name: row.get("name"),
+        reviews: row.get("reviews"),
+        score: row.get("score"),
+    })
+    .collect();
+
+    Ok(ReviewerProfile {
+        id,
+        name: reviewer.get("name"),
+        role: reviewer.get::<String, _>("role").parse()?,
+        reviews: row.get("reviews"),
+        mean_score: row.get("mean_score"),
+        harshness,
+        tendency: harshness.map(RatingTendency::from_harshness),
+        favorite_cuisines,
+        top_restaurants,
+        visits: row.get("visits"),
+    })
+}
+
+#[tokio::test]
+async fn test_reviewer_profile() {
+    use super::*;
+
+    let db = test_pool().await;
+    register_reviewer(&db, 1, "alice").await.unwrap();
+    register_reviewer(&db, 2, "bob").await.unwrap();
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    let mcd = add_restaurant(&db, Actor::System, "McDonald", "光谷")
+        .await
+        .unwrap();
+    add_tags(
+        &db,
+        Actor::System,
+        EntityKind::Restaurant,
+        kfc,
+        &["快餐".into()],
+    )
+    .await
+    .unwrap();
+    let mut dishes = Vec::new();
+    for (restaurant, name) in [(kfc, "汉堡"), (kfc, "薯条"), (mcd, "麦旋风")] {
+        let dish = add_dish(&db, Actor::System, restaurant, name, None).await;
+        dishes.push(dish.unwrap());
+    }
+    add_tags(
+        &db,
+        Actor::System,
+        EntityKind::Dish,
+        dishes[2],
+        &["甜品".into()],
+    )
+    .await
+    .unwrap();
+
+    // alice gives 2 while bob gives 4 on the same dishes
+    let reviews = [
+        (1, dishes[0], 2),
+        (1, dishes[1], 2),
+        (1, dishes[2], 3),
+        (2, dishes[0], 4),
+        (2, dishes[1], 4),
+    ];
+    for (reviewer, dish, score) in reviews {
+        let props = NewReviewPropsBuilder::default()
+            .reviewer(ReviewerProp::Id(reviewer))
+            .dish(DishProp::Id(dish))
+            .score(score)
+            .details(String::new())
+            .build()
+            .unwrap();
+        add_new_review(&db, props).await.unwrap();
+    }
+
+    let alice = reviewer_profile(&db, 1).await.unwrap();
+    assert_eq!(alice.name.as_deref(), Some("alice"));
+    assert_eq!(alice.reviews, 3);
+    assert_eq!(alice.mean_score, Some(7.0 / 3.0));
+    // the review of 麦旋风 has nothing to compare with
+    assert_eq!(alice.harshness, Some(2.0));
+    assert_eq!(alice.tendency, Some(RatingTendency::Harsh));
+    assert_eq!(alice.favorite_cuisines[0].name, "快餐");
+    assert_eq!(alice.favorite_cuisines[0].reviews, 2);
+    assert_eq!(alice.favorite_cuisines[1].name, "甜品");
+    assert_eq!(alice.top_restaurants[0].id, kfc);
+    assert_eq!(alice.top_restaurants[1].reviews, 1);
+
+    let bob = reviewer_profile(&db, 2).await.unwrap();
+    assert_eq!(bob.tendency, Some(RatingTendency::Generous));
+    assert_eq!(bob.visits, 0);
+
+    register_reviewer(&db, 3, "carol").await.unwrap();
+    let carol = reviewer_profile(&db, 3).await.unwrap();
+    assert_eq!(carol.reviews, 0);
+    assert_eq!(carol.mean_score, None);
+    assert_eq!(carol.tendency, None);
+    assert!(reviewer_profile(&db, 4).await.is_err());
+}
@@ -0,0 +1,237 @@
+use super::{Pool, DB};
+use anyhow::Context;
+use sqlx::{Row, Transaction};
+use std::collections::{BTreeMap, HashMap};
+
+/// Aspects of a dish that can be rated besides the overall score
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RatingDimension {
+    Taste,
+    Portion,
+    Value,
+    Speed,
+    Spiciness,
+}
+
+impl RatingDimension {
+    pub const ALL: [Self; 5] = [
+        Self::Taste,
+        Self::Portion,
+        Self::Value,
+        Self::Speed,
+        Self::Spiciness,
+    ];
+}
+
+impl std::fmt::Display for RatingDimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Taste => "taste",
+            Self::Portion => "portion",
+            Self::Value => "value",
+            Self::Speed => "speed",
+            Self::Spiciness => "spiciness",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl std::str::FromStr for RatingDimension {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|d| d.to_string() == s)
+            .with_context(|| {
+                format!("unknown rating {s}, expect taste, portion, value, speed or spiciness")
+            })
+    }
+}
+
+pub(super) async fn insert_ratings(
+    tx: &mut Transaction<'_, DB>,
+    review: i64,
+    ratings: &[(RatingDimension, u8)],
+) -> anyhow::Result<()> {
+    for (dimension, score) in ratings {
+        sqlx::query(
+            r#"
+INSERT INTO review_rating (review, dimension, score) VALUES ($1, $2, $3)
+ON CONFLICT (review, dimension) DO UPDATE SET score = excluded.score"#,
+        )
+        .bind(review)
+        .bind(dimension.to_string())
+        .bind(i64::from(*score))
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("fail to save {dimension} rating of review {review}"))?;
+    }
+    Ok(())
+}
+
+pub(super) async fn get_ratings(
+    db_conn: &Pool,
+    review: i64,
+) -> anyhow::Result<BTreeMap<RatingDimension, u8>> {
+    sqlx::query("SELECT dimension, score FROM review_rating WHERE review=$1")
+        .bind(review)
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let score = row.get::<i64, _>("score") as u8;
+            Ok((row.get::<String, _>("dimension").parse()?, score))
+        })
+        .collect()
+}
+
+pub(super) async fn get_ratings_of(
+    db_conn: &Pool,
+    reviews: &[i64],
+) -> anyhow::Result<HashMap<i64, BTreeMap<RatingDimension, u8>>> {
+    if reviews.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let sql = format!(
+        "SELECT review, dimension, score FROM review_rating WHERE review IN ({})",
+        super::placeholders(reviews.len())
+    );
+    let mut query = sqlx::query(&sql);
+    for id in reviews {
+        query = query.bind(id);
+    }
+    let mut ratings = HashMap::<_, BTreeMap<_, _>>::new();
+    for row in query.fetch_all(db_conn).await? {
+        let dimension = row.get::<String, _>("dimension").parse()?;
+        ratings
+            .entry(row.get("review"))
+            .or_default()
+            .insert(dimension, row.get::<i64, _>("score") as u8);
+    }
+    Ok(ratings)
+}
+
+/// Aggregated ratings of a dish or a restaurant
+#[derive(Debug, Default, serde::Serialize, utoipa::ToSchema)]
+pub struct RatingSummary {
+    pub reviews: i64,
+    /// Average of the overall score
+    pub score: Option<f64>,
+    /// Average of each sub-score, dimensions that nobody rated are absent
+    pub dimensions: BTreeMap<RatingDimension, f64>,
+    /// Average price paid
+    pub price: Option<f64>,
+}
+
+async fn summarize(db_conn: &Pool, filter: &str, id: i64) -> anyhow::Result<RatingSummary> {
+    let row = sqlx::query(&format!(
+        r#"
+SELECT COUNT(review.id) AS reviews, CAST(AVG(review.score) AS DOUBLE PRECISION) AS score,
+    CAST(AVG(review.price) AS DOUBLE PRECISION) AS price
+FROM review JOIN dish ON review.dish = dish.id
+WHERE {filter} = $1"#
+    ))
+    .bind(id)
+    .fetch_one(db_conn)
+    .await?;
+
+    let dimensions = sqlx::query(&format!(
+        r#"
+SELECT review_rating.dimension, CAST(AVG(review_rating.score) AS DOUBLE PRECISION) AS score
+FROM review_rating
+    JOIN review ON review_rating.review = review.id
+    JOIN dish ON review.dish = dish.id
+WHERE {filter} = $1
+GROUP BY review_rating.dimension"#
+    ))
+    .bind(id)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| Ok((row.get::<String, _>("dimension").parse()?, row.get("score"))))
+    .collect::<anyhow::Result<_>>()?;
+
+    Ok(RatingSummary {
+        reviews: row.get("reviews"),
+        score: row.get("score"),
+        dimensions,
+        price: row.get("price"),
+    })
+}
+
+pub async fn dish_rating(db_conn: &Pool, dish: i64) -> anyhow::Result<RatingSummary> {
+    summarize(db_conn, "dish.id", dish)
+        .await
+        .with_context(|| format!("fail to summarize ratings of dish {dish}"))
+}
+
+pub async fn restaurant_rating(db_conn: &Pool, restaurant: i64) -> anyhow::Result<RatingSummary> {
+    summarize(db_conn, "dish.restaurant", restaurant)
+        .await
+        .with_context(|| format!("fail to summarize ratings of restaurant {restaurant}"))
+}
+
+#[tokio::test]
+async fn test_rating_summary() {
+    use super::*;
+
+    let db = test_pool().await;
+    add_new_user(&db, (1, "Avimitin")).await.unwrap();
+    let rid = add_restaurant(&db, Actor::System, "KFC", "WuHan")
+        .await
+        .unwrap();
+    let did = add_dish(&db, Actor::System, rid, "吮指原味鸡", None)
+        .await
+        .unwrap();
+
+    let review = |score, ratings: Vec<(RatingDimension, u8)>| {
+        let mut builder = NewReviewPropsBuilder::default();
+        builder
+            .dish(DishProp::Id(did))
+            .reviewer(ReviewerProp::Id(1))
+            .details("好恰".to_string())
+            .score(score)
+            .ratings(ratings);
+        builder
+    };
+    // legacy review with only the overall score
+    add_new_review(&db, review(3, vec![]).build().unwrap())
+        .await
+        .unwrap();
+    let props = review(
+        5,
+        vec![(RatingDimension::Taste, 5), (RatingDimension::Value, 2)],
+    )
+    .price(29.5)
+    .build()
+    .unwrap();
+    add_new_review(&db, props).await.unwrap();
+    let invalid = review(5, vec![(RatingDimension::Speed, 6)])
+        .build()
+        .unwrap();
+    assert!(add_new_review(&db, invalid).await.is_err());
+
+    let summary = dish_rating(&db, did).await.unwrap();
+    assert_eq!(summary.reviews, 2);
+    assert_eq!(summary.score, Some(4.0));
+    assert_eq!(summary.price, Some(29.5));
+    assert_eq!(summary.dimensions.get(&RatingDimension::Taste), Some(&5.0));
+    assert_eq!(summary.dimensions.get(&RatingDimension::Speed), None);
+
+    let summary = restaurant_rating(&db, rid).await.unwrap();
+    assert_eq!(summary.reviews, 2);
+    assert_eq!(dish_rating(&db, did + 1).await.unwrap().reviews, 0);
+}
@@ -0,0 +1,308 @@
+use super::{record, snapshot, Actor, AuditAction, EntityKind, Pool, DB};
+use anyhow::Context;
+use sqlx::{Row, Transaction};
+
+/// Split comma separated tags. Both ASCII and full width comma are accepted, tags are trimmed,
+/// lowercased and deduplicated.
+pub fn split_tags(s: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    for tag in s.split([',', '，']).map(|t| t.trim().to_lowercase()) {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Return true if the comma separated `tags` contains every tag in `wanted`
+pub fn has_tags(tags: Option<&str>, wanted: &[String]) -> bool {
+    let tags = tags.map(split_tags).unwrap_or_default();
+    wanted.iter().all(|w| tags.contains(w))
+}
+
+// link table and its entity column
+fn link_table(kind: EntityKind) -> anyhow::Result<(&'static str, &'static str)> {
+    match kind {
+        EntityKind::Restaurant => Ok(("restaurant_tag", "restaurant")),
+        EntityKind::Dish => Ok(("dish_tag", "dish")),
+        _ => anyhow::bail!("{kind} can not be tagged"),
+    }
+}
+
+/// Tags of the row, or None for the kinds that can't be tagged
+pub(super) async fn tags_of(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let Ok((table, column)) = link_table(kind) else {
+        return Ok(None);
+    };
+    let tags = sqlx::query(&format!(
+        "SELECT tag.name FROM {table} JOIN tag ON {table}.tag = tag.id \
+        WHERE {table}.{column} = $1 ORDER BY tag.name"
+    ))
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.get("name"))
+    .collect();
+    Ok(Some(tags))
+}
+
+/// Replace every tag of the row
+pub(super) async fn replace_tags(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    id: i64,
+    tags: &[String],
+) -> anyhow::Result<()> {
+    let (table, column) = link_table(kind)?;
+    sqlx::query(&format!("DELETE FROM {table} WHERE {column} = $1"))
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    for tag in tags {
+        sqlx::query("INSERT INTO tag (name) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "INSERT INTO {table} ({column}, tag) SELECT $1, id FROM tag WHERE name = $2 ON CONFLICT DO NOTHING"
+        ))
+        .bind(id)
+        .bind(tag)
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("fail to tag {kind} {id} with {tag}"))?;
+    }
+    Ok(())
+}
+
+/// Copy every tag from one row to another, used when merging duplicated rows
+pub(super) async fn copy_tags(
+    tx: &mut Transaction<'_, DB>,
+    kind: EntityKind,
+    from: i64,
+    into: i64,
+) -> anyhow::Result<()> {
+    let (table, column) = link_table(kind)?;
+    sqlx::query(&format!(
+        "INSERT INTO {table} ({column}, tag) SELECT $1, tag FROM {table} WHERE {column} = $2 ON CONFLICT DO NOTHING"
+    ))
+    .bind(into)
+    .bind(from)
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_tags(db_conn: &Pool, kind: EntityKind, id: i64) -> anyhow::Result<Vec<String>> {
+    let mut tx = db_conn.begin().await?;
+    let tags = tags_of(&mut tx, kind, id).await?;
+    tags.with_context(|| format!("{kind} can not be tagged"))
+}
+
+async fn update_tags(
+    db_conn: &Pool,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+    update: impl FnOnce(&mut Vec<String>),
+) -> anyhow::Result<Vec<String>> {
+    let mut tx = db_conn.begin().await?;
+    let before = snapshot(&mut tx, kind, id)
+        .await?
+        .with_context(|| format!("{kind} {id} not found"))?;
+    let mut tags = tags_of(&mut tx, kind, id)
+        .await?
+        .with_context(|| format!("{kind} can not be tagged"))?;
+    update(&mut tags);
+    replace_tags(&mut tx, kind, id, &tags).await?;
+
+    let after = snapshot(&mut tx, kind, id).await?;
+    if after.as_ref() != Some(&before) {
+        record(
+            &mut tx,
+            actor,
+            AuditAction::Update,
+            kind,
+            id,
+            Some(before),
+            after,
+        )
+        .await?;
+    }
+    tx.commit()
+        .await
+        .with_context(|| format!("fail to update tags of {kind} {id}"))?;
+    Ok(tags)
+}
+
+/// Add tags to a restaurant or a dish, return all of its tags
+pub async fn add_tags(
+    db_conn: &Pool,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+    tags: &[String],
+) -> anyhow::Result<Vec<String>> {
+    edit_tags(db_conn, actor, kind, id, tags, &[]).await
+}
+
+/// Remove tags from a restaurant or a dish, return the remaining tags
+pub async fn remove_tags(
+    db_conn: &Pool,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+    tags: &[String],
+) -> anyhow::Result<Vec<String>> {
+    edit_tags(db_conn, actor, kind, id, &[], tags).await
+}
+
+/// Add some tags and remove some others in one change, return the tags after it
+pub async fn edit_tags(
+    db_conn: &Pool,
+    actor: Actor,
+    kind: EntityKind,
+    id: i64,
+    add: &[String],
+    remove: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let removing = remove
+        .iter()
+        .flat_map(|t| split_tags(t))
+        .collect::<Vec<_>>();
+    update_tags(db_conn, actor, kind, id, |current| {
+        for tag in add.iter().flat_map(|t| split_tags(t)) {
+            if !current.contains(&tag) {
+                current.push(tag);
+            }
+        }
+        current.retain(|t| !removing.contains(t))
+    })
+    .await
+}
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct TagCount {
+    pub id: i64,
+    pub name: String,
+    pub restaurants: i64,
+    pub dishes: i64,
+}
+
+/// Every tag in use with the number of restaurants and dishes, the most used first
+pub async fn tag_cloud(db_conn: &Pool) -> anyhow::Result<Vec<TagCount>> {
+    let cloud = sqlx::query(
+        r#"
+SELECT * FROM (
+    SELECT
+        tag.id,
+        tag.name,
+        (SELECT COUNT(*) FROM restaurant_tag WHERE restaurant_tag.tag = tag.id) AS restaurants,
+        (SELECT COUNT(*) FROM dish_tag WHERE dish_tag.tag = tag.id) AS dishes
+    FROM tag
+) AS counted
+ORDER BY restaurants + dishes DESC, name"#,
+    )
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| "fail to count tags")?
+    .into_iter()
+    .map(|row| TagCount {
+        id: row.get("id"),
+        name: row.get("name"),
+        restaurants: row.get("restaurants"),
+        dishes: row.get("dishes"),
+    })
+    .filter(|t| t.restaurants + t.dishes > 0)
+    .collect();
+    Ok(cloud)
+}
+
+pub async fn get_tag_name(db_conn: &Pool, id: i64) -> anyhow::Result<Option<String>> {
+    let row = sqlx::query("SELECT name FROM tag WHERE id=$1")
+        .bind(id)
+        .fetch_optional(db_conn)
+        .await?;
+    Ok(row.map(|r| r.get("name")))
+}
+
+#[tokio::test]
+async fn test_tags() {
+    use super::*;
+
+    let db = test_pool().await;
+    let actor = Actor::System;
+    let props = NewRestaurantPropsBuilder::default()
+        .name("老乡鸡")
+        .address("光谷")
+        .tags("快餐， Chinese,快餐")
+        .build()
+        .unwrap();
+    let rid = add_restaurant_detail(&db, actor, props).await.unwrap();
+    let did = add_dish(&db, actor, rid, "农家小炒肉", None).await.unwrap();
+
+    let rst = get_restaurant(&db, RestaurantSearchProps::Id(rid))
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(rst.tags.as_deref(), Some("快餐,chinese"));
+    assert!(has_tags(rst.tags.as_deref(), &["快餐".to_string()]));
+    assert!(!has_tags(rst.tags.as_deref(), &["noodles".to_string()]));
+
+    let tags = add_tags(&db, actor, EntityKind::Dish, did, &["spicy,辣".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(tags, ["spicy", "辣"]);
+    let tags = remove_tags(&db, actor, EntityKind::Dish, did, &["辣".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(tags, ["spicy"]);
+    // both sides in one audit entry
+    let (add, remove) = (["甜".to_string()], ["spicy".to_string()]);
+    let tags = edit_tags(&db, actor, EntityKind::Dish, did, &add, &remove)
+        .await
+        .unwrap();
+    assert_eq!(tags, ["甜"]);
+    let query = AuditQueryBuilder::default().limit(1).build().unwrap();
+    let entry = get_audit_log(&db, query).await.unwrap().remove(0);
+    assert_eq!(entry.before.unwrap()["tags"], "spicy");
+    assert_eq!(entry.after.unwrap()["tags"], "甜");
+    edit_tags(&db, actor, EntityKind::Dish, did, &remove, &add)
+        .await
+        .unwrap();
+    assert!(add_tags(&db, actor, EntityKind::Review, 1, &[])
+        .await
+        .is_err());
+
+    let cloud = tag_cloud(&db).await.unwrap();
+    assert_eq!(cloud.len(), 3);
+    assert!(cloud.iter().all(|t| t.name != "辣"));
+
+    let dish = get_dish(&db, rid, Some(did)).await.unwrap().remove(0);
+    assert_eq!(dish.tags.as_deref(), Some("spicy"));
+
+    // reverting a deletion restores the tags
+    let rid = add_restaurant(&db, actor, "海底捞", "群光").await.unwrap();
+    add_tags(
+        &db,
+        actor,
+        EntityKind::Restaurant,
+        rid,
+        &["hotpot".to_string()],
+    )
+    .await
+    .unwrap();
+    update_restaurant(&db, actor, rid, UpdateRestaurantProps::Delete)
+        .await
+        .unwrap();
+    let query = AuditQueryBuilder::default().limit(1).build().unwrap();
+    let entry = get_audit_log(&db, query).await.unwrap().remove(0);
+    revert_audit(&db, actor, entry.id).await.unwrap();
+    let tags = get_tags(&db, EntityKind::Restaurant, rid).await.unwrap();
+    assert_eq!(tags, ["hotpot"]);
+}
@@ -0,0 +1,94 @@
+use super::{EntityKind, Pool};
+use anyhow::Context;
+use sqlx::Row;
+use std::collections::HashMap;
+
+/// Current counters of the kinds in the same order, bumped by every change of their rows.
+///
+/// Only restaurants, dishes and reviews have a counter, see the `entity_version` migration for
+/// the changes counted in each.
+pub async fn entity_versions(db_conn: &Pool, kinds: &[EntityKind]) -> anyhow::Result<Vec<i64>> {
+    let versions = sqlx::query("SELECT kind, version FROM entity_version")
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(|row| (row.get::<String, _>("kind"), row.get::<i64, _>("version")))
+        .collect::<HashMap<_, _>>();
+    kinds
+        .iter()
+        .map(|kind| {
+            versions
+                .get(kind.table())
+                .copied()
+                .with_context(|| format!("{kind} has no version counter"))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_entity_versions() {
+    use super::*;
+
+    let db = test_pool().await;
+    let kinds = [EntityKind::Restaurant, EntityKind::Dish, EntityKind::Review];
+    let versions = |db| async move { entity_versions(db, &kinds).await.unwrap() };
+    let start = versions(&db).await;
+
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    let after_restaurant = versions(&db).await;
+    assert!(after_restaurant[0] > start[0]);
+    assert_eq!(after_restaurant[1..], start[1..]);
+
+    let dish = add_dish(&db, Actor::System, kfc, "汉堡", None)
+        .await
+        .unwrap();
+    let before_tag = versions(&db).await;
+    add_tags(&db, Actor::System, EntityKind::Dish, dish, &["辣".into()])
+        .await
+        .unwrap();
+    let after_tag = versions(&db).await;
+    // a new tag is a new filter of both lists
+    assert!(after_tag[0] > before_tag[0]);
+    assert!(after_tag[1] > before_tag[1]);
+    // an existing one changes only the tagged list
+    add_tags(
+        &db,
+        Actor::System,
+        EntityKind::Restaurant,
+        kfc,
+        &["辣".into()],
+    )
+    .await
+    .unwrap();
+    let after_existing = versions(&db).await;
+    assert!(after_existing[0] > after_tag[0]);
+    assert_eq!(after_existing[1..], after_tag[1..]);
+
+    add_new_user(&db, (1, "alice")).await.unwrap();
+    let prop = NewReviewPropsBuilder::default()
+        .dish(DishProp::Id(dish))
+        .reviewer(ReviewerProp::Id(1))
+        .details(String::new())
+        .score(4)
+        .build()
+        .unwrap();
+    add_new_review(&db, prop).await.unwrap();
+    let after_review = versions(&db).await;
+    assert!(after_review[2] > after_tag[2]);
+
+    // the reviews show the name of the reviewer, which is the same
+    sqlx::query("UPDATE reviewer SET name = name WHERE id = 1")
+        .execute(&*db)
+        .await
+        .unwrap();
+    assert_eq!(versions(&db).await, after_review);
+    sqlx::query("UPDATE reviewer SET name = 'bob' WHERE id = 1")
+        .execute(&*db)
+        .await
+        .unwrap();
+    assert!(versions(&db).await[2] > after_review[2]);
+
+    assert!(entity_versions(&db, &[EntityKind::Visit]).await.is_err());
+}
@@ -0,0 +1,264 @@
+use super::{local_now, record_create, Actor, EntityKind, Pool, RestaurantProp};
+use anyhow::Context;
+use chrono::NaiveDate;
+use derive_builder::Builder;
+use sqlx::Row;
+
+#[derive(Builder)]
+pub struct NewVisitProps {
+    reviewer: i64,
+    restaurant: RestaurantProp,
+    /// Today by the local time if not given
+    #[builder(setter(strip_option), default)]
+    date: Option<NaiveDate>,
+    #[builder(setter(strip_option), default)]
+    party_size: Option<u32>,
+    /// Total spend of the party, in the default currency
+    #[builder(setter(strip_option), default)]
+    spend: Option<f64>,
+}
+
+/// Record a visit and return its id
+pub async fn add_visit(db_conn: &Pool, props: NewVisitProps) -> anyhow::Result<i64> {
+    let NewVisitProps {
+        reviewer,
+        restaurant,
+        date,
+        party_size,
+        spend,
+    } = props;
+
+    let today = local_now().date();
+    let date = date.unwrap_or(today);
+    anyhow::ensure!(date <= today, "{date} is in the future");
+    anyhow::ensure!(party_size != Some(0), "party size should be at least 1");
+    if let Some(spend) = spend {
+        anyhow::ensure!(
+            spend.is_finite() && spend >= 0.0,
+            "spend should be a positive number"
+        );
+    }
+    let restaurant = restaurant.resolve(db_conn).await?;
+
+    let mut tx = db_conn.begin().await?;
+    let id = sqlx::query(
+        r#"
+INSERT INTO visit
+    (reviewer, restaurant, visited_on, party_size, spend)
+VALUES
+    ($1, $2, $3, $4, $5)
+RETURNING id"#,
+    )
+    .bind(reviewer)
+    .bind(restaurant)
+    .bind(date.to_string())
+    .bind(party_size.map(i64::from))
+    .bind(spend)
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("fail to add visit to restaurant {restaurant}"))?
+    .get("id");
+    record_create(&mut tx, Actor::User(reviewer), EntityKind::Visit, id).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct Visit {
+    pub id: i64,
+    pub reviewer: i64,
+    pub restaurant: i64,
+    pub visited_on: String,
+    pub party_size: Option<i64>,
+    pub spend: Option<f64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct VisitCount {
+    pub id: i64,
+    pub name: String,
+    pub visits: i64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, utoipa::ToSchema)]
+pub struct VisitStats {
+    pub visits: i64,
+    /// Sum of the party sizes, the visit without party size counts one person
+    pub people: i64,
+    pub total_spend: Option<f64>,
+    /// Average spend per person of the visits with spend
+    pub spend_per_person: Option<f64>,
+    pub first_visit: Option<String>,
+    pub last_visit: Option<String>,
+    /// Visits by reviewer for a restaurant, or by restaurant for a reviewer, the most first
+    pub top: Vec<VisitCount>,
+    pub recent: Vec<Visit>,
+}
+
+const TOP_LIMIT: i64 = 10;
+
+// `column` is the visit column to filter by, `top` is the query of the visit counts
+async fn visit_stats(
+    db_conn: &Pool,
+    column: &str,
+    id: i64,
+    top: &str,
+) -> anyhow::Result<VisitStats> {
+    let row = sqlx::query(&format!(
+        r#"
+SELECT
+    COUNT(*) AS visits,
+    CAST(COALESCE(SUM(COALESCE(party_size, 1)), 0) AS BIGINT) AS people,
+    SUM(spend) AS total_spend,
+    SUM(spend) / CAST(SUM(CASE WHEN spend IS NULL THEN NULL ELSE COALESCE(party_size, 1) END)
+        AS DOUBLE PRECISION) AS spend_per_person,
+    MIN(visited_on) AS first_visit,
+    MAX(visited_on) AS last_visit
+FROM visit
+WHERE {column} = $1"#
+    ))
+    .bind(id)
+    .fetch_one(db_conn)
+    .await
+    .with_context(|| format!("fail to count the visits of {column} {id}"))?;
+
+    let top = sqlx::query(top)
+        .bind(id)
+        .bind(TOP_LIMIT)
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(|row| VisitCount {
+            id: row.get("id"),
+            name: row.get("name"),
+            visits: row.get("visits"),
+        })
+        .collect();
+    let recent = sqlx::query_as(&format!(
+        "SELECT * FROM visit WHERE {column} = $1 ORDER BY visited_on DESC, id DESC LIMIT $2"
+    ))
+    .bind(id)
+    .bind(TOP_LIMIT)
+    .fetch_all(db_conn)
+    .await?;
+
+    Ok(VisitStats {
+        visits: row.get("visits"),
+        people: row.get("people"),
+        total_spend: row.get("total_spend"),
+        spend_per_person: row.get("spend_per_person"),
+        first_visit: row.get("first_visit"),
+        last_visit: row.get("last_visit"),
+        top,
+        recent,
+    })
+}
+
+/// Visits of a restaurant, with the reviewers who go there the most
+pub async fn restaurant_visits(db_conn: &Pool, restaurant: i64) -> anyhow::Result<VisitStats> {
+    let top = r#"
+SELECT reviewer.id, reviewer.name, COUNT(*) AS visits
+FROM visit JOIN reviewer ON visit.reviewer = reviewer.id
+WHERE visit.restaurant = $1
+GROUP BY reviewer.id
+ORDER BY visits DESC, reviewer.id
+LIMIT $2"#;
+    visit_stats(db_conn, "restaurant", restaurant, top).await
+}
+
+/// Visits of a reviewer, with the restaurants the reviewer has been to the most
+pub async fn reviewer_visits(db_conn: &Pool, reviewer: i64) -> anyhow::Result<VisitStats> {
+    let top = r#"
+SELECT restaurant.id, restaurant.name, COUNT(*) AS visits
+FROM visit JOIN restaurant ON visit.restaurant = restaurant.id
+WHERE visit.reviewer = $1
+GROUP BY restaurant.id
+ORDER BY visits DESC, restaurant.id
+LIMIT $2"#;
+    visit_stats(db_conn, "reviewer", reviewer, top).await
+}
+
+/// How many times the reviewer has been to the restaurant
+pub async fn visit_count(db_conn: &Pool, reviewer: i64, restaurant: i64) -> anyhow::Result<i64> {
+    let count =
+        sqlx::query("SELECT COUNT(*) AS visits FROM visit WHERE reviewer=$1 AND restaurant=$2")
+            .bind(reviewer)
+            .bind(restaurant)
+            .fetch_one(db_conn)
+            .await?
+            .get("visits");
+    Ok(count)
+}
+
+#[tokio::test]
+async fn test_visits() {
+    use super::*;
+
+    let db = test_pool().await;
+    register_reviewer(&db, 1, "alice").await.unwrap();
+    register_reviewer(&db, 2, "bob").await.unwrap();
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    let mcd = add_restaurant(&db, Actor::System, "McDonald", "光谷")
+        .await
+        .unwrap();
+
+    let date = |d: &str| d.parse::<NaiveDate>().unwrap();
+    let visits = [
+        (1, "KFC", "2023-01-02", Some(3), Some(90.0)),
+        (1, "KFC", "2023-01-05", None, Some(30.0)),
+        (2, "KFC", "2023-01-03", Some(2), None),
+        (1, "McDonald", "2023-01-04", None, None),
+    ];
+    for (reviewer, restaurant, day, party, spend) in visits {
+        let mut props = NewVisitPropsBuilder::default();
+        props
+            .reviewer(reviewer)
+            .restaurant(RestaurantProp::Name(restaurant.to_string()))
+            .date(date(day));
+        if let Some(party) = party {
+            props.party_size(party);
+        }
+        if let Some(spend) = spend {
+            props.spend(spend);
+        }
+        add_visit(&db, props.build().unwrap()).await.unwrap();
+    }
+
+    let stats = restaurant_visits(&db, kfc).await.unwrap();
+    assert_eq!(stats.visits, 3);
+    assert_eq!(stats.people, 6);
+    assert_eq!(stats.total_spend, Some(120.0));
+    // 120 by 4 people
+    assert_eq!(stats.spend_per_person, Some(30.0));
+    assert_eq!(stats.first_visit.as_deref(), Some("2023-01-02"));
+    assert_eq!(stats.last_visit.as_deref(), Some("2023-01-05"));
+    assert_eq!(stats.top[0].name, "alice");
+    assert_eq!(stats.top[0].visits, 2);
+    assert_eq!(stats.recent[0].visited_on, "2023-01-05");
+
+    let stats = reviewer_visits(&db, 1).await.unwrap();
+    assert_eq!(stats.visits, 3);
+    assert_eq!(stats.top.len(), 2);
+    assert_eq!(stats.top[0].id, kfc);
+    assert_eq!(visit_count(&db, 2, mcd).await.unwrap(), 0);
+    assert_eq!(
+        restaurant_visits(&db, mcd).await.unwrap().spend_per_person,
+        None
+    );
+
+    let props = NewVisitPropsBuilder::default()
+        .reviewer(2)
+        .restaurant(RestaurantProp::Id(mcd))
+        .party_size(0)
+        .build()
+        .unwrap();
+    assert!(add_visit(&db, props).await.is_err());
+
+    // visits follow the merged restaurant
+    merge_restaurant(&db, Actor::System, mcd, kfc)
+        .await
+        .unwrap();
+    assert_eq!(visit_count(&db, 1, kfc).await.unwrap(), 3);
+}
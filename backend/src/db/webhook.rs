@@ -0,0 +1,498 @@
+use super::{local_now, record, Actor, AuditAction, EntityKind, Pool, DB, DEFAULT_UTC_OFFSET};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::{any::AnyRow, Row, Transaction};
+
+// a delivery is given up after this many failed attempts
+const MAX_ATTEMPTS: i64 = 8;
+// wait before the retry, doubled after every failed attempt
+const BASE_DELAY_SECS: i64 = 30;
+const MAX_DELAY_SECS: i64 = 3600;
+
+/// Header of the signature, `sha256=` followed by the hex HMAC-SHA256 of the body
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Value of the [`SIGNATURE_HEADER`] for the payload
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes a key of any size");
+    mac.update(payload.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// the kinds whose changes are sent to webhooks
+fn event_name(kind: EntityKind, action: AuditAction) -> Option<String> {
+    match kind {
+        EntityKind::Restaurant | EntityKind::Dish | EntityKind::Review => {
+            Some(format!("{kind}.{action}"))
+        }
+        _ => None,
+    }
+}
+
+// pattern is `*`, `<kind>.*` or `<kind>.<action>`
+fn matches_event(pattern: &str, event: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => event.starts_with(prefix),
+        None => pattern == event,
+    }
+}
+
+fn validate_pattern(pattern: &str) -> anyhow::Result<()> {
+    if pattern == "*" {
+        return Ok(());
+    }
+    let (kind, action) = pattern
+        .split_once('.')
+        .with_context(|| format!("event {pattern} should be like review.create or dish.*"))?;
+    anyhow::ensure!(
+        matches!(kind, "restaurant" | "dish" | "review"),
+        "only restaurant, dish and review events are sent, not {kind}"
+    );
+    anyhow::ensure!(
+        matches!(action, "*" | "create" | "update" | "delete" | "merge"),
+        "unknown action {action}, use create, update, delete, merge or *"
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_by: Option<i64>,
+    pub created_at: String,
+}
+
+impl From<AnyRow> for Webhook {
+    fn from(row: AnyRow) -> Self {
+        Self {
+            id: row.get("id"),
+            url: row.get("url"),
+            events: row
+                .get::<String, _>("events")
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+/// Register a webhook and return its id and signing secret. The secret is only returned here.
+pub async fn add_webhook(
+    db_conn: &Pool,
+    actor: Actor,
+    url: &str,
+    events: &[String],
+) -> anyhow::Result<(i64, String)> {
+    let url = url.trim();
+    anyhow::ensure!(
+        url.starts_with("http://") || url.starts_with("https://"),
+        "webhook url should start with http:// or https://"
+    );
+    let events = events
+        .iter()
+        .map(|e| e.trim().to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!events.is_empty(), "webhook needs at least one event");
+    for event in &events {
+        validate_pattern(event)?;
+    }
+
+    let secret = hex::encode(rand::random::<[u8; 24]>());
+    let mut tx = db_conn.begin().await?;
+    let id = sqlx::query(
+        "INSERT INTO webhook (url, secret, events, created_by) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(url)
+    .bind(&secret)
+    .bind(events.join(","))
+    .bind(actor.db_id())
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("fail to add webhook {url}"))?
+    .get("id");
+    let after = serde_json::json!({ "url": url, "events": events });
+    let action = AuditAction::Create;
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Webhook,
+        id,
+        None,
+        Some(after),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((id, secret))
+}
+
+pub async fn list_webhooks(db_conn: &Pool) -> anyhow::Result<Vec<Webhook>> {
+    let webhooks = sqlx::query("SELECT * FROM webhook ORDER BY id")
+        .fetch_all(db_conn)
+        .await?
+        .into_iter()
+        .map(Webhook::from)
+        .collect();
+    Ok(webhooks)
+}
+
+/// Remove the webhook with its deliveries, return false if it doesn't exist
+pub async fn remove_webhook(db_conn: &Pool, actor: Actor, id: i64) -> anyhow::Result<bool> {
+    let mut tx = db_conn.begin().await?;
+    let Some(webhook) = sqlx::query("SELECT * FROM webhook WHERE id=$1")
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        .map(Webhook::from)
+    else {
+        return Ok(false);
+    };
+    sqlx::query("DELETE FROM webhook WHERE id=$1")
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("fail to remove webhook {id}"))?;
+    let before = serde_json::json!({ "url": webhook.url, "events": webhook.events });
+    let action = AuditAction::Delete;
+    record(
+        &mut tx,
+        actor,
+        action,
+        EntityKind::Webhook,
+        id,
+        Some(before),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Queue the change for the webhooks that want it, called with the audit log entry of the change
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn enqueue_deliveries(
+    tx: &mut Transaction<'_, DB>,
+    entry: i64,
+    actor: Actor,
+    action: AuditAction,
+    kind: EntityKind,
+    id: i64,
+    before: Option<&Value>,
+    after: Option<&Value>,
+) -> anyhow::Result<()> {
+    let Some(event) = event_name(kind, action) else {
+        return Ok(());
+    };
+    let webhooks = sqlx::query("SELECT id, events FROM webhook")
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .filter(|row| {
+            row.get::<String, _>("events")
+                .split(',')
+                .any(|p| matches_event(p, &event))
+        })
+        .map(|row| row.get::<i64, _>("id"))
+        .collect::<Vec<_>>();
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let occurred_at: String = sqlx::query("SELECT created_at FROM audit_log WHERE id=$1")
+        .bind(entry)
+        .fetch_one(&mut *tx)
+        .await?
+        .get("created_at");
+    let payload = serde_json::json!({
+        "event": event,
+        "audit_id": entry,
+        "entity": kind,
+        "entity_id": id,
+        "actor": actor.db_id(),
+        "before": before,
+        "after": after,
+        "occurred_at": occurred_at,
+    })
+    .to_string();
+    for webhook in webhooks {
+        sqlx::query("INSERT INTO webhook_delivery (webhook, event, payload) VALUES ($1, $2, $3)")
+            .bind(webhook)
+            .bind(&event)
+            .bind(&payload)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("fail to queue {event} for webhook {webhook}"))?;
+    }
+    Ok(())
+}
+
+/// A queued delivery with everything needed for sending it
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    pub id: i64,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i64,
+}
+
+// UTC time `secs` from now in the format of CURRENT_TIMESTAMP, for comparing with the columns
+fn utc_after(secs: i64) -> String {
+    (local_now() + chrono::Duration::seconds(secs - DEFAULT_UTC_OFFSET))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Pending deliveries whose next attempt is due, the oldest first
+pub async fn due_deliveries(db_conn: &Pool, limit: i64) -> anyhow::Result<Vec<PendingDelivery>> {
+    let deliveries = sqlx::query(
+        r#"
+SELECT webhook_delivery.id, webhook.url, webhook.secret, webhook_delivery.event,
+    webhook_delivery.payload, webhook_delivery.attempts
+FROM webhook_delivery JOIN webhook ON webhook_delivery.webhook = webhook.id
+WHERE webhook_delivery.status = 'pending' AND webhook_delivery.next_attempt_at <= $1
+ORDER BY webhook_delivery.next_attempt_at, webhook_delivery.id
+LIMIT $2"#,
+    )
+    .bind(utc_after(0))
+    .bind(limit)
+    .fetch_all(db_conn)
+    .await?
+    .into_iter()
+    .map(|row| PendingDelivery {
+        id: row.get("id"),
+        url: row.get("url"),
+        secret: row.get("secret"),
+        event: row.get("event"),
+        payload: row.get("payload"),
+        attempts: row.get("attempts"),
+    })
+    .collect();
+    Ok(deliveries)
+}
+
+/// Record the result of an attempt, `outcome` is the HTTP status or the error of the request.
+/// A failed delivery is retried later with exponential backoff, until [`MAX_ATTEMPTS`].
+pub async fn finish_delivery(
+    db_conn: &Pool,
+    id: i64,
+    outcome: Result<u16, String>,
+) -> anyhow::Result<()> {
+    let (status, error) = match &outcome {
+        Ok(status) => (Some(i64::from(*status)), None),
+        Err(e) => (None, Some(e.as_str())),
+    };
+    if matches!(status, Some(200..=299)) {
+        sqlx::query(
+            r#"
+UPDATE webhook_delivery
+SET status = 'delivered', attempts = attempts + 1, response_status = $1, last_error = NULL,
+    delivered_at = $2
+WHERE id = $3"#,
+        )
+        .bind(status)
+        .bind(utc_after(0))
+        .bind(id)
+        .execute(db_conn)
+        .await?;
+        return Ok(());
+    }
+
+    let attempts: i64 = sqlx::query("SELECT attempts FROM webhook_delivery WHERE id=$1")
+        .bind(id)
+        .fetch_one(db_conn)
+        .await
+        .with_context(|| format!("webhook delivery {id} not found"))?
+        .get("attempts");
+    let attempts = attempts + 1;
+    let delay = BASE_DELAY_SECS
+        .saturating_mul(1 << (attempts - 1).min(20))
+        .min(MAX_DELAY_SECS);
+    let error = error
+        .map(str::to_string)
+        .or_else(|| status.map(|s| format!("receiver responded with status {s}")));
+    sqlx::query(
+        r#"
+UPDATE webhook_delivery
+SET status = $1, attempts = $2, response_status = $3, last_error = $4,
+    next_attempt_at = $5
+WHERE id = $6"#,
+    )
+    .bind(if attempts >= MAX_ATTEMPTS {
+        "failed"
+    } else {
+        "pending"
+    })
+    .bind(attempts)
+    .bind(status)
+    .bind(error)
+    .bind(utc_after(delay))
+    .bind(id)
+    .execute(db_conn)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct Delivery {
+    pub id: i64,
+    pub webhook: i64,
+    pub event: String,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+/// Deliveries of the webhook, the latest first
+pub async fn delivery_log(
+    db_conn: &Pool,
+    webhook: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<Delivery>> {
+    let deliveries = sqlx::query_as(
+        r#"
+SELECT id, webhook, event, status, attempts, next_attempt_at, response_status, last_error,
+    created_at, delivered_at
+FROM webhook_delivery
+WHERE webhook = $1
+ORDER BY id DESC
+LIMIT $2"#,
+    )
+    .bind(webhook)
+    .bind(limit)
+    .fetch_all(db_conn)
+    .await
+    .with_context(|| format!("fail to get the deliveries of webhook {webhook}"))?;
+    Ok(deliveries)
+}
+
+#[test]
+fn test_sign_payload() {
+    // test case 2 of RFC 4231
+    assert_eq!(
+        sign_payload("Jefe", "what do ya want for nothing?"),
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[tokio::test]
+async fn test_webhook_queue() {
+    use super::*;
+
+    let db = test_pool().await;
+    assert!(
+        add_webhook(&db, Actor::System, "ftp://example.com", &["*".into()])
+            .await
+            .is_err()
+    );
+    assert!(
+        add_webhook(&db, Actor::System, "http://a.com", &["tag.*".into()])
+            .await
+            .is_err()
+    );
+    let (all, _) = add_webhook(&db, Actor::System, "http://a.com", &["*".into()])
+        .await
+        .unwrap();
+    let (reviews, secret) = add_webhook(
+        &db,
+        Actor::System,
+        "http://b.com/hook",
+        &["review.create".into(), "Dish.*".into()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        list_webhooks(&db).await.unwrap()[1].events,
+        ["review.create", "dish.*"]
+    );
+
+    let kfc = add_restaurant(&db, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    update_restaurant(
+        &db,
+        Actor::System,
+        kfc,
+        UpdateRestaurantProps::UpdateName("KFC 光谷店".into()),
+    )
+    .await
+    .unwrap();
+    add_dish(&db, Actor::System, kfc, "汉堡", None)
+        .await
+        .unwrap();
+
+    // restaurant.create, restaurant.update and dish.create for the first one, dish.create for
+    // the second one
+    let due = due_deliveries(&db, 10).await.unwrap();
+    assert_eq!(due.len(), 4);
+    assert_eq!(due[0].event, "restaurant.create");
+    let payload: Value = serde_json::from_str(&due[1].payload).unwrap();
+    assert_eq!(payload["event"], "restaurant.update");
+    assert_eq!(payload["before"]["name"], "KFC");
+    assert_eq!(payload["after"]["name"], "KFC 光谷店");
+    let dish = due.iter().find(|d| d.secret == secret).unwrap();
+    assert_eq!(dish.event, "dish.create");
+
+    finish_delivery(&db, dish.id, Ok(204)).await.unwrap();
+    finish_delivery(&db, due[0].id, Ok(500)).await.unwrap();
+    finish_delivery(&db, due[1].id, Err("connection refused".into()))
+        .await
+        .unwrap();
+    // the failed ones wait for the retry
+    assert_eq!(due_deliveries(&db, 10).await.unwrap().len(), 1);
+
+    let log = delivery_log(&db, reviews, 10).await.unwrap();
+    assert_eq!(log[0].status, "delivered");
+    assert_eq!(log[0].response_status, Some(204));
+    let log = delivery_log(&db, all, 10).await.unwrap();
+    let failed = log.iter().find(|d| d.id == due[0].id).unwrap();
+    assert_eq!(failed.status, "pending");
+    assert_eq!(failed.attempts, 1);
+    assert!(failed.next_attempt_at > failed.created_at);
+    assert_eq!(
+        failed.last_error.as_deref(),
+        Some("receiver responded with status 500")
+    );
+
+    // give up after the last attempt
+    for _ in 1..MAX_ATTEMPTS {
+        finish_delivery(&db, due[1].id, Err("timeout".into()))
+            .await
+            .unwrap();
+    }
+    let log = delivery_log(&db, all, 10).await.unwrap();
+    let gave_up = log.iter().find(|d| d.id == due[1].id).unwrap();
+    assert_eq!(gave_up.status, "failed");
+    assert_eq!(gave_up.attempts, MAX_ATTEMPTS);
+
+    assert!(remove_webhook(&db, Actor::System, all).await.unwrap());
+    assert!(!remove_webhook(&db, Actor::System, all).await.unwrap());
+    assert!(delivery_log(&db, all, 10).await.unwrap().is_empty());
+
+    // the changes are logged without the secrets
+    let query = AuditQueryBuilder::default()
+        .entity(EntityKind::Webhook)
+        .build()
+        .unwrap();
+    let log = get_audit_log(&db, query).await.unwrap();
+    let actions = log.iter().map(|e| e.action).collect::<Vec<_>>();
+    use AuditAction::{Create, Delete};
+    assert_eq!(actions, [Delete, Create, Create]);
+    assert_eq!(log[0].before.as_ref().unwrap()["url"], "http://a.com");
+    assert!(!log[1].after.as_ref().unwrap().to_string().contains(&secret));
+}
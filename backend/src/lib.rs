@@ -1,2 +1,7 @@
+pub mod api_server;
+#[allow(dead_code)]
 mod data;
 pub mod db;
+pub mod ops;
+pub mod ratelimit;
+pub mod tgbot;
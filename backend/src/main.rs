@@ -1,3 +1,73 @@
-fn main() {
-    println!("Hello, world!");
+use meal_review::db;
+
+const USAGE: &str = "Administration tool for the review database
+
+Usage:
+    meal-review migrate
+        Create or update the tables of the database
+    meal-review duplicates
+        List the restaurants and dishes that are possibly duplicated
+    meal-review merge <restaurant|dish> <from id> <into id>
+        Move everything from one row into another, then delete the first one
+
+The database is read from the DATABASE_URL env, a sqlite: or postgres: url.";
+
+async fn run(args: &[String]) -> anyhow::Result<()> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    if matches!(args.as_slice(), [] | ["help" | "-h" | "--help"]) {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    let url =
+        std::env::var("DATABASE_URL").map_err(|_| anyhow::anyhow!("DATABASE_URL env not found"))?;
+    let pool = db::connect(&url).await?;
+
+    match args.as_slice() {
+        ["migrate"] => {
+            db::migrate(&pool).await?;
+            println!("The database is up to date");
+        }
+        ["duplicates"] => {
+            let report = db::find_duplicates(&pool).await?;
+            if report.is_empty() {
+                println!("No duplicate found");
+            }
+            for candidates in report {
+                println!("{} with {}:", candidates.kind, candidates.reason);
+                for (id, name) in candidates.items {
+                    println!("    {id}. {name}");
+                }
+            }
+        }
+        ["merge", kind, from, into] => {
+            let (from, into) = (from.parse()?, into.parse()?);
+            let actor = db::Actor::System;
+            let summary = match kind.parse()? {
+                db::EntityKind::Restaurant => {
+                    db::merge_restaurant(&pool, actor, from, into).await?
+                }
+                db::EntityKind::Dish => db::merge_dish(&pool, actor, from, into).await?,
+                other => anyhow::bail!("{other} can not be merged"),
+            };
+            println!(
+                "Merged {kind} {from} into {into}: {} dishes moved, {} dishes merged, {} reviews moved",
+                summary.dishes_moved, summary.dishes_merged, summary.reviews_moved
+            );
+        }
+        _ => anyhow::bail!("unknown command\n\n{USAGE}"),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Err(e) = run(&args).await {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    }
 }
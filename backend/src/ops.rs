@@ -0,0 +1,152 @@
+use crate::db::Pool;
+use actix_web::{web, HttpResponse};
+use prometheus::{Encoder, TextEncoder};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+// a database slower than this is not ready to serve
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serve `/healthz`, `/readyz` and `/metrics`, the readiness check needs the pool in the app data
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz).service(readyz).service(metrics);
+}
+
+/// Wait for SIGTERM or ctrl-c
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("fail to listen SIGTERM: {e}");
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate => (),
+    }
+    tracing::info!("shutting down");
+}
+
+/// Told to the background tasks when the process is shutting down
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Wait until the shutdown starts
+    pub async fn wait(&mut self) {
+        while !*self.0.borrow() {
+            // the sender is gone without telling, nothing will stop the task
+            if self.0.changed().await.is_err() {
+                std::future::pending::<()>().await
+            }
+        }
+    }
+}
+
+/// The background tasks of the process, they finish their work in hand when told to shut down
+pub struct Tasks {
+    shutdown: watch::Sender<bool>,
+    running: Vec<JoinHandle<()>>,
+}
+
+impl Default for Tasks {
+    fn default() -> Self {
+        Self {
+            shutdown: watch::channel(false).0,
+            running: Vec::new(),
+        }
+    }
+}
+
+impl Tasks {
+    /// Spawn the task, which should return soon after the [`Shutdown`] it is given fires
+    pub fn spawn<T, F>(&mut self, task: T)
+    where
+        T: FnOnce(Shutdown) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = Shutdown(self.shutdown.subscribe());
+        self.running.push(tokio::spawn(task(shutdown)));
+    }
+
+    /// Tell every task to shut down and wait for them
+    pub async fn shutdown(self) {
+        self.shutdown.send_replace(true);
+        for task in self.running {
+            if let Err(e) = task.await {
+                tracing::error!("background task failed: {e}");
+            }
+        }
+    }
+}
+
+/// The process is up
+#[actix_web::get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().body("ok")
+}
+
+/// The database answers a query
+#[actix_web::get("/readyz")]
+async fn readyz(pool: web::Data<Pool>) -> HttpResponse {
+    let ping = sqlx::query("SELECT 1").execute(pool.get_ref());
+    match tokio::time::timeout(READY_TIMEOUT, ping).await {
+        Ok(Ok(_)) => HttpResponse::Ok().body("ok"),
+        Ok(Err(e)) => HttpResponse::ServiceUnavailable().body(format!("database error: {e}")),
+        Err(_) => HttpResponse::ServiceUnavailable().body("database timeout"),
+    }
+}
+
+/// Metrics of the default registry in the Prometheus text format
+#[actix_web::get("/metrics")]
+async fn metrics() -> HttpResponse {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut body) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[actix_web::test]
+async fn test_ops_routes() {
+    use actix_web::{http::StatusCode, test, App};
+
+    let pool = crate::db::test_pool().await;
+    prometheus::register_int_counter!("test_ops_total", "Counter of the test")
+        .unwrap()
+        .inc();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("test_ops_total 1"), "{body}");
+
+    pool.close().await;
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// violations within the window that ban the client
+const STRIKES: u32 = 5;
+const STRIKE_WINDOW: Duration = Duration::from_secs(10 * 60);
+const BAN: Duration = Duration::from_secs(15 * 60);
+// clients kept before dropping the idle ones
+const PRUNE_AT: usize = 4096;
+
+/// Class of a request, each class has its own budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    Read,
+    Write,
+}
+
+/// Token bucket of `burst` requests, refilled evenly over `period`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    burst: u32,
+    period: Duration,
+}
+
+impl Limit {
+    pub const fn new(burst: u32, period: Duration) -> Self {
+        Self { burst, period }
+    }
+
+    fn refill(&self, tokens: f64, elapsed: Duration) -> f64 {
+        let rate = self.burst as f64 / self.period.as_secs_f64();
+        (tokens + elapsed.as_secs_f64() * rate).min(self.burst as f64)
+    }
+
+    /// Time until the bucket has one token again
+    fn wait(&self, tokens: f64) -> Duration {
+        let rate = self.burst as f64 / self.period.as_secs_f64();
+        Duration::from_secs_f64((1.0 - tokens).max(0.0) / rate)
+    }
+}
+
+/// Parse `<burst>/<period>`, the period is `sec`, `min` or `hour`, eg. `30/min`
+impl FromStr for Limit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (burst, period) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expect <burst>/<period> but got {s}"))?;
+        let burst = burst
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&b| b > 0)
+            .ok_or_else(|| format!("invalid burst {burst}"))?;
+        let period = match period.trim() {
+            "s" | "sec" | "second" => Duration::from_secs(1),
+            "m" | "min" | "minute" => Duration::from_secs(60),
+            "h" | "hour" => Duration::from_secs(60 * 60),
+            other => return Err(format!("unknown period {other}, use sec, min or hour")),
+        };
+        Ok(Self { burst, period })
+    }
+}
+
+/// Why a request is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    /// Out of budget for now
+    Limited(Duration),
+    /// Banned by this request for repeating the violations
+    Banned(Duration),
+    /// Banned by the earlier violations
+    StillBanned(Duration),
+}
+
+impl Throttled {
+    /// Time until the client can send the request again
+    pub fn retry_after(&self) -> Duration {
+        match *self {
+            Self::Limited(after) | Self::Banned(after) | Self::StillBanned(after) => after,
+        }
+    }
+}
+
+impl std::fmt::Display for Throttled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // round up, "retry in 0 seconds" reads wrong
+        let secs = (self.retry_after().as_secs_f64().ceil() as u64).max(1);
+        match self {
+            Self::Limited(_) => write!(f, "too many requests, please retry in {secs} seconds"),
+            Self::Banned(_) | Self::StillBanned(_) => write!(
+                f,
+                "too many requests, you are blocked for {} minutes",
+                secs.div_ceil(60)
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Client {
+    read: Bucket,
+    write: Bucket,
+    strikes: u32,
+    first_strike: Instant,
+    banned_until: Option<Instant>,
+}
+
+/// Token bucket rate limiter of the clients keyed by `K`, with a read and a write budget.
+///
+/// Every refused request is a strike, and the client is banned for a while after repeating them.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    read: Limit,
+    write: Limit,
+    clients: Mutex<HashMap<K, Client>>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(read: Limit, write: Limit) -> Self {
+        Self {
+            read,
+            write,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Read the limits from `{prefix}_READ_LIMIT` and `{prefix}_WRITE_LIMIT`, see [`Limit`] for
+    /// the format. The defaults are used when they are missing or invalid.
+    pub fn from_env(prefix: &str, read: Limit, write: Limit) -> Self {
+        let var = |name: String, default: Limit| match std::env::var(&name) {
+            Ok(value) => value.parse().unwrap_or_else(|e| {
+                tracing::warn!("ignore invalid {name}: {e}");
+                default
+            }),
+            Err(_) => default,
+        };
+        Self::new(
+            var(format!("{prefix}_READ_LIMIT"), read),
+            var(format!("{prefix}_WRITE_LIMIT"), write),
+        )
+    }
+
+    /// Take a token of the class from the client, or tell why it's refused
+    pub fn check(&self, key: K, class: RequestClass) -> Result<(), Throttled> {
+        self.check_at(key, class, Instant::now())
+    }
+
+    fn check_at(&self, key: K, class: RequestClass, now: Instant) -> Result<(), Throttled> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= PRUNE_AT {
+            clients.retain(|_, client| !self.is_idle(client, now));
+        }
+        let client = clients.entry(key).or_insert_with(|| Client {
+            read: Bucket {
+                tokens: self.read.burst as f64,
+                updated: now,
+            },
+            write: Bucket {
+                tokens: self.write.burst as f64,
+                updated: now,
+            },
+            strikes: 0,
+            first_strike: now,
+            banned_until: None,
+        });
+
+        if let Some(until) = client.banned_until {
+            if until > now {
+                return Err(Throttled::StillBanned(until - now));
+            }
+            client.banned_until = None;
+        }
+
+        let (bucket, limit) = match class {
+            RequestClass::Read => (&mut client.read, self.read),
+            RequestClass::Write => (&mut client.write, self.write),
+        };
+        bucket.tokens = limit.refill(bucket.tokens, now - bucket.updated);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = limit.wait(bucket.tokens);
+
+        if now - client.first_strike > STRIKE_WINDOW {
+            client.strikes = 0;
+        }
+        if client.strikes == 0 {
+            client.first_strike = now;
+        }
+        client.strikes += 1;
+        if client.strikes >= STRIKES {
+            client.strikes = 0;
+            client.banned_until = Some(now + BAN);
+            return Err(Throttled::Banned(BAN));
+        }
+        Err(Throttled::Limited(wait))
+    }
+
+    /// Whether the client is back to the initial state and can be forgot
+    fn is_idle(&self, client: &Client, now: Instant) -> bool {
+        let full = |bucket: &Bucket, limit: Limit| {
+            limit.refill(bucket.tokens, now - bucket.updated) >= limit.burst as f64
+        };
+        client.banned_until.is_none_or(|until| until <= now)
+            && (client.strikes == 0 || now - client.first_strike > STRIKE_WINDOW)
+            && full(&client.read, self.read)
+            && full(&client.write, self.write)
+    }
+}
+
+#[test]
+fn test_parse_limit() {
+    assert_eq!(
+        "30/min".parse::<Limit>(),
+        Ok(Limit::new(30, Duration::from_secs(60)))
+    );
+    assert_eq!(
+        " 2 / sec".parse::<Limit>(),
+        Ok(Limit::new(2, Duration::from_secs(1)))
+    );
+    assert!("0/min".parse::<Limit>().is_err());
+    assert!("30".parse::<Limit>().is_err());
+    assert!("30/day".parse::<Limit>().is_err());
+}
+
+#[test]
+fn test_rate_limiter() {
+    let limiter = RateLimiter::new(
+        Limit::new(3, Duration::from_secs(3)),
+        Limit::new(1, Duration::from_secs(60)),
+    );
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+
+    for _ in 0..3 {
+        assert_eq!(limiter.check_at(1, RequestClass::Read, at(0)), Ok(()));
+    }
+    assert_eq!(
+        limiter.check_at(1, RequestClass::Read, at(0)),
+        Err(Throttled::Limited(Duration::from_secs(1)))
+    );
+    // the budgets are separated by class and client
+    assert_eq!(limiter.check_at(1, RequestClass::Write, at(0)), Ok(()));
+    assert_eq!(limiter.check_at(2, RequestClass::Read, at(0)), Ok(()));
+    // refilled one per second
+    assert_eq!(limiter.check_at(1, RequestClass::Read, at(1)), Ok(()));
+    assert!(limiter.check_at(1, RequestClass::Read, at(1)).is_err());
+
+    // the fifth strike bans the client from every class
+    for _ in 0..2 {
+        assert!(matches!(
+            limiter.check_at(1, RequestClass::Write, at(2)),
+            Err(Throttled::Limited(_))
+        ));
+    }
+    assert_eq!(
+        limiter.check_at(1, RequestClass::Write, at(2)),
+        Err(Throttled::Banned(BAN))
+    );
+    assert_eq!(
+        limiter.check_at(1, RequestClass::Read, at(62)),
+        Err(Throttled::StillBanned(BAN - Duration::from_secs(60)))
+    );
+    let lifted = at(2) + BAN;
+    assert_eq!(limiter.check_at(1, RequestClass::Read, lifted), Ok(()));
+
+    // strikes out of the window don't add up
+    let limiter = RateLimiter::new(
+        Limit::new(1, Duration::from_secs(1)),
+        Limit::new(1, Duration::from_secs(1)),
+    );
+    let mut now = start;
+    for _ in 0..STRIKES * 2 {
+        assert_eq!(limiter.check_at(1, RequestClass::Read, now), Ok(()));
+        assert!(matches!(
+            limiter.check_at(1, RequestClass::Read, now),
+            Err(Throttled::Limited(_))
+        ));
+        now += STRIKE_WINDOW / 2 + Duration::from_secs(1);
+    }
+}
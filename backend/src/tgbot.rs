@@ -0,0 +1,83 @@
+use crate::ops::Tasks;
+use std::time::Duration;
+use teloxide::{
+    dispatching::{DefaultKey, ShutdownToken},
+    dptree,
+    prelude::{Dispatcher, LoggingErrorHandler},
+    Bot,
+};
+
+mod args;
+mod config;
+mod handlers;
+pub mod metrics;
+mod notifier;
+mod scheduler;
+pub mod webhook;
+
+// how often the database is checked for the rows added by the API server
+const WATCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Start the background tasks of the bot in `tasks` and build its dispatcher on the pool. The
+/// dispatcher doesn't handle the signals, see [`stop`].
+pub fn start(
+    bot: Bot,
+    dbpool: crate::db::Pool,
+    tasks: &mut Tasks,
+) -> Dispatcher<Bot, anyhow::Error, DefaultKey> {
+    let notifier = notifier::Notifier::new(bot.clone(), dbpool.clone());
+    let events = crate::db::subscribe_events();
+    tasks.spawn(|shutdown| notifier.run(events, shutdown));
+    // the API may run in another process, notify the followers of its changes too
+    let pool = dbpool.clone();
+    tasks.spawn(|shutdown| async move {
+        match crate::db::ChangeWatcher::new(pool).await {
+            Ok(watcher) => watcher.run(WATCH_INTERVAL, shutdown).await,
+            Err(e) => tracing::error!("fail to watch the database changes: {e:#}"),
+        }
+    });
+    let (sender, pool) = (bot.clone(), dbpool.clone());
+    tasks.spawn(|shutdown| scheduler::run(sender, pool, shutdown));
+
+    Dispatcher::builder(bot, handlers::handler_schema())
+        .dependencies(dptree::deps![
+            metrics::DialogueStorage::new(),
+            dbpool,
+            std::sync::Arc::new(config::BotConfig::from_env()),
+            std::sync::Arc::new(config::rate_limiter())
+        ])
+        .default_handler(|_| async move {})
+        .error_handler(std::sync::Arc::new(metrics::handle_error))
+        .build()
+}
+
+/// Dispatch the updates pushed to the webhook, or poll them when there is no webhook or telegram
+/// refuses it
+pub async fn dispatch(
+    dispatcher: &mut Dispatcher<Bot, anyhow::Error, DefaultKey>,
+    bot: &Bot,
+    webhook: Option<(webhook::Webhook, webhook::WebhookListener)>,
+) {
+    if let Some((webhook, listener)) = webhook {
+        match webhook.register(bot).await {
+            Ok(()) => {
+                tracing::info!("receive the updates from the webhook");
+                let error_handler = LoggingErrorHandler::with_custom_text("webhook error");
+                return dispatcher
+                    .dispatch_with_listener(listener, error_handler)
+                    .await;
+            }
+            Err(e) => tracing::error!("fail to set the webhook, poll the updates: {e:#}"),
+        }
+    }
+    // polling deletes the webhook set by the earlier runs
+    dispatcher.dispatch().await
+}
+
+/// Stop receiving updates and wait for the handlers already running
+pub async fn stop(shutdown: &ShutdownToken) {
+    // an error means it's not dispatching yet, nothing to drain
+    if let Ok(drained) = shutdown.shutdown() {
+        drained.await
+    }
+}
@@ -0,0 +1,740 @@
+use crate::db::{self, split_tags, DishProp, EntityKind, RestaurantProp, Role};
+use std::collections::{HashMap, VecDeque};
+use teloxide::utils::command::ParseError;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ArgError {
+    UnclosedQuote(char),
+    TrailingEscape,
+}
+
+impl std::fmt::Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnclosedQuote(q) => write!(f, "missing closing quote {q}"),
+            Self::TrailingEscape => write!(f, "nothing to escape after the trailing \\"),
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// Arguments split from the command text in a shell-like way.
+///
+/// Words are separated by any amount of whitespace. Single and double quotes group words, a
+/// backslash escapes the next character, and a bare `key=value` word is stored as a flag. Write
+/// `"key=value"` with quotes to keep it positional.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct Args {
+    positional: VecDeque<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Args {
+    pub(super) fn parse(input: &str) -> Result<Self, ArgError> {
+        let mut args = Self::default();
+        let mut chars = input.chars();
+
+        // current word, and the flag key if an unquoted `=` was met in it
+        let mut word: Option<String> = None;
+        let mut key: Option<String> = None;
+        let mut quoted = false;
+
+        let mut finish = |word: &mut Option<String>, key: &mut Option<String>| {
+            if let Some(w) = word.take() {
+                match key.take() {
+                    Some(k) => {
+                        args.flags.insert(k, w);
+                    }
+                    None => args.positional.push_back(w),
+                }
+            }
+        };
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' | '\'' => {
+                    let buf = word.get_or_insert_with(String::new);
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some(end) if end == c => break,
+                            // only double quote handle escape, like what shell does
+                            Some('\\') if c == '"' => {
+                                buf.push(chars.next().ok_or(ArgError::TrailingEscape)?)
+                            }
+                            Some(ch) => buf.push(ch),
+                            None => return Err(ArgError::UnclosedQuote(c)),
+                        }
+                    }
+                }
+                '\\' => word
+                    .get_or_insert_with(String::new)
+                    .push(chars.next().ok_or(ArgError::TrailingEscape)?),
+                '=' if key.is_none() && !quoted && word.as_deref().is_some_and(is_flag_key) => {
+                    key = word.replace(String::new());
+                }
+                c if c.is_whitespace() => {
+                    finish(&mut word, &mut key);
+                    quoted = false;
+                }
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        finish(&mut word, &mut key);
+
+        Ok(args)
+    }
+
+    /// Take the next positional argument
+    pub(super) fn next(&mut self) -> Option<String> {
+        self.positional.pop_front()
+    }
+
+    /// Take all the remaining positional arguments, joined by single space
+    pub(super) fn rest(&mut self) -> Option<String> {
+        if self.positional.is_empty() {
+            return None;
+        }
+        let rest = self.positional.drain(..).collect::<Vec<_>>();
+        Some(rest.join(" "))
+    }
+
+    /// Take the value of the first present flag in `keys`
+    pub(super) fn flag(&mut self, keys: &[&str]) -> Option<String> {
+        keys.iter().find_map(|k| self.flags.remove(*k))
+    }
+}
+
+fn is_flag_key(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_id(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("{s} is not a valid number"))
+}
+
+/// Parse a price like `25.5` or `¥25.5`
+pub(super) fn parse_price(s: &str) -> Result<f64, String> {
+    match s.trim().trim_start_matches(['¥', '￥', '$']).parse::<f64>() {
+        Ok(price) if price.is_finite() && price >= 0.0 => Ok(price),
+        _ => Err(format!("{s} is not a valid price, send a number like 25.5")),
+    }
+}
+
+/// A command argument structure that can be completed interactively when some of the fields are
+/// missing from the command text.
+pub(super) trait Prompt {
+    /// Return the question for the first missing field, or None if every field is present
+    fn missing(&self) -> Option<&'static str>;
+    /// Fill the first missing field with user input
+    fn fill(&mut self, input: &str) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum RestArgs {
+    Add {
+        name: Option<String>,
+        address: Option<String>,
+        phone: Option<String>,
+        hours: Option<String>,
+        tags: Option<String>,
+    },
+    Search {
+        pattern: Option<String>,
+        /// Every one of them is required
+        tags: Vec<String>,
+    },
+    Edit {
+        id: Option<i64>,
+    },
+}
+
+impl RestArgs {
+    pub(super) const USAGE: &str = "Usage: /rest <add|search|edit> ...\n\n\
+        /rest add [name] [address] [phone=..] [hours=..] [tags=..]\n\
+        /rest search <pattern> [tags=..]\n\
+        /rest edit <id>\n\n\
+        Quote the argument that contains spaces, like: /rest add \"老乡鸡 光谷店\" 武汉";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let Some(action) = args.next() else {
+            return Err(Self::USAGE.to_string());
+        };
+
+        let parsed = match action.as_str() {
+            "add" => {
+                let name = args.flag(&["name"]).or_else(|| args.next());
+                let address = args.flag(&["addr", "address"]).or_else(|| args.rest());
+                Self::Add {
+                    name,
+                    address,
+                    phone: args.flag(&["phone"]),
+                    hours: args.flag(&["hours"]),
+                    tags: args.flag(&["tags"]),
+                }
+            }
+            "search" => Self::Search {
+                tags: args
+                    .flag(&["tags"])
+                    .map(|t| split_tags(&t))
+                    .unwrap_or_default(),
+                pattern: args.flag(&["pattern"]).or_else(|| args.rest()),
+            },
+            "edit" => {
+                let id = args.flag(&["id"]).or_else(|| args.next());
+                Self::Edit {
+                    id: id.as_deref().map(parse_id).transpose()?,
+                }
+            }
+            _ => return Err(format!("unexpected action {action}\n\n{}", Self::USAGE)),
+        };
+
+        Ok(parsed)
+    }
+}
+
+// The missing fields of `add` are asked by the restaurant creation wizard
+impl Prompt for RestArgs {
+    fn missing(&self) -> Option<&'static str> {
+        match self {
+            // searching by tags only is fine
+            Self::Search {
+                pattern: None,
+                tags,
+            } if tags.is_empty() => Some("Please send the pattern to search"),
+            Self::Edit { id: None } => Some("Please send the id of the restaurant"),
+            _ => None,
+        }
+    }
+
+    fn fill(&mut self, input: &str) -> Result<(), String> {
+        let input = input.trim();
+        match self {
+            Self::Search {
+                pattern: pattern @ None,
+                ..
+            } => *pattern = Some(input.to_string()),
+            Self::Edit { id: id @ None } => *id = Some(parse_id(input)?),
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ReviewArgs {
+    pub(super) dish: Option<DishProp>,
+    // restaurant scope for looking up the dish by name
+    restaurant: Option<i64>,
+}
+
+impl ReviewArgs {
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let restaurant = args.flag(&["rest", "restaurant"]);
+        let mut parsed = Self {
+            dish: None,
+            restaurant: restaurant.as_deref().map(parse_id).transpose()?,
+        };
+        if let Some(dish) = args.flag(&["dish"]).or_else(|| args.rest()) {
+            parsed.fill(&dish)?;
+        }
+        Ok(parsed)
+    }
+}
+
+impl Prompt for ReviewArgs {
+    fn missing(&self) -> Option<&'static str> {
+        self.dish
+            .is_none()
+            .then_some("Please send the id or the name of the dish you want to review")
+    }
+
+    fn fill(&mut self, input: &str) -> Result<(), String> {
+        let input = input.trim();
+        if self.dish.is_none() {
+            self.dish = Some(match input.parse() {
+                Ok(id) => DishProp::Id(id),
+                Err(_) => DishProp::Name {
+                    name: input.to_string(),
+                    restaurant: self.restaurant,
+                },
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct MergeArgs {
+    pub(super) kind: EntityKind,
+    pub(super) from: Option<i64>,
+    pub(super) into: Option<i64>,
+}
+
+impl MergeArgs {
+    pub(super) const USAGE: &str = "Usage: /merge <restaurant|dish> <from id> <into id>";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let Some(kind) = args.flag(&["kind"]).or_else(|| args.next()) else {
+            return Err(Self::USAGE.to_string());
+        };
+        let kind = kind
+            .parse()
+            .map_err(|e| format!("{e}\n\n{}", Self::USAGE))?;
+        let from = args.flag(&["from"]).or_else(|| args.next());
+        let into = args.flag(&["into"]).or_else(|| args.next());
+        Ok(Self {
+            kind,
+            from: from.as_deref().map(parse_id).transpose()?,
+            into: into.as_deref().map(parse_id).transpose()?,
+        })
+    }
+}
+
+impl Prompt for MergeArgs {
+    fn missing(&self) -> Option<&'static str> {
+        match (self.kind, self.from, self.into) {
+            (EntityKind::Restaurant, None, _) => {
+                Some("Please send the id of the duplicated restaurant")
+            }
+            (EntityKind::Restaurant, _, None) => {
+                Some("Please send the id of the restaurant to keep")
+            }
+            (EntityKind::Dish, None, _) => Some("Please send the id of the duplicated dish"),
+            (EntityKind::Dish, _, None) => Some("Please send the id of the dish to keep"),
+            _ => None,
+        }
+    }
+
+    fn fill(&mut self, input: &str) -> Result<(), String> {
+        if self.from.is_none() {
+            self.from = Some(parse_id(input)?);
+        } else if self.into.is_none() {
+            self.into = Some(parse_id(input)?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct RoleArgs {
+    pub(super) user: Option<i64>,
+    pub(super) role: Option<Role>,
+}
+
+impl RoleArgs {
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let user = args.flag(&["user"]).or_else(|| args.next());
+        let role = args.flag(&["role"]).or_else(|| args.next());
+        Ok(Self {
+            user: user.as_deref().map(parse_id).transpose()?,
+            role: role
+                .map(|r| r.parse())
+                .transpose()
+                .map_err(|e: anyhow::Error| e.to_string())?,
+        })
+    }
+}
+
+impl Prompt for RoleArgs {
+    fn missing(&self) -> Option<&'static str> {
+        match (self.user, self.role) {
+            (None, _) => Some("Please send the id of the user"),
+            (_, None) => Some("Please send the role: admin, editor, reviewer or banned"),
+            _ => None,
+        }
+    }
+
+    fn fill(&mut self, input: &str) -> Result<(), String> {
+        if self.user.is_none() {
+            self.user = Some(parse_id(input)?);
+        } else if self.role.is_none() {
+            self.role = Some(
+                input
+                    .trim()
+                    .parse()
+                    .map_err(|e: anyhow::Error| e.to_string())?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Chat id argument, None for the current chat
+#[derive(Debug, Clone)]
+pub(super) struct ChatArgs {
+    pub(super) chat: Option<i64>,
+}
+
+impl ChatArgs {
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let chat = args.flag(&["chat"]).or_else(|| args.next());
+        Ok(Self {
+            chat: chat.as_deref().map(parse_id).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct HistoryArgs {
+    pub(super) kind: EntityKind,
+    pub(super) id: i64,
+}
+
+impl HistoryArgs {
+    pub(super) const USAGE: &str =
+        "Usage: /history <restaurant|dish|review|reviewer|visit|exception> <id>, \
+        show the last changes of a row";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let kind = args.flag(&["kind"]).or_else(|| args.next());
+        let id = args.flag(&["id"]).or_else(|| args.next());
+        let (Some(kind), Some(id)) = (kind, id) else {
+            return Err(Self::USAGE.to_string());
+        };
+        Ok(Self {
+            kind: kind
+                .parse()
+                .map_err(|e| format!("{e}\n\n{}", Self::USAGE))?,
+            id: parse_id(&id)?,
+        })
+    }
+}
+
+/// Show the price of a dish, or report a new price when the amount is given
+#[derive(Debug, Clone)]
+pub(super) struct PriceArgs {
+    pub(super) dish: DishProp,
+    pub(super) amount: Option<f64>,
+    pub(super) currency: Option<String>,
+}
+
+impl PriceArgs {
+    pub(super) const USAGE: &str =
+        "Usage: /price <dish id or name> [rest=<restaurant id>] [amount=25.5] [currency=CNY]";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let restaurant = args.flag(&["rest", "restaurant"]);
+        let restaurant = restaurant.as_deref().map(parse_id).transpose()?;
+        let amount = args.flag(&["amount", "price"]);
+        let currency = args.flag(&["currency"]);
+        let Some(dish) = args.flag(&["dish"]).or_else(|| args.rest()) else {
+            return Err(Self::USAGE.to_string());
+        };
+        Ok(Self {
+            dish: match dish.parse() {
+                Ok(id) => DishProp::Id(id),
+                Err(_) => DishProp::Name {
+                    name: dish,
+                    restaurant,
+                },
+            },
+            amount: amount.as_deref().map(parse_price).transpose()?,
+            currency,
+        })
+    }
+}
+
+// parse date like 2023-01-08, today or yesterday
+fn parse_date(s: &str) -> Result<chrono::NaiveDate, String> {
+    let today = db::local_now().date();
+    match s.trim() {
+        "today" | "今天" => Ok(today),
+        "yesterday" | "昨天" => Ok(today.pred_opt().unwrap_or(today)),
+        s => s
+            .parse()
+            .map_err(|_| format!("{s} is not a valid date, send a date like 2023-01-08")),
+    }
+}
+
+/// Record a visit to the restaurant
+#[derive(Debug, Clone)]
+pub(super) struct VisitArgs {
+    pub(super) restaurant: Option<RestaurantProp>,
+    pub(super) date: Option<chrono::NaiveDate>,
+    pub(super) party_size: Option<u32>,
+    pub(super) spend: Option<f64>,
+}
+
+impl VisitArgs {
+    pub(super) const USAGE: &str = "Usage: /visited <restaurant id or name> \
+        [date=yesterday|2023-01-08] [party=3] [spend=120]";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let date = args.flag(&["date"]);
+        let party_size = args.flag(&["party", "people"]);
+        let spend = args.flag(&["spend"]);
+        let mut parsed = Self {
+            restaurant: None,
+            date: date.as_deref().map(parse_date).transpose()?,
+            party_size: party_size
+                .map(|p| match p.trim().parse() {
+                    Ok(size) if size > 0 => Ok(size),
+                    _ => Err(format!("{p} is not a valid party size")),
+                })
+                .transpose()?,
+            spend: spend.as_deref().map(parse_price).transpose()?,
+        };
+        if let Some(restaurant) = args.flag(&["rest", "restaurant"]).or_else(|| args.rest()) {
+            parsed.fill(&restaurant)?;
+        }
+        Ok(parsed)
+    }
+}
+
+impl Prompt for VisitArgs {
+    fn missing(&self) -> Option<&'static str> {
+        self.restaurant
+            .is_none()
+            .then_some("Please send the id or the name of the restaurant you have been to")
+    }
+
+    fn fill(&mut self, input: &str) -> Result<(), String> {
+        let input = input.trim();
+        if self.restaurant.is_none() {
+            self.restaurant = Some(match input.parse() {
+                Ok(id) => RestaurantProp::Id(id),
+                Err(_) => RestaurantProp::Name(input.to_string()),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Follow or unfollow a restaurant or a reviewer, list the followings when nothing is given
+#[derive(Debug, Clone)]
+pub(super) struct FollowArgs {
+    /// Kind of the target, and its id or name
+    pub(super) target: Option<(EntityKind, String)>,
+}
+
+impl FollowArgs {
+    pub(super) const USAGE: &str = "Usage: /follow <restaurant|reviewer> <id or name>, \
+        /unfollow with the same arguments, or /follow alone to list what you follow";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let Some(kind) = args.flag(&["kind"]).or_else(|| args.next()) else {
+            return Ok(Self { target: None });
+        };
+        let kind = match kind.parse() {
+            Ok(kind @ (EntityKind::Restaurant | EntityKind::Reviewer)) => kind,
+            _ => return Err(format!("can not follow {kind}\n\n{}", Self::USAGE)),
+        };
+        let Some(target) = args.rest() else {
+            return Err(Self::USAGE.to_string());
+        };
+        // telegram username is written with @
+        let target = target.trim_start_matches('@').to_string();
+        Ok(Self {
+            target: Some((kind, target)),
+        })
+    }
+}
+
+/// Weekly digest of the current chat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum DigestArgs {
+    /// Show the schedule
+    Show,
+    /// Post the digest of the last 7 days now
+    Now,
+    On {
+        weekday: chrono::Weekday,
+        hour: u32,
+    },
+    Off,
+}
+
+impl DigestArgs {
+    pub(super) const USAGE: &str = "Usage: /digest [now|on|off]\n\n\
+        /digest now: preview the digest of the last 7 days\n\
+        /digest on [weekday] [hour]: post it weekly to this chat, default monday 9\n\
+        /digest off: stop posting";
+
+    fn from_args(mut args: Args) -> Result<Self, String> {
+        let Some(action) = args.next() else {
+            return Ok(Self::Show);
+        };
+
+        let parsed = match action.as_str() {
+            "now" | "preview" => Self::Now,
+            "on" => {
+                let weekday = args.flag(&["weekday", "day"]).or_else(|| args.next());
+                let hour = args.flag(&["hour"]).or_else(|| args.next());
+                Self::On {
+                    weekday: match weekday {
+                        Some(day) => day
+                            .parse()
+                            .map_err(|_| format!("{day} is not a weekday\n\n{}", Self::USAGE))?,
+                        None => chrono::Weekday::Mon,
+                    },
+                    hour: match hour {
+                        Some(hour) => match hour.parse() {
+                            Ok(h) if h < 24 => h,
+                            _ => return Err(format!("{hour} is not an hour in range 0 - 23")),
+                        },
+                        None => 9,
+                    },
+                }
+            }
+            "off" => Self::Off,
+            _ => return Err(format!("unexpected action {action}\n\n{}", Self::USAGE)),
+        };
+
+        Ok(parsed)
+    }
+}
+
+/// Build the `parse_with` function for [`super::handlers::Commands`] from the `from_args`
+/// constructor of a typed argument structure.
+macro_rules! command_parser {
+    ($fn_name:ident, $ty:ty) => {
+        pub(super) fn $fn_name(input: String) -> Result<($ty,), ParseError> {
+            let args = Args::parse(&input).map_err(|e| ParseError::Custom(e.into()))?;
+            <$ty>::from_args(args)
+                .map(|parsed| (parsed,))
+                .map_err(|e| ParseError::Custom(e.into()))
+        }
+    };
+}
+
+command_parser!(parse_rest_args, RestArgs);
+command_parser!(parse_review_args, ReviewArgs);
+command_parser!(parse_merge_args, MergeArgs);
+command_parser!(parse_role_args, RoleArgs);
+command_parser!(parse_chat_args, ChatArgs);
+command_parser!(parse_history_args, HistoryArgs);
+command_parser!(parse_price_args, PriceArgs);
+command_parser!(parse_visit_args, VisitArgs);
+command_parser!(parse_follow_args, FollowArgs);
+command_parser!(parse_digest_args, DigestArgs);
+
+#[test]
+fn test_parse_args() {
+    let mut args =
+        Args::parse(r#"add  "老乡鸡 光谷店" 'a "b"' c\ d addr=武汉 "x=y" e\"f"#).unwrap();
+    assert_eq!(args.next().as_deref(), Some("add"));
+    assert_eq!(args.next().as_deref(), Some("老乡鸡 光谷店"));
+    assert_eq!(args.next().as_deref(), Some(r#"a "b""#));
+    assert_eq!(args.next().as_deref(), Some("c d"));
+    assert_eq!(args.next().as_deref(), Some("x=y"));
+    assert_eq!(args.next().as_deref(), Some(r#"e"f"#));
+    assert_eq!(args.next(), None);
+    assert_eq!(args.flag(&["address", "addr"]).as_deref(), Some("武汉"));
+
+    let mut args = Args::parse(r#"name="KFC Crazy Thursday" 1 2"#).unwrap();
+    assert_eq!(args.flag(&["name"]).as_deref(), Some("KFC Crazy Thursday"));
+    assert_eq!(args.rest().as_deref(), Some("1 2"));
+
+    assert_eq!(
+        Args::parse(r#"add "KFC"#),
+        Err(ArgError::UnclosedQuote('"'))
+    );
+    assert_eq!(Args::parse(r"add KFC\"), Err(ArgError::TrailingEscape));
+    assert_eq!(Args::parse("   ").unwrap(), Args::default());
+}
+
+#[test]
+fn test_rest_args_prompt() {
+    let add = RestArgs::from_args(Args::parse("add 老乡鸡 武汉 光谷 phone=123").unwrap()).unwrap();
+    assert!(add.missing().is_none());
+    assert!(matches!(
+        add,
+        RestArgs::Add { name: Some(n), address: Some(a), phone: Some(p), hours: None, .. }
+            if n == "老乡鸡" && a == "武汉 光谷" && p == "123"
+    ));
+
+    let mut search = RestArgs::from_args(Args::parse("search").unwrap()).unwrap();
+    assert_eq!(search.missing(), Some("Please send the pattern to search"));
+    search.fill(" KFC ").unwrap();
+    assert!(search.missing().is_none());
+    assert!(matches!(search, RestArgs::Search { pattern: Some(p), .. } if p == "KFC"));
+
+    let search = RestArgs::from_args(Args::parse("search tags=川菜,Spicy").unwrap()).unwrap();
+    assert!(search.missing().is_none());
+    assert!(matches!(
+        search,
+        RestArgs::Search { pattern: None, tags } if tags == ["川菜", "spicy"]
+    ));
+
+    let mut edit = RestArgs::from_args(Args::parse("edit").unwrap()).unwrap();
+    assert!(edit.fill("abc").is_err());
+    edit.fill("12").unwrap();
+    assert!(matches!(edit, RestArgs::Edit { id: Some(12) }));
+
+    assert!(RestArgs::from_args(Args::parse("").unwrap()).is_err());
+    assert!(RestArgs::from_args(Args::parse("edit abc").unwrap()).is_err());
+}
+
+#[test]
+fn test_review_args() {
+    let review = ReviewArgs::from_args(Args::parse("宫保鸡丁 rest=2").unwrap()).unwrap();
+    assert!(matches!(
+        review.dish,
+        Some(DishProp::Name { name, restaurant: Some(2) }) if name == "宫保鸡丁"
+    ));
+    let mut review = ReviewArgs::from_args(Args::parse("").unwrap()).unwrap();
+    review.fill("12").unwrap();
+    assert!(matches!(review.dish, Some(DishProp::Id(12))));
+}
+
+#[test]
+fn test_visit_args() {
+    let args =
+        VisitArgs::from_args(Args::parse("老乡鸡 光谷店 party=3 spend=¥120").unwrap()).unwrap();
+    assert!(args.missing().is_none());
+    assert!(matches!(
+        args.restaurant,
+        Some(RestaurantProp::Name(name)) if name == "老乡鸡 光谷店"
+    ));
+    assert_eq!(args.party_size, Some(3));
+    assert_eq!(args.spend, Some(120.0));
+    assert!(args.date.is_none());
+
+    let mut args = VisitArgs::from_args(Args::parse("date=2023-01-08").unwrap()).unwrap();
+    assert_eq!(args.date, "2023-01-08".parse().ok());
+    args.fill("12").unwrap();
+    assert!(matches!(args.restaurant, Some(RestaurantProp::Id(12))));
+
+    assert!(VisitArgs::from_args(Args::parse("KFC party=0").unwrap()).is_err());
+    assert!(VisitArgs::from_args(Args::parse("KFC date=01/08").unwrap()).is_err());
+}
+
+#[test]
+fn test_follow_args() {
+    let args = FollowArgs::from_args(Args::parse("reviewer @alice").unwrap()).unwrap();
+    assert!(matches!(
+        args.target,
+        Some((EntityKind::Reviewer, name)) if name == "alice"
+    ));
+    let args = FollowArgs::from_args(Args::parse("").unwrap()).unwrap();
+    assert!(args.target.is_none());
+    assert!(FollowArgs::from_args(Args::parse("dish 1").unwrap()).is_err());
+    assert!(FollowArgs::from_args(Args::parse("restaurant").unwrap()).is_err());
+}
+
+#[test]
+fn test_digest_args() {
+    let parse = |s: &str| DigestArgs::from_args(Args::parse(s).unwrap());
+    assert_eq!(parse(""), Ok(DigestArgs::Show));
+    assert_eq!(parse("now"), Ok(DigestArgs::Now));
+    assert_eq!(
+        parse("on fri 18"),
+        Ok(DigestArgs::On {
+            weekday: chrono::Weekday::Fri,
+            hour: 18
+        })
+    );
+    assert_eq!(
+        parse("on hour=8"),
+        Ok(DigestArgs::On {
+            weekday: chrono::Weekday::Mon,
+            hour: 8
+        })
+    );
+    assert!(parse("on someday").is_err());
+    assert!(parse("on mon 24").is_err());
+}
@@ -0,0 +1,48 @@
+use crate::ratelimit::{Limit, RateLimiter};
+use std::collections::HashSet;
+use std::time::Duration;
+use teloxide::types::UserId;
+
+const READ_LIMIT: Limit = Limit::new(30, Duration::from_secs(60));
+const WRITE_LIMIT: Limit = Limit::new(5, Duration::from_secs(60));
+
+/// Settings of the bot read from the environment
+#[derive(Debug, Default)]
+pub(super) struct BotConfig {
+    admins: HashSet<UserId>,
+    private_mode: bool,
+}
+
+impl BotConfig {
+    pub(super) fn from_env() -> Self {
+        // comma separated telegram user id
+        let admins = std::env::var("TGBOT_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok().map(UserId))
+            .collect();
+        // only serve the chats in allowlist
+        let private_mode = std::env::var("TGBOT_PRIVATE_MODE")
+            .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        Self {
+            admins,
+            private_mode,
+        }
+    }
+
+    /// Admins from the environment, they always have the admin role
+    pub(super) fn is_admin(&self, user: UserId) -> bool {
+        self.admins.contains(&user)
+    }
+
+    pub(super) fn private_mode(&self) -> bool {
+        self.private_mode
+    }
+}
+
+/// Budget of every user from `TGBOT_READ_LIMIT` and `TGBOT_WRITE_LIMIT`, eg. `5/min`. Every update
+/// counts as a read, and the commands adding data count as a write too.
+pub(super) fn rate_limiter() -> RateLimiter<UserId> {
+    RateLimiter::from_env("TGBOT", READ_LIMIT, WRITE_LIMIT)
+}
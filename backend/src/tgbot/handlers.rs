@@ -0,0 +1,1344 @@
+use super::args::{
+    self, ChatArgs, DigestArgs, FollowArgs, HistoryArgs, MergeArgs, PriceArgs, Prompt, RestArgs,
+    ReviewArgs, RoleArgs, VisitArgs,
+};
+use super::metrics::{self, DialogueStorage};
+use crate::db::{self, Permission, Pool};
+use crate::ratelimit::{RateLimiter, RequestClass};
+use anyhow::Context;
+use auth::Caller;
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{Me, Message},
+    utils::command::{BotCommands, ParseError},
+    Bot,
+};
+
+macro_rules! send {
+    ([$bot:expr, $msg:expr], $text:expr) => {
+        if let Err(e) = $bot.send_message($msg.chat.id, $text).await {
+            tracing::error!("fail to send message: {e}")
+        }
+    };
+}
+
+mod admin;
+mod auth;
+mod digest;
+mod follow;
+mod restaurant_wizard;
+mod review;
+mod tags;
+
+#[derive(Debug, Default, Clone)]
+pub(super) enum ChatState {
+    #[default]
+    None,
+    CreatingDishesStage1(i64),
+    CreatingDishPrice(i64, String),
+    CreatingDisheFinal(i64, String, Option<f64>),
+    CreatingReview(review::ReviewDraft),
+    EditingRstName(i64),
+    EditingRstAddr(i64),
+    EditingRstHours(i64),
+    EditingTags(db::EntityKind, i64),
+    AwaitingArgs(PendingCommand),
+    CreatingRestaurant(restaurant_wizard::RestaurantDraft),
+}
+
+impl ChatState {
+    /// Name of the variant, the label of the dialogue metrics
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::CreatingDishesStage1(_) => "CreatingDishesStage1",
+            Self::CreatingDishPrice(..) => "CreatingDishPrice",
+            Self::CreatingDisheFinal(..) => "CreatingDisheFinal",
+            Self::CreatingReview(_) => "CreatingReview",
+            Self::EditingRstName(_) => "EditingRstName",
+            Self::EditingRstAddr(_) => "EditingRstAddr",
+            Self::EditingRstHours(_) => "EditingRstHours",
+            Self::EditingTags(..) => "EditingTags",
+            Self::AwaitingArgs(_) => "AwaitingArgs",
+            Self::CreatingRestaurant(_) => "CreatingRestaurant",
+        }
+    }
+}
+
+/// A command that is waiting for the user to send its missing arguments
+#[derive(Debug, Clone)]
+pub(super) enum PendingCommand {
+    Rest(RestArgs),
+    Review(ReviewArgs),
+    Merge(MergeArgs),
+    Grant(RoleArgs),
+    Visited(VisitArgs),
+}
+
+impl PendingCommand {
+    fn prompt(&mut self) -> &mut dyn Prompt {
+        match self {
+            Self::Rest(args) => args,
+            Self::Review(args) => args,
+            Self::Merge(args) => args,
+            Self::Grant(args) => args,
+            Self::Visited(args) => args,
+        }
+    }
+}
+
+type Dialogue = teloxide::prelude::Dialogue<ChatState, DialogueStorage>;
+
+#[derive(BotCommands, Clone, Debug)]
+#[command(
+    rename_rule = "lowercase",
+    description = "Commands for operating the database"
+)]
+enum Commands {
+    #[command(description = "Display this help page")]
+    Help,
+    #[command(
+        description = "Operate the restaurant",
+        parse_with = args::parse_rest_args
+    )]
+    Rest(RestArgs),
+    #[command(description = "Operate on dish")]
+    Dish,
+    #[command(
+        description = "Operate the review",
+        parse_with = args::parse_review_args
+    )]
+    Review(ReviewArgs),
+    #[command(description = "List possibly duplicated restaurants and dishes (admin)")]
+    Duplicates,
+    #[command(
+        description = "Merge duplicated restaurants or dishes (admin)",
+        parse_with = args::parse_merge_args
+    )]
+    Merge(MergeArgs),
+    #[command(description = "Show your user id and role")]
+    Whoami,
+    #[command(description = "Show your reviewer profile and statistics")]
+    Me,
+    #[command(description = "Create a token for the mutating API routes, private chat only")]
+    Token,
+    #[command(
+        description = "Grant role to a user: /grant <user id> <role> (admin)",
+        parse_with = args::parse_role_args
+    )]
+    Grant(RoleArgs),
+    #[command(
+        description = "Reset a user to reviewer and remove its API tokens (admin)",
+        parse_with = args::parse_role_args
+    )]
+    Revoke(RoleArgs),
+    #[command(
+        description = "Allow a chat to use the bot in private mode, default current chat (admin)",
+        parse_with = args::parse_chat_args
+    )]
+    Allow(ChatArgs),
+    #[command(
+        description = "Remove a chat from the allowlist, default current chat (admin)",
+        parse_with = args::parse_chat_args
+    )]
+    Disallow(ChatArgs),
+    #[command(
+        description = "Show the price trend of a dish, or report its price with amount=..",
+        parse_with = args::parse_price_args
+    )]
+    Price(PriceArgs),
+    #[command(
+        description = "Record a visit: /visited <restaurant> [date=..] [party=..] [spend=..]",
+        parse_with = args::parse_visit_args
+    )]
+    Visited(VisitArgs),
+    #[command(
+        description = "Follow a restaurant or reviewer to get its news in private chat",
+        parse_with = args::parse_follow_args
+    )]
+    Follow(FollowArgs),
+    #[command(
+        description = "Stop following a restaurant or reviewer",
+        parse_with = args::parse_follow_args
+    )]
+    Unfollow(FollowArgs),
+    #[command(
+        description = "Preview the weekly digest, or post it weekly to this chat: /digest [now|on|off]",
+        parse_with = args::parse_digest_args
+    )]
+    Digest(DigestArgs),
+    #[command(
+        description = "Show the last changes of a restaurant, dish, review or reviewer",
+        parse_with = args::parse_history_args
+    )]
+    History(HistoryArgs),
+}
+
+impl Commands {
+    /// The commands adding data take the write budget too
+    fn class(&self) -> RequestClass {
+        match self {
+            Self::Rest(RestArgs::Add { .. }) | Self::Review(_) | Self::Visited(_) | Self::Token => {
+                RequestClass::Write
+            }
+            Self::Price(args) if args.amount.is_some() => RequestClass::Write,
+            _ => RequestClass::Read,
+        }
+    }
+}
+
+async fn throttle_command(
+    bot: Bot,
+    msg: Message,
+    cmd: Commands,
+    caller: Caller,
+    limiter: Arc<RateLimiter<UserId>>,
+) -> bool {
+    match cmd.class() {
+        // counted when the update came in
+        RequestClass::Read => true,
+        class => auth::within_budget(&bot, Some(msg.chat.id), &caller, &limiter, class).await,
+    }
+}
+
+pub(super) fn handler_schema() -> teloxide::dispatching::UpdateHandler<anyhow::Error> {
+    use dptree::case;
+    let command_handler = teloxide::filter_command::<Commands, _>()
+        .filter_async(throttle_command)
+        .branch(case![Commands::Rest(args)].endpoint(restaurant_handler))
+        .branch(case![Commands::Review(args)].endpoint(cmd_review_handler))
+        .branch(case![Commands::Duplicates].endpoint(admin::duplicates_handler))
+        .branch(case![Commands::Merge(args)].endpoint(admin::merge_handler))
+        .branch(case![Commands::Whoami].endpoint(whoami_handler))
+        .branch(case![Commands::Me].endpoint(me_handler))
+        .branch(case![Commands::Token].endpoint(token_handler))
+        .branch(case![Commands::History(args)].endpoint(history_handler))
+        .branch(case![Commands::Price(args)].endpoint(price_handler))
+        .branch(case![Commands::Visited(args)].endpoint(visited_handler))
+        .branch(case![Commands::Digest(args)].endpoint(digest::digest_handler))
+        .branch(case![Commands::Grant(args)].endpoint(admin::grant_handler))
+        .branch(case![Commands::Revoke(args)].endpoint(admin::revoke_handler))
+        .branch(
+            case![Commands::Allow(args)]
+                .map(|| true)
+                .endpoint(admin::allowlist_handler),
+        )
+        .branch(
+            case![Commands::Disallow(args)]
+                .map(|| false)
+                .endpoint(admin::allowlist_handler),
+        )
+        .branch(
+            case![Commands::Follow(args)]
+                .map(|| true)
+                .endpoint(follow::follow_handler),
+        )
+        .branch(
+            case![Commands::Unfollow(args)]
+                .map(|| false)
+                .endpoint(follow::follow_handler),
+        )
+        .branch(
+            case![Commands::Help].endpoint(|msg: Message, bot: Bot| async move {
+                send!([bot, msg], Commands::descriptions().to_string());
+                Ok(())
+            }),
+        );
+
+    let message_handler = Update::filter_message()
+        .branch(case![ChatState::EditingRstName(_name)].endpoint(edit_restaurant_name_handler))
+        .branch(case![ChatState::EditingRstAddr(_a)].endpoint(edit_restaurant_address_handler))
+        .branch(case![ChatState::EditingRstHours(_a)].endpoint(edit_restaurant_hours_handler))
+        .branch(case![ChatState::CreatingDishesStage1(_a)].endpoint(add_dish_stage1_handler))
+        .branch(case![ChatState::CreatingDishPrice(_a, _b)].endpoint(add_dish_price_handler))
+        .branch(case![ChatState::CreatingDisheFinal(_a, _b, _c)].endpoint(add_dish_final_handler))
+        .branch(case![ChatState::CreatingReview(_a)].endpoint(review::message_handler))
+        .branch(case![ChatState::EditingTags(_a, _b)].endpoint(tags::message_handler))
+        .branch(case![ChatState::AwaitingArgs(_a)].endpoint(pending_command_handler))
+        .branch(
+            case![ChatState::CreatingRestaurant(_a)].endpoint(restaurant_wizard::message_handler),
+        )
+        .branch(command_handler)
+        .branch(dptree::endpoint(invalid_command_handler));
+
+    let callback_handler = Update::filter_callback_query().endpoint(callback_dispatcher);
+
+    let dialogue_handler =
+        teloxide::dispatching::dialogue::enter::<Update, DialogueStorage, ChatState, _>()
+            .branch(callback_handler)
+            .branch(message_handler);
+
+    // every update must come from a known caller within its budget, the handlers then check its
+    // permission
+    dptree::entry()
+        .inspect(metrics::count_update)
+        .filter_map_async(auth::identify)
+        .filter_async(auth::throttle)
+        .chain(dialogue_handler)
+}
+
+struct BtnPrefix;
+impl BtnPrefix {
+    const RESTAURANT: &str = "RSTBTN";
+    const UPDATE_RESTAURANT: &str = "RSTUPDBTN";
+    const NEW_RESTAURANT: &str = "RSTNEW";
+    const MERGE: &str = "MERGE";
+    const REVIEW_DISH: &str = "REVDISH";
+    const REVIEW: &str = "REVIEW";
+    const TAG: &str = "TAG";
+}
+
+struct RstBtnAction;
+impl RstBtnAction {
+    const UPDATE: &str = "update";
+    const ADD: &str = "new_dishes";
+    const LIST: &str = "list_dishes";
+    const DEL: &str = "delete";
+    const TAGS: &str = "tags";
+}
+
+enum AddRestaurantAction {
+    Search(Option<String>, Vec<String>),
+    Edit(i64),
+}
+impl AddRestaurantAction {
+    // Return None when the arguments are not completed yet
+    fn new(args: RestArgs) -> Option<Self> {
+        match args {
+            RestArgs::Search { pattern, tags } if pattern.is_some() || !tags.is_empty() => {
+                Some(Self::Search(pattern, tags))
+            }
+            RestArgs::Edit { id: Some(id) } => Some(Self::Edit(id)),
+            _ => None,
+        }
+    }
+
+    // consumed the action
+    async fn run(self, msg: &Message, bot: &Bot, pool: &Pool) -> anyhow::Result<()> {
+        match self {
+            Self::Search(pattern, tags) => {
+                let mut rests = match pattern {
+                    Some(pattern) => db::search_restaurant(pool, &pattern).await?,
+                    None => db::get_restaurant(pool, db::RestaurantSearchProps::All).await?,
+                };
+                rests.retain(|r| db::has_tags(r.tags.as_deref(), &tags));
+                let hours = db::all_opening_hours(pool).await?;
+                let now = db::local_now();
+                let result = if rests.is_empty() {
+                    String::from("No restaurant found")
+                } else {
+                    rests.into_iter().fold(String::new(), |sumed, unit| {
+                        let tags = unit.tags.map(|t| format!(" [{t}]")).unwrap_or_default();
+                        let status = match hours.get(&unit.id).map(|h| h.status_at(now)) {
+                            Some(db::OpenStatus::Unknown) | None => String::new(),
+                            Some(status) => format!(" ({status})"),
+                        };
+                        format!(
+                            "{sumed}\n{}. {} {}{tags}{status}",
+                            unit.id, unit.name, unit.address
+                        )
+                    })
+                };
+                send!([bot, msg], result);
+            }
+            //
+            Self::Edit(id) => {
+                let rest = db::get_restaurant(pool, db::RestaurantSearchProps::Id(id)).await?;
+                if rest.is_empty() {
+                    send!([bot, msg], "Incorrect id, no restaurant found");
+                    return Ok(());
+                }
+                send_restaurant_menu(bot, msg.chat.id, pool, &rest[0]).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn send_restaurant_menu(
+    bot: &Bot,
+    chat: ChatId,
+    pool: &Pool,
+    rest: &db::Restaurant,
+) -> anyhow::Result<()> {
+    // build the callback data by "{category}-{id}-{action}"
+    let cbd = |action: &str| format!("{}-{}-{action}", BtnPrefix::RESTAURANT, rest.id);
+    let btn = teloxide::types::InlineKeyboardButton::callback;
+    let buttons = vec![
+        vec![
+            btn("Update Restaurant", cbd(RstBtnAction::UPDATE)),
+            btn("New Dish", cbd(RstBtnAction::ADD)),
+        ],
+        vec![
+            btn("List Dishes", cbd(RstBtnAction::LIST)),
+            btn("Tags", cbd(RstBtnAction::TAGS)),
+        ],
+        vec![btn("Delete", cbd(RstBtnAction::DEL))],
+    ];
+    let markup = teloxide::types::InlineKeyboardMarkup::new(buttons);
+
+    let tags = rest
+        .tags
+        .as_deref()
+        .map(|t| format!("\nTags: {t}"))
+        .unwrap_or_default();
+    let hours = db::opening_hours(pool, rest.id).await?;
+    let hours = match (&rest.opening_hours, hours.status_at(db::local_now())) {
+        (Some(text), db::OpenStatus::Unknown) => format!("\nHours: {text}"),
+        (Some(text), status) => format!("\nHours: {text} ({status})"),
+        (None, _) => String::new(),
+    };
+    bot.send_message(
+        chat,
+        format!(
+            "List of operation for: \n\n{} {}{hours}{tags}",
+            rest.name, rest.address
+        ),
+    )
+    .reply_markup(markup)
+    .await?;
+
+    Ok(())
+}
+
+async fn restaurant_handler(
+    msg: Message,
+    bot: Bot,
+    pool: Pool,
+    dialogue: Dialogue,
+    caller: Caller,
+    args: RestArgs,
+) -> anyhow::Result<()> {
+    let permission = match args {
+        RestArgs::Add { .. } => Permission::Create,
+        RestArgs::Search { .. } => Permission::Read,
+        // the menu holds the reading buttons too, each button checks its own permission
+        RestArgs::Edit { .. } => Permission::Read,
+    };
+    if !caller.permit(&bot, msg.chat.id, permission).await {
+        return Ok(());
+    }
+
+    if let Some(question) = args.missing() {
+        send!([bot, msg], format!("{question}, or /cancel"));
+        dialogue
+            .update(ChatState::AwaitingArgs(PendingCommand::Rest(args)))
+            .await?;
+        return Ok(());
+    }
+
+    if let RestArgs::Add {
+        name,
+        address,
+        phone,
+        hours,
+        tags,
+    } = args
+    {
+        match restaurant_wizard::RestaurantDraft::new(name, address, phone, hours, tags) {
+            Ok(draft) => {
+                return restaurant_wizard::start(&bot, &msg, &dialogue, &pool, draft).await
+            }
+            Err(e) => {
+                send!([bot, msg], format!("{e:#}, please retry the command"));
+                return Ok(());
+            }
+        }
+    }
+
+    let Some(action) = AddRestaurantAction::new(args) else {
+        return Ok(());
+    };
+    if let Err(e) = action.run(&msg, &bot, &pool).await {
+        send!(
+            [bot, msg],
+            format!("Fail to take action on restaurant: {e}")
+        );
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+async fn pending_command_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    mut pending: PendingCommand,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    let Some(text) = msg.text() else {
+        send!([bot, msg], "Please send text message, or /cancel");
+        return Ok(());
+    };
+
+    if text.contains("/cancel") {
+        dialogue.exit().await?;
+        send!([bot, msg], "Process cancelled");
+        return Ok(());
+    }
+
+    if let Err(hint) = pending.prompt().fill(text) {
+        send!([bot, msg], format!("{hint}, please retry or /cancel"));
+        return Ok(());
+    }
+
+    // the following handlers will update the dialogue when there are still missing fields
+    dialogue.exit().await?;
+    match pending {
+        PendingCommand::Rest(args) => {
+            restaurant_handler(msg, bot, pool, dialogue, caller, args).await
+        }
+        PendingCommand::Review(args) => {
+            cmd_review_handler(bot, msg, dialogue, args, pool, caller).await
+        }
+        PendingCommand::Merge(args) => {
+            admin::merge_handler(bot, msg, dialogue, pool, caller, args).await
+        }
+        PendingCommand::Grant(args) => {
+            admin::grant_handler(bot, msg, dialogue, pool, caller, args).await
+        }
+        PendingCommand::Visited(args) => {
+            visited_handler(bot, msg, dialogue, pool, caller, args).await
+        }
+    }
+}
+
+// Reply the reason when the message looks like a command but its arguments can not be parsed
+async fn invalid_command_handler(bot: Bot, msg: Message, me: Me) -> anyhow::Result<()> {
+    let Some(text) = msg.text() else {
+        return Ok(());
+    };
+    if !text.starts_with('/') {
+        return Ok(());
+    }
+
+    if let Err(ParseError::Custom(e)) = Commands::parse(text, me.username()) {
+        send!([bot, msg], e.to_string());
+    }
+
+    Ok(())
+}
+
+async fn edit_restaurant_name_handler(
+    msg: Message,
+    bot: Bot,
+    dialogue: Dialogue,
+    rid: i64,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let Some(text) = msg.text() else {
+        send!(
+            [bot, msg],
+            "Text name is required, please resend a valid restaurant name, or /cancel."
+        );
+        return Ok(());
+    };
+
+    if text.contains("/cancel") {
+        send!([bot, msg], "Process cancelled");
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    db::update_restaurant(
+        &pool,
+        caller.actor(),
+        rid,
+        db::UpdateRestaurantProps::UpdateName(text.to_string()),
+    )
+    .await?;
+
+    send!([bot, msg], format!("Restaurant name is changed to {text}"));
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+async fn edit_restaurant_address_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    dialogue: Dialogue,
+    rid: i64,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let Some(text) = msg.text() else {
+        send!(
+            [bot, msg],
+            "Required text message, please resend a valid restaurant address, or /cancel."
+        );
+        return Ok(());
+    };
+
+    if text.contains("/cancel") {
+        send!([bot, msg], "Process cancelled");
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    db::update_restaurant(
+        &pool,
+        caller.actor(),
+        rid,
+        db::UpdateRestaurantProps::UpdateAddr(text.to_string()),
+    )
+    .await?;
+
+    send!([bot, msg], format!("Restaurant name is changed to {text}"));
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+async fn edit_restaurant_hours_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    dialogue: Dialogue,
+    rid: i64,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let Some(text) = msg.text() else {
+        send!(
+            [bot, msg],
+            "Required text message, please resend the opening hours, or /cancel."
+        );
+        return Ok(());
+    };
+
+    if text.contains("/cancel") {
+        send!([bot, msg], "Process cancelled");
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let hours = Some(text).filter(|t| !t.trim().eq_ignore_ascii_case("none"));
+    match db::set_opening_hours(&pool, caller.actor(), rid, hours).await {
+        Ok(Some(hours)) => send!([bot, msg], format!("Opening hours are changed to {hours}")),
+        Ok(None) => send!([bot, msg], "Opening hours are removed"),
+        Err(e) => {
+            send!([bot, msg], format!("{e:#}, please retry or /cancel"));
+            return Ok(());
+        }
+    }
+    dialogue.exit().await?;
+
+    Ok(())
+}
+
+async fn callback_dispatcher(
+    bot: Bot,
+    query: CallbackQuery,
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    // just silently exit
+    let Some(data) = query.data else {
+        return Ok(());
+    };
+    let callback_action = data.split('-').collect::<Vec<_>>();
+    if callback_action.is_empty() {
+        anyhow::bail!("Get callback action without data")
+    }
+    // we don't handle inline query, so there must be a message
+    let Some(message) = query.message else {
+        return Ok(());
+    };
+    match callback_action[0] {
+        // This callback format is PREFIX-id-action
+        BtnPrefix::RESTAURANT => {
+            if callback_action.len() != 3 {
+                return Ok(());
+            }
+            let id: i64 = callback_action[1]
+                .parse()
+                .with_context(|| {
+                    format!("[RSTBTN dispatcher] original format: {callback_action:?}")
+                })
+                .expect("Met unexpected callback format, please check");
+
+            rst_cb_handler(
+                bot,
+                message,
+                id,
+                callback_action[2],
+                &dialogue,
+                &pool,
+                &caller,
+            )
+            .await?;
+        }
+        BtnPrefix::UPDATE_RESTAURANT => {
+            if callback_action.len() != 3 {
+                anyhow::bail!("invalid callback action data")
+            }
+            let id: i64 = callback_action[1]
+                .parse()
+                .with_context(|| {
+                    format!("[RSTBTN dispatcher] original format: {callback_action:?}")
+                })
+                .expect("Met unexpected callback format, please check");
+            rstupd_cb_handler(
+                bot,
+                message,
+                id,
+                callback_action[2],
+                &dialogue,
+                &pool,
+                &caller,
+            )
+            .await?;
+        }
+        BtnPrefix::REVIEW_DISH => {
+            let [_, id, RevBtnAction::PICK] = callback_action.as_slice() else {
+                anyhow::bail!("invalid callback action data")
+            };
+            let id: i64 = id.parse()?;
+            if !caller
+                .permit(&bot, message.chat.id, Permission::Review)
+                .await
+            {
+                return Ok(());
+            }
+            bot.edit_message_reply_markup(message.chat.id, message.id)
+                .await?;
+            review::start(&bot, message.chat.id, &dialogue, &pool, caller.db_id(), id).await?;
+        }
+        BtnPrefix::REVIEW => {
+            review::callback_handler(
+                bot,
+                message,
+                &callback_action[1..],
+                &dialogue,
+                &pool,
+                &caller,
+            )
+            .await?;
+        }
+        BtnPrefix::TAG => {
+            tags::callback_handler(
+                bot,
+                message,
+                &callback_action[1..],
+                &dialogue,
+                &pool,
+                &caller,
+            )
+            .await?;
+        }
+        BtnPrefix::MERGE => {
+            admin::merge_cb_handler(bot, message, &caller, &callback_action[1..], &pool).await?;
+        }
+        BtnPrefix::NEW_RESTAURANT => {
+            restaurant_wizard::callback_handler(
+                bot,
+                message,
+                &callback_action[1..],
+                &dialogue,
+                &pool,
+                &caller,
+            )
+            .await?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+struct RstBtnUpdActionBtn;
+impl RstBtnUpdActionBtn {
+    const NAME: &str = "name";
+    const ADDR: &str = "address";
+    const HOURS: &str = "hours";
+    const DEL_CONFIRM: &str = "delete_confirm";
+    const DEL_CANCEL: &str = "delete_cancel";
+}
+
+async fn rst_cb_handler(
+    bot: Bot,
+    msg: Message,
+    rst_id: i64,
+    action: &str,
+    dialogue: &Dialogue,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    let permission = match action {
+        RstBtnAction::UPDATE | RstBtnAction::TAGS => Permission::Edit,
+        RstBtnAction::ADD => Permission::Create,
+        RstBtnAction::DEL => Permission::Delete,
+        _ => Permission::Read,
+    };
+    if !caller.permit(&bot, msg.chat.id, permission).await {
+        return Ok(());
+    }
+
+    match action {
+        RstBtnAction::UPDATE => {
+            let new_text = "What you want to do with this restaurant";
+            let cbd = |field: &str| format!("{}-{rst_id}-{field}", BtnPrefix::UPDATE_RESTAURANT);
+            let btn = teloxide::types::InlineKeyboardButton::callback;
+            let buttons = vec![
+                btn("Update Name", cbd(RstBtnUpdActionBtn::NAME)),
+                btn("Update Address", cbd(RstBtnUpdActionBtn::ADDR)),
+                btn("Update Hours", cbd(RstBtnUpdActionBtn::HOURS)),
+            ];
+            let new_markup = teloxide::types::InlineKeyboardMarkup::default().append_row(buttons);
+            bot.edit_message_text(msg.chat.id, msg.id, new_text)
+                .reply_markup(new_markup)
+                .await?;
+        }
+        RstBtnAction::ADD => {
+            send!([bot, msg], "Please send the name of the dish");
+            dialogue
+                .update(ChatState::CreatingDishesStage1(rst_id))
+                .await?;
+        }
+        RstBtnAction::LIST => {
+            let dishes = db::get_dish(pool, rst_id, None).await?;
+            if dishes.is_empty() {
+                send!([bot, msg], "No dishes found");
+                return Ok(());
+            }
+            let text = dishes.iter().fold(String::new(), |sum, now| {
+                let price = match (now.price, &now.currency) {
+                    (Some(price), Some(currency)) => format!(" {price} {currency}"),
+                    _ => String::new(),
+                };
+                let tags = now
+                    .tags
+                    .as_deref()
+                    .map(|t| format!(" [{t}]"))
+                    .unwrap_or_default();
+                format!("{sum}* {} {}{price}{tags}\n", now.id, now.name)
+            });
+            // one button per dish for editing its tags
+            let buttons = dishes.iter().map(|d| {
+                vec![teloxide::types::InlineKeyboardButton::callback(
+                    format!("Tags of {}", d.name),
+                    tags::open_cbd(db::EntityKind::Dish, d.id),
+                )]
+            });
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(teloxide::types::InlineKeyboardMarkup::new(buttons))
+                .await?;
+        }
+        RstBtnAction::TAGS => {
+            tags::send_editor(&bot, msg.chat.id, pool, db::EntityKind::Restaurant, rst_id).await?;
+        }
+        RstBtnAction::DEL => {
+            let cbd = |field: &str| format!("{}-{rst_id}-{field}", BtnPrefix::UPDATE_RESTAURANT);
+            let btn = teloxide::types::InlineKeyboardButton::callback;
+            let buttons = vec![
+                btn("Confirm", cbd(RstBtnUpdActionBtn::DEL_CONFIRM)),
+                btn("Cancel", cbd(RstBtnUpdActionBtn::DEL_CANCEL)),
+            ];
+            let new_markup = teloxide::types::InlineKeyboardMarkup::default().append_row(buttons);
+            bot.edit_message_text(msg.chat.id, msg.id, "Delete this restaurant?")
+                .reply_markup(new_markup)
+                .await?;
+        }
+        _ => panic!("Unexpected action {action} present, please check your code"),
+    }
+    Ok(())
+}
+
+async fn rstupd_cb_handler(
+    bot: Bot,
+    msg: Message,
+    rid: i64,
+    field: &str,
+    dialogue: &Dialogue,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    let permission = match field {
+        RstBtnUpdActionBtn::DEL_CONFIRM => Permission::Delete,
+        RstBtnUpdActionBtn::DEL_CANCEL => Permission::Read,
+        _ => Permission::Edit,
+    };
+    if !caller.permit(&bot, msg.chat.id, permission).await {
+        return Ok(());
+    }
+
+    match field {
+        RstBtnUpdActionBtn::NAME => {
+            send!(
+                [bot, msg],
+                "Please send the new name, press /cancel to cancel"
+            );
+            dialogue.update(ChatState::EditingRstName(rid)).await?;
+        }
+        RstBtnUpdActionBtn::ADDR => {
+            send!(
+                [bot, msg],
+                "Please send the new address, press /cancel to cancel"
+            );
+            dialogue.update(ChatState::EditingRstAddr(rid)).await?;
+        }
+        RstBtnUpdActionBtn::HOURS => {
+            send!(
+                [bot, msg],
+                "Please send the opening hours, like \"10:00-22:00, Mon closed\", \
+                or \"none\" to remove them. Press /cancel to cancel"
+            );
+            dialogue.update(ChatState::EditingRstHours(rid)).await?;
+        }
+        RstBtnUpdActionBtn::DEL_CONFIRM => {
+            let props = db::UpdateRestaurantProps::Delete;
+            let text = match db::update_restaurant(pool, caller.actor(), rid, props).await {
+                Ok(()) => String::from("Restaurant deleted"),
+                // dishes are still referring to it
+                Err(e) => format!("Fail to delete, remove or merge its dishes first: {e:#}"),
+            };
+            bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+        }
+        RstBtnUpdActionBtn::DEL_CANCEL => {
+            bot.edit_message_text(msg.chat.id, msg.id, "Delete cancelled")
+                .await?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+async fn add_dish_stage1_handler(
+    bot: Bot,
+    msg: Message,
+    rid: i64,
+    dialogue: Dialogue,
+) -> anyhow::Result<()> {
+    let Some(text) = msg.text() else {
+        send!([bot, msg], "Please send text message");
+        return Ok(());
+    };
+
+    if text.contains("/cancel") {
+        dialogue.exit().await?;
+        send!([bot, msg], "Cancelled");
+        return Ok(());
+    }
+
+    send!(
+        [bot, msg],
+        format!("How much is {text}? Please send the price, or just click /skip")
+    );
+    dialogue
+        .update(ChatState::CreatingDishPrice(rid, text.to_string()))
+        .await?;
+    Ok(())
+}
+
+async fn add_dish_price_handler(
+    bot: Bot,
+    msg: Message,
+    stage1: (i64, String),
+    dialogue: Dialogue,
+) -> anyhow::Result<()> {
+    let text = msg.text().unwrap_or_default();
+    if text.contains("/cancel") {
+        dialogue.exit().await?;
+        send!([bot, msg], "Cancelled");
+        return Ok(());
+    }
+
+    let price = if text.contains("/skip") {
+        None
+    } else {
+        match args::parse_price(text) {
+            Ok(price) => Some(price),
+            Err(e) => {
+                send!([bot, msg], format!("{e}, or /skip"));
+                return Ok(());
+            }
+        }
+    };
+
+    send!(
+        [bot, msg],
+        format!(
+            "{} created, please send a picture, or just click /skip",
+            stage1.1
+        )
+    );
+    dialogue
+        .update(ChatState::CreatingDisheFinal(stage1.0, stage1.1, price))
+        .await?;
+    Ok(())
+}
+
+async fn add_dish_final_handler(
+    bot: Bot,
+    msg: Message,
+    stage1: (i64, String, Option<f64>),
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Create).await {
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let mut skipped = false;
+    if let Some(text) = msg.text() {
+        if text.contains("/skip") {
+            skipped = true;
+        } else {
+            send!([bot, msg], "Need Image, not text, /skip ?");
+            return Ok(());
+        }
+    }
+
+    let image = if skipped {
+        None
+    } else {
+        let Some(images) = msg.photo() else {
+            send!([bot, msg], "Need images, please retry or /skip");
+            return Ok(());
+        };
+
+        if images.len() > 1 {
+            send!(
+                [bot, msg],
+                "Multiple images detected, choosing the first one"
+            );
+        }
+
+        Some(images[0].file.id.clone())
+    };
+
+    let price = stage1.2.map(|price| (price, None));
+    db::add_dish_with_price(&pool, caller.actor(), stage1.0, &stage1.1, image, price).await?;
+
+    dialogue.exit().await?;
+
+    send!([bot, msg], "Dish added");
+    Ok(())
+}
+
+struct RevBtnAction;
+impl RevBtnAction {
+    const PICK: &str = "pick";
+}
+
+async fn cmd_review_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    args: ReviewArgs,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Review).await {
+        return Ok(());
+    }
+
+    let Some(dish) = &args.dish else {
+        send!(
+            [bot, msg],
+            format!("{}, or /cancel", args.missing().unwrap_or_default())
+        );
+        dialogue
+            .update(ChatState::AwaitingArgs(PendingCommand::Review(args)))
+            .await?;
+        return Ok(());
+    };
+
+    let dish_id = match dish.resolve(&pool).await {
+        Ok(id) => id,
+        Err(e) => match e.downcast::<db::LookupError>() {
+            Ok(db::LookupError::Ambiguous { candidates, .. }) => {
+                // let user pick one with the "{category}-{id}-{action}" callback
+                let buttons = candidates.into_iter().map(|c| {
+                    vec![teloxide::types::InlineKeyboardButton::callback(
+                        c.description,
+                        format!("{}-{}-{}", BtnPrefix::REVIEW_DISH, c.id, RevBtnAction::PICK),
+                    )]
+                });
+                bot.send_message(
+                    msg.chat.id,
+                    "Found multiple dishes with this name, which one do you mean?",
+                )
+                .reply_markup(teloxide::types::InlineKeyboardMarkup::new(buttons))
+                .await?;
+                return Ok(());
+            }
+            Ok(e) => {
+                send!([bot, msg], e.to_string());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        },
+    };
+
+    review::start(&bot, msg.chat.id, &dialogue, &pool, caller.db_id(), dish_id).await
+}
+
+async fn whoami_handler(bot: Bot, msg: Message, caller: Caller) -> anyhow::Result<()> {
+    send!(
+        [bot, msg],
+        format!("Your user id is {}, role is {}", caller.id, caller.role)
+    );
+    Ok(())
+}
+
+async fn me_handler(bot: Bot, msg: Message, pool: Pool, caller: Caller) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Read).await {
+        return Ok(());
+    }
+
+    let profile = db::reviewer_profile(&pool, caller.db_id()).await?;
+    let mut text = format!(
+        "{} ({}), {} review(s), {} visit(s)",
+        profile.name.as_deref().unwrap_or("Anonymous"),
+        caller.role,
+        profile.reviews,
+        profile.visits
+    );
+    if let Some(score) = profile.mean_score {
+        text.push_str(&format!("\nMean score given: {score:.1} / 5"));
+    }
+    if let (Some(harshness), Some(tendency)) = (profile.harshness, profile.tendency) {
+        text.push_str(&format!(
+            "\nRating tendency: {tendency} ({:+.1} to the others)",
+            -harshness
+        ));
+    }
+    if !profile.favorite_cuisines.is_empty() {
+        let cuisines = profile
+            .favorite_cuisines
+            .iter()
+            .map(|c| format!("{} ({})", c.name, c.reviews))
+            .collect::<Vec<_>>();
+        text.push_str(&format!("\nFavorite cuisines: {}", cuisines.join(", ")));
+    }
+    for rst in &profile.top_restaurants {
+        text.push_str(&format!(
+            "\n* {}: {} review(s), {:.1} on average",
+            rst.name, rst.reviews, rst.score
+        ));
+    }
+    send!([bot, msg], text);
+    Ok(())
+}
+
+async fn history_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    args: HistoryArgs,
+) -> anyhow::Result<()> {
+    // the log tells who did what, which is only for the people who can change the data
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        return Ok(());
+    }
+
+    let query = db::AuditQueryBuilder::default()
+        .entity(args.kind)
+        .entity_id(args.id)
+        .limit(10)
+        .build()
+        .unwrap();
+    let entries = db::get_audit_log(&pool, query).await?;
+    if entries.is_empty() {
+        send!(
+            [bot, msg],
+            format!("No change of {} {}", args.kind, args.id)
+        );
+        return Ok(());
+    }
+
+    let mut text = format!("Last changes of {} {}:", args.kind, args.id);
+    for entry in entries {
+        let actor = entry
+            .actor
+            .map(|id| format!("user {id}"))
+            .unwrap_or_else(|| "system".to_string());
+        text.push_str(&format!(
+            "\n#{} {} {} by {actor}",
+            entry.id, entry.created_at, entry.action
+        ));
+        let change = db::describe_change(&entry);
+        if !change.is_empty() {
+            text.push_str(&format!(": {change}"));
+        }
+        if let Some(by) = entry.reverted_by {
+            text.push_str(&format!(" (reverted by #{by})"));
+        }
+    }
+    send!([bot, msg], text);
+    Ok(())
+}
+
+async fn price_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    args: PriceArgs,
+) -> anyhow::Result<()> {
+    let permission = match args.amount {
+        Some(_) => Permission::Review,
+        None => Permission::Read,
+    };
+    if !caller.permit(&bot, msg.chat.id, permission).await {
+        return Ok(());
+    }
+
+    let dish = match args.dish.resolve(&pool).await {
+        Ok(id) => id,
+        Err(e) => match e.downcast::<db::LookupError>() {
+            Ok(e) => {
+                send!([bot, msg], e.to_string());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    if let Some(amount) = args.amount {
+        let currency = args.currency.as_deref();
+        db::report_price(&pool, caller.actor(), dish, amount, currency).await?;
+    }
+
+    let price = db::dish_price(&pool, dish).await?;
+    let Some(current) = &price.current else {
+        send!(
+            [bot, msg],
+            format!("No price of dish {dish} yet\n\n{}", PriceArgs::USAGE)
+        );
+        return Ok(());
+    };
+    let mut text = format!(
+        "Dish {dish} costs {} {}, reported at {}\nTrend: {}",
+        current.amount, current.currency, current.observed_at, price.trend
+    );
+    if let Some(change) = price.change {
+        text.push_str(&format!(" ({change:+.1}%)"));
+    }
+    if let Some(value) = price.value_for_money {
+        text.push_str(&format!("\nValue for money: {value:.1} / 5"));
+    }
+    for point in price.history.iter().skip(1).take(5) {
+        text.push_str(&format!(
+            "\n* {} {} at {}",
+            point.amount, point.currency, point.observed_at
+        ));
+    }
+    send!([bot, msg], text);
+    Ok(())
+}
+
+async fn visited_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+    args: VisitArgs,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Review).await {
+        return Ok(());
+    }
+
+    let Some(restaurant) = &args.restaurant else {
+        send!(
+            [bot, msg],
+            format!(
+                "{}, or /cancel\n\n{}",
+                args.missing().unwrap_or_default(),
+                VisitArgs::USAGE
+            )
+        );
+        dialogue
+            .update(ChatState::AwaitingArgs(PendingCommand::Visited(args)))
+            .await?;
+        return Ok(());
+    };
+    let restaurant = match restaurant.resolve(&pool).await {
+        Ok(id) => id,
+        Err(e) => match e.downcast::<db::LookupError>() {
+            Ok(e) => {
+                send!([bot, msg], e.to_string());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        },
+    };
+
+    let mut props = db::NewVisitPropsBuilder::default();
+    props
+        .reviewer(caller.db_id())
+        .restaurant(db::RestaurantProp::Id(restaurant));
+    if let Some(date) = args.date {
+        props.date(date);
+    }
+    if let Some(party_size) = args.party_size {
+        props.party_size(party_size);
+    }
+    if let Some(spend) = args.spend {
+        props.spend(spend);
+    }
+    if let Err(e) = db::add_visit(&pool, props.build()?).await {
+        send!([bot, msg], format!("Fail to record the visit: {e:#}"));
+        return Ok(());
+    }
+
+    let name = db::get_restaurant(&pool, db::RestaurantSearchProps::Id(restaurant))
+        .await?
+        .pop()
+        .map(|rst| rst.name)
+        .unwrap_or_else(|| format!("restaurant {restaurant}"));
+    let count = db::visit_count(&pool, caller.db_id(), restaurant).await?;
+    send!(
+        [bot, msg],
+        format!("Visit to {name} recorded, you have been there {count} time(s)")
+    );
+    Ok(())
+}
+
+async fn token_handler(bot: Bot, msg: Message, pool: Pool, caller: Caller) -> anyhow::Result<()> {
+    if !msg.chat.is_private() {
+        send!([bot, msg], "Please ask for token in private chat with me");
+        return Ok(());
+    }
+    // the token carry the role of the caller, banned user never reach here
+    if !caller.permit(&bot, msg.chat.id, Permission::Review).await {
+        return Ok(());
+    }
+
+    let token = db::create_api_token(&pool, caller.db_id()).await?;
+    send!(
+        [bot, msg],
+        format!(
+            "Your API token is:\n\n{token}\n\n\
+            Send it with the `Authorization: Bearer <token>` header. \
+            It is only shown once, ask an admin to /revoke it if leaked."
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use super::args::{ChatArgs, MergeArgs, Prompt, RoleArgs};
+use super::{auth::Caller, BtnPrefix, ChatState, Dialogue, PendingCommand};
+use crate::db::{self, EntityKind, Permission, Pool, Role};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, Message},
+    Bot,
+};
+
+pub(super) async fn duplicates_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+
+    let report = db::find_duplicates(&pool).await?;
+    let text = if report.is_empty() {
+        String::from("No duplicate found")
+    } else {
+        report.iter().fold(String::new(), |sum, candidates| {
+            let items = candidates
+                .items
+                .iter()
+                .map(|(id, name)| format!("  * {id} {name}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "{sum}{} with {}:\n{items}\n",
+                candidates.kind, candidates.reason
+            )
+        })
+    };
+    send!(
+        [bot, msg],
+        format!("{text}\nMerge them with {}", MergeArgs::USAGE)
+    );
+
+    Ok(())
+}
+
+async fn describe(pool: &Pool, kind: EntityKind, id: i64) -> anyhow::Result<Option<String>> {
+    let name = match kind {
+        EntityKind::Restaurant => db::get_restaurant(pool, db::RestaurantSearchProps::Id(id))
+            .await?
+            .pop()
+            .map(|r| format!("{}. {} {}", r.id, r.name, r.address)),
+        EntityKind::Dish => db::get_dish(pool, 0, Some(id))
+            .await?
+            .pop()
+            .map(|d| format!("{}. {} (restaurant {})", d.id, d.name, d.rid)),
+        other => anyhow::bail!("{other} can not be merged"),
+    };
+    Ok(name)
+}
+
+pub(super) async fn merge_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+    args: MergeArgs,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+
+    let MergeArgs {
+        kind,
+        from: Some(from),
+        into: Some(into),
+    } = args
+    else {
+        send!(
+            [bot, msg],
+            format!("{}, or /cancel", args.missing().unwrap_or_default())
+        );
+        dialogue
+            .update(ChatState::AwaitingArgs(PendingCommand::Merge(args)))
+            .await?;
+        return Ok(());
+    };
+
+    let (Some(from_desc), Some(into_desc)) = (
+        describe(&pool, kind, from).await?,
+        describe(&pool, kind, into).await?,
+    ) else {
+        send!([bot, msg], format!("Incorrect id, no {kind} found"));
+        return Ok(());
+    };
+
+    // build the callback data by "{category}-{kind}-{from}-{into}-{action}"
+    let cbd = |action: &str| format!("{}-{kind}-{from}-{into}-{action}", BtnPrefix::MERGE);
+    let markup = InlineKeyboardMarkup::default().append_row(vec![
+        InlineKeyboardButton::callback("Confirm", cbd(MergeBtnAction::CONFIRM)),
+        InlineKeyboardButton::callback("Cancel", cbd(MergeBtnAction::CANCEL)),
+    ]);
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Merge {kind}\n\n{from_desc}\n\ninto\n\n{into_desc}\n\nThe first one will be deleted."
+        ),
+    )
+    .reply_markup(markup)
+    .await?;
+
+    Ok(())
+}
+
+struct MergeBtnAction;
+impl MergeBtnAction {
+    const CONFIRM: &str = "confirm";
+    const CANCEL: &str = "cancel";
+}
+
+/// Handle the merge confirmation buttons, the callback format is `MERGE-kind-from-into-action`
+pub(super) async fn merge_cb_handler(
+    bot: Bot,
+    msg: Message,
+    caller: &Caller,
+    args: &[&str],
+    pool: &Pool,
+) -> anyhow::Result<()> {
+    let [kind, from, into, action] = args else {
+        anyhow::bail!("invalid merge callback data {args:?}")
+    };
+    // only an admin can confirm or cancel the merge
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+    if *action == MergeBtnAction::CANCEL {
+        bot.edit_message_text(msg.chat.id, msg.id, "Merge cancelled")
+            .await?;
+        return Ok(());
+    }
+
+    let (kind, from, into): (EntityKind, i64, i64) = (kind.parse()?, from.parse()?, into.parse()?);
+    let result = match kind {
+        EntityKind::Restaurant => db::merge_restaurant(pool, caller.actor(), from, into).await,
+        EntityKind::Dish => db::merge_dish(pool, caller.actor(), from, into).await,
+        other => Err(anyhow::anyhow!("{other} can not be merged")),
+    };
+    let text = match result {
+        Ok(summary) => format!(
+            "Merged {kind} {from} into {into}: {} dishes moved, {} dishes merged, {} reviews moved",
+            summary.dishes_moved, summary.dishes_merged, summary.reviews_moved
+        ),
+        Err(e) => format!("Fail to merge: {e:#}"),
+    };
+    bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+
+    Ok(())
+}
+
+// Use the sender of the replied message when the user id is not given
+fn fill_replied_user(msg: &Message, args: &mut RoleArgs) {
+    if args.user.is_none() {
+        if let Some(user) = msg.reply_to_message().and_then(|m| m.from()) {
+            args.user = Some(user.id.0 as i64);
+        }
+    }
+}
+
+pub(super) async fn grant_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+    mut args: RoleArgs,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+
+    fill_replied_user(&msg, &mut args);
+    let (Some(user), Some(role)) = (args.user, args.role) else {
+        send!(
+            [bot, msg],
+            format!("{}, or /cancel", args.missing().unwrap_or_default())
+        );
+        dialogue
+            .update(ChatState::AwaitingArgs(PendingCommand::Grant(args)))
+            .await?;
+        return Ok(());
+    };
+
+    if user == caller.db_id() && role < Role::Admin {
+        send!([bot, msg], "You can not downgrade yourself");
+        return Ok(());
+    }
+
+    match db::set_role(&pool, caller.actor(), user, role).await {
+        Ok(()) => send!([bot, msg], format!("User {user} is {role} now")),
+        Err(e) => send!([bot, msg], format!("Fail to grant role: {e:#}")),
+    }
+
+    Ok(())
+}
+
+pub(super) async fn revoke_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    mut args: RoleArgs,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+
+    fill_replied_user(&msg, &mut args);
+    let Some(user) = args.user else {
+        send!(
+            [bot, msg],
+            "Usage: /revoke <user id>, or reply to the message of the user"
+        );
+        return Ok(());
+    };
+
+    if user == caller.db_id() {
+        send!([bot, msg], "You can not downgrade yourself");
+        return Ok(());
+    }
+
+    if let Err(e) = db::set_role(&pool, caller.actor(), user, Role::Reviewer).await {
+        send!([bot, msg], format!("Fail to revoke role: {e:#}"));
+        return Ok(());
+    }
+    let tokens = db::revoke_api_tokens(&pool, caller.actor(), user).await?;
+    send!(
+        [bot, msg],
+        format!("User {user} is reviewer now, {tokens} API tokens removed")
+    );
+
+    Ok(())
+}
+
+pub(super) async fn allowlist_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    args: ChatArgs,
+    allow: bool,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Admin).await {
+        return Ok(());
+    }
+
+    let chat = args.chat.unwrap_or(msg.chat.id.0);
+    if allow {
+        db::allow_chat(&pool, caller.actor(), chat).await?;
+        send!([bot, msg], format!("Chat {chat} is added into allowlist"));
+    } else {
+        db::disallow_chat(&pool, caller.actor(), chat).await?;
+        send!([bot, msg], format!("Chat {chat} is removed from allowlist"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use super::super::config::BotConfig;
+use crate::db::{self, Actor, Permission, Pool, Role};
+use crate::ratelimit::{RateLimiter, RequestClass, Throttled};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ChatId, Bot};
+
+/// The user who sent the update, with its role. Every handler checks it before taking action.
+#[derive(Debug, Clone)]
+pub(crate) struct Caller {
+    pub(crate) id: UserId,
+    pub(crate) role: Role,
+}
+
+impl Caller {
+    pub(crate) fn db_id(&self) -> i64 {
+        // telegram user id fits in 52 bits
+        self.id.0 as i64
+    }
+
+    pub(crate) fn actor(&self) -> Actor {
+        Actor::User(self.db_id())
+    }
+
+    /// Return true if the caller has the permission, or reply the denial reason to the chat
+    pub(crate) async fn permit(&self, bot: &Bot, chat: ChatId, permission: Permission) -> bool {
+        match self.role.check(permission) {
+            Ok(()) => true,
+            Err(denied) => {
+                if let Err(e) = bot.send_message(chat, denied.to_string()).await {
+                    tracing::error!("fail to send message: {e}")
+                }
+                false
+            }
+        }
+    }
+}
+
+/// Register the sender of the update and find its role. Return None to drop the update when the
+/// sender is banned, or when the bot runs in private mode and the chat is not in the allowlist.
+pub(super) async fn identify(update: Update, pool: Pool, config: Arc<BotConfig>) -> Option<Caller> {
+    let user = update.user()?;
+    let name = user.username.clone().unwrap_or_else(|| user.full_name());
+
+    let role = match db::register_reviewer(&pool, user.id.0 as i64, &name).await {
+        Ok(role) => role,
+        Err(e) => {
+            tracing::error!("fail to get the role of user {}: {e:#}", user.id);
+            return None;
+        }
+    };
+    // admins from the environment can't be locked out
+    let role = if config.is_admin(user.id) {
+        Role::Admin
+    } else {
+        role
+    };
+    let caller = Caller { id: user.id, role };
+
+    if caller.role == Role::Banned {
+        tracing::info!("drop update from banned user {}", user.id);
+        return None;
+    }
+
+    if config.private_mode() && caller.role != Role::Admin {
+        let chat = update.chat()?.id;
+        match db::is_chat_allowed(&pool, chat.0).await {
+            Ok(true) => (),
+            Ok(false) => {
+                tracing::info!("drop update from chat {chat} not in the allowlist");
+                return None;
+            }
+            Err(e) => {
+                tracing::error!("fail to check allowlist for chat {chat}: {e:#}");
+                return None;
+            }
+        }
+    }
+
+    Some(caller)
+}
+
+/// Count the update against the read budget of the caller, see [`within_budget`]
+pub(super) async fn throttle(
+    bot: Bot,
+    update: Update,
+    caller: Caller,
+    limiter: Arc<RateLimiter<UserId>>,
+) -> bool {
+    let chat = update.chat().map(|chat| chat.id);
+    within_budget(&bot, chat, &caller, &limiter, RequestClass::Read).await
+}
+
+/// Return true if the caller has budget for the request. Otherwise reply to the chat, except
+/// while the caller stays banned so the bot doesn't join the flood. Admins are not limited.
+pub(super) async fn within_budget(
+    bot: &Bot,
+    chat: Option<ChatId>,
+    caller: &Caller,
+    limiter: &RateLimiter<UserId>,
+    class: RequestClass,
+) -> bool {
+    if caller.role == Role::Admin {
+        return true;
+    }
+    let throttled = match limiter.check(caller.id, class) {
+        Ok(()) => return true,
+        Err(throttled) => throttled,
+    };
+    tracing::info!("throttle user {}: {throttled:?}", caller.id);
+    if let (Some(chat), Throttled::Limited(_) | Throttled::Banned(_)) = (chat, throttled) {
+        if let Err(e) = bot.send_message(chat, format!("Sorry, {throttled}.")).await {
+            tracing::error!("fail to send message: {e}")
+        }
+    }
+    false
+}
@@ -0,0 +1,43 @@
+use super::args::DigestArgs;
+use super::auth::Caller;
+use crate::db::{self, Permission, Pool};
+use teloxide::{prelude::*, types::Message, Bot};
+
+/// Preview the weekly digest, or change its schedule in the current chat
+pub(super) async fn digest_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    args: DigestArgs,
+) -> anyhow::Result<()> {
+    let permission = match args {
+        DigestArgs::Show | DigestArgs::Now => Permission::Read,
+        DigestArgs::On { .. } | DigestArgs::Off => Permission::Edit,
+    };
+    if !caller.permit(&bot, msg.chat.id, permission).await {
+        return Ok(());
+    }
+
+    let chat = msg.chat.id.0;
+    let text = match args {
+        DigestArgs::Show => match db::digest_setting(&pool, chat).await? {
+            Some(setting) => format!("Weekly digest is posted {setting}"),
+            None => format!("Weekly digest is off in this chat\n\n{}", DigestArgs::USAGE),
+        },
+        DigestArgs::Now => db::weekly_digest(&pool, db::local_now()).await?.to_string(),
+        DigestArgs::On { weekday, hour } => {
+            db::set_digest(&pool, caller.actor(), chat, weekday, hour, db::local_now()).await?;
+            format!("Weekly digest will be posted every {weekday} at {hour:02}:00")
+        }
+        DigestArgs::Off => {
+            if db::remove_digest(&pool, caller.actor(), chat).await? {
+                "Weekly digest is turned off".to_string()
+            } else {
+                "Weekly digest is already off".to_string()
+            }
+        }
+    };
+    send!([bot, msg], text);
+    Ok(())
+}
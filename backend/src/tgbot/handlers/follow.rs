@@ -0,0 +1,79 @@
+use super::args::FollowArgs;
+use super::auth::Caller;
+use crate::db::{self, EntityKind, FollowTarget, Permission, Pool, RestaurantProp, ReviewerProp};
+use teloxide::{prelude::*, types::Message, Bot};
+
+/// Handle /follow and /unfollow, `follow` tells which one it is
+pub(super) async fn follow_handler(
+    bot: Bot,
+    msg: Message,
+    pool: Pool,
+    caller: Caller,
+    args: FollowArgs,
+    follow: bool,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Read).await {
+        return Ok(());
+    }
+
+    let Some((kind, target)) = args.target else {
+        return send_followings(&bot, &msg, &pool, &caller).await;
+    };
+    let resolved = match (kind, target.parse::<i64>()) {
+        (_, Ok(id)) => Ok(id),
+        (EntityKind::Reviewer, Err(_)) => ReviewerProp::Name(target).resolve(&pool).await,
+        (_, Err(_)) => RestaurantProp::Name(target).resolve(&pool).await,
+    };
+    let id = match resolved {
+        Ok(id) => id,
+        Err(e) => match e.downcast::<db::LookupError>() {
+            Ok(e) => {
+                send!([bot, msg], e.to_string());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    let target = FollowTarget::new(kind, id)?;
+
+    let text = if follow {
+        match db::follow(&pool, caller.db_id(), target).await {
+            Ok(true) => format!(
+                "Following {target}, new reviews, dishes and photos will be sent to you. \
+                Please start a private chat with me if you haven't"
+            ),
+            Ok(false) => format!("You are already following {target}"),
+            Err(e) => format!("Fail to follow: {e:#}"),
+        }
+    } else if db::unfollow(&pool, caller.db_id(), target).await? {
+        format!("Unfollowed {target}")
+    } else {
+        format!("You are not following {target}")
+    };
+    send!([bot, msg], text);
+    Ok(())
+}
+
+async fn send_followings(
+    bot: &Bot,
+    msg: &Message,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    let followings = db::followings(pool, caller.db_id()).await?;
+    if followings.is_empty() {
+        send!(
+            [bot, msg],
+            format!("You are not following anything\n\n{}", FollowArgs::USAGE)
+        );
+        return Ok(());
+    }
+
+    let mut text = "You are following:".to_string();
+    for following in followings {
+        let name = following.name.as_deref().unwrap_or("(deleted)");
+        text.push_str(&format!("\n* {} {name}", following.target));
+    }
+    send!([bot, msg], text);
+    Ok(())
+}
@@ -0,0 +1,438 @@
+use super::{auth::Caller, send_restaurant_menu, BtnPrefix, ChatState, Dialogue};
+use crate::db::{self, Permission, Pool};
+use teloxide::{
+    prelude::*,
+    types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, Message},
+    Bot,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    #[default]
+    Name,
+    Address,
+    Phone,
+    Hours,
+    Tags,
+    Preview,
+}
+
+impl Step {
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Address,
+            Self::Address => Self::Phone,
+            Self::Phone => Self::Hours,
+            Self::Hours => Self::Tags,
+            Self::Tags | Self::Preview => Self::Preview,
+        }
+    }
+
+    fn question(self) -> &'static str {
+        match self {
+            Self::Name => "Please send the name of the restaurant, or /cancel",
+            Self::Address => {
+                "Please send the address of the restaurant. \
+                You can also share a location or a venue. Or /cancel"
+            }
+            Self::Phone => "Please send the phone number, or /skip",
+            Self::Hours => "Please send the opening hours, like \"10:00-22:00\", or /skip",
+            Self::Tags => {
+                "Please send the tags separated by comma, like \"川菜, noodles\", or /skip"
+            }
+            Self::Preview => "Please choose with the buttons above, or /cancel",
+        }
+    }
+
+    fn is_optional(self) -> bool {
+        matches!(self, Self::Phone | Self::Hours | Self::Tags)
+    }
+}
+
+/// The restaurant being created, stored in the dialogue between each steps
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RestaurantDraft {
+    step: Step,
+    // go back to preview after the current step, when the user is editing from the preview
+    editing: bool,
+    name: Option<String>,
+    address: Option<String>,
+    location: Option<(f64, f64)>,
+    phone: Option<String>,
+    hours: Option<String>,
+    tags: Option<String>,
+}
+
+impl RestaurantDraft {
+    /// Start the draft from the command arguments, fail if the opening hours are not understood
+    pub(super) fn new(
+        name: Option<String>,
+        address: Option<String>,
+        phone: Option<String>,
+        hours: Option<String>,
+        tags: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let step = if name.is_none() {
+            Step::Name
+        } else if address.is_none() {
+            Step::Address
+        } else {
+            // optional fields can be filled later with the edit button
+            Step::Preview
+        };
+        let hours = match hours {
+            Some(hours) => Some(hours.parse::<db::WeeklyHours>()?.to_string()),
+            None => None,
+        };
+        Ok(Self {
+            step,
+            name,
+            address,
+            phone,
+            hours,
+            tags: tags.map(|t| normalize_tags(&t)),
+            ..Default::default()
+        })
+    }
+
+    fn advance(&mut self) {
+        self.step = if self.editing {
+            Step::Preview
+        } else {
+            self.step.next()
+        };
+        if self.step == Step::Preview {
+            self.editing = false;
+        }
+    }
+
+    fn preview(&self) -> String {
+        let field = |f: &Option<String>| f.clone().unwrap_or_else(|| "-".to_string());
+        let mut text = format!(
+            "Please confirm the new restaurant:\n\n\
+            Name: {}\n\
+            Address: {}\n",
+            field(&self.name),
+            field(&self.address)
+        );
+        if let Some((lat, lon)) = self.location {
+            text.push_str(&format!("Location: {lat:.6}, {lon:.6}\n"));
+        }
+        text.push_str(&format!(
+            "Phone: {}\nOpening hours: {}\nTags: {}",
+            field(&self.phone),
+            field(&self.hours),
+            field(&self.tags)
+        ));
+        text
+    }
+
+    fn into_props(self) -> Option<db::NewRestaurantProps> {
+        let mut builder = db::NewRestaurantPropsBuilder::default();
+        builder.name(self.name?).address(self.address?);
+        if let Some(phone) = self.phone {
+            builder.phone(phone);
+        }
+        if let Some(hours) = self.hours {
+            builder.opening_hours(hours);
+        }
+        if let Some(tags) = self.tags {
+            builder.tags(tags);
+        }
+        if let Some(location) = self.location {
+            builder.location(location);
+        }
+        builder.build().ok()
+    }
+}
+
+// accept both ASCII and full width comma
+fn normalize_tags(text: &str) -> String {
+    text.split([',', '，'])
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+struct WizardBtn;
+impl WizardBtn {
+    const CONFIRM: &str = "confirm";
+    const EDIT: &str = "edit";
+    const CANCEL: &str = "cancel";
+    const BACK: &str = "back";
+    // followed by the restaurant id
+    const OPEN: &str = "open";
+    // followed by the field to edit
+    const FIELD: &str = "field";
+}
+
+fn callback_data(args: &[&str]) -> String {
+    format!("{}-{}", BtnPrefix::NEW_RESTAURANT, args.join("-"))
+}
+
+async fn preview_markup(
+    draft: &RestaurantDraft,
+    pool: &Pool,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let btn = InlineKeyboardButton::callback;
+    let mut text = draft.preview();
+    let mut markup = InlineKeyboardMarkup::default().append_row(vec![
+        btn("Confirm", callback_data(&[WizardBtn::CONFIRM])),
+        btn("Edit", callback_data(&[WizardBtn::EDIT])),
+        btn("Cancel", callback_data(&[WizardBtn::CANCEL])),
+    ]);
+
+    let similar = match &draft.name {
+        Some(name) => db::similar_restaurant(pool, name).await?,
+        None => Vec::new(),
+    };
+    if !similar.is_empty() {
+        text.push_str("\n\nFound similar restaurants, maybe you want to open one of them instead:");
+        for rst in similar.iter().take(5) {
+            text.push_str(&format!("\n{}. {} {}", rst.id, rst.name, rst.address));
+            markup = markup.append_row(vec![InlineKeyboardButton::callback(
+                format!("Open {}. {}", rst.id, rst.name),
+                callback_data(&[WizardBtn::OPEN, &rst.id.to_string()]),
+            )]);
+        }
+    }
+
+    Ok((text, markup))
+}
+
+fn edit_markup() -> InlineKeyboardMarkup {
+    let btn = |text: &str, field: Step| {
+        InlineKeyboardButton::callback(
+            text,
+            callback_data(&[WizardBtn::FIELD, &format!("{field:?}")]),
+        )
+    };
+    InlineKeyboardMarkup::default()
+        .append_row(vec![
+            btn("Name", Step::Name),
+            btn("Address", Step::Address),
+            btn("Phone", Step::Phone),
+        ])
+        .append_row(vec![
+            btn("Opening Hours", Step::Hours),
+            btn("Tags", Step::Tags),
+            InlineKeyboardButton::callback("Back", callback_data(&[WizardBtn::BACK])),
+        ])
+}
+
+/// Ask for the current step of the draft, and save the draft into dialogue
+async fn ask(
+    bot: &Bot,
+    chat: ChatId,
+    dialogue: &Dialogue,
+    pool: &Pool,
+    draft: RestaurantDraft,
+) -> anyhow::Result<()> {
+    if draft.step == Step::Preview {
+        let (text, markup) = preview_markup(&draft, pool).await?;
+        bot.send_message(chat, text).reply_markup(markup).await?;
+    } else {
+        bot.send_message(chat, draft.step.question()).await?;
+    }
+    dialogue
+        .update(ChatState::CreatingRestaurant(draft))
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn start(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &Dialogue,
+    pool: &Pool,
+    draft: RestaurantDraft,
+) -> anyhow::Result<()> {
+    ask(bot, msg.chat.id, dialogue, pool, draft).await
+}
+
+pub(super) async fn message_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    mut draft: RestaurantDraft,
+    pool: Pool,
+) -> anyhow::Result<()> {
+    let text = msg.text().map(str::trim);
+    if text.is_some_and(|t| t.contains("/cancel")) {
+        dialogue.exit().await?;
+        send!([bot, msg], "Process cancelled");
+        return Ok(());
+    }
+
+    if text.is_some_and(|t| t.contains("/skip")) {
+        if !draft.step.is_optional() {
+            send!([bot, msg], "This field is required, it can not be skipped");
+            return Ok(());
+        }
+        draft.advance();
+        return ask(&bot, msg.chat.id, &dialogue, &pool, draft).await;
+    }
+
+    match draft.step {
+        Step::Address => {
+            if let Some(venue) = msg.venue() {
+                draft.address = Some(venue.address.clone());
+                draft.location = Some((venue.location.latitude, venue.location.longitude));
+            } else if let Some(location) = msg.location() {
+                draft.address = Some(format!(
+                    "{:.6}, {:.6}",
+                    location.latitude, location.longitude
+                ));
+                draft.location = Some((location.latitude, location.longitude));
+            } else if let Some(text) = text.filter(|t| !t.is_empty()) {
+                draft.address = Some(text.to_string());
+            } else {
+                send!([bot, msg], draft.step.question());
+                return Ok(());
+            }
+        }
+        Step::Preview => {
+            send!([bot, msg], draft.step.question());
+            return Ok(());
+        }
+        step => {
+            let Some(text) = text.filter(|t| !t.is_empty()) else {
+                send!([bot, msg], step.question());
+                return Ok(());
+            };
+            if step == Step::Hours {
+                match text.parse::<db::WeeklyHours>() {
+                    Ok(hours) => draft.hours = Some(hours.to_string()),
+                    Err(e) => {
+                        send!([bot, msg], format!("{e:#}, please retry or /skip"));
+                        return Ok(());
+                    }
+                }
+            }
+            let text = text.to_string();
+            match step {
+                Step::Name => draft.name = Some(text),
+                Step::Phone => draft.phone = Some(text),
+                Step::Hours => (),
+                Step::Tags => draft.tags = Some(normalize_tags(&text)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    draft.advance();
+    ask(&bot, msg.chat.id, &dialogue, &pool, draft).await
+}
+
+/// Handle the buttons from the preview message. The callback format is `RSTNEW-action[-argument]`.
+pub(super) async fn callback_handler(
+    bot: Bot,
+    msg: Message,
+    args: &[&str],
+    dialogue: &Dialogue,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Create).await {
+        return Ok(());
+    }
+
+    let Some(ChatState::CreatingRestaurant(mut draft)) = dialogue.get().await? else {
+        bot.edit_message_text(msg.chat.id, msg.id, "This draft is expired")
+            .await?;
+        return Ok(());
+    };
+
+    match args {
+        [WizardBtn::CONFIRM] => {
+            let Some(props) = draft.into_props() else {
+                anyhow::bail!("confirming an incomplete restaurant draft");
+            };
+            let id = db::add_restaurant_detail(pool, caller.actor(), props).await?;
+            dialogue.exit().await?;
+            bot.edit_message_text(
+                msg.chat.id,
+                msg.id,
+                format!("Restaurant added, edit it with /rest edit {id}"),
+            )
+            .await?;
+        }
+        [WizardBtn::EDIT] => {
+            bot.edit_message_reply_markup(msg.chat.id, msg.id)
+                .reply_markup(edit_markup())
+                .await?;
+        }
+        [WizardBtn::BACK] => {
+            let (text, markup) = preview_markup(&draft, pool).await?;
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .reply_markup(markup)
+                .await?;
+        }
+        [WizardBtn::FIELD, field] => {
+            let step = [
+                Step::Name,
+                Step::Address,
+                Step::Phone,
+                Step::Hours,
+                Step::Tags,
+            ]
+            .into_iter()
+            .find(|s| format!("{s:?}") == *field);
+            let Some(step) = step else { return Ok(()) };
+            draft.step = step;
+            draft.editing = true;
+            bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+            ask(&bot, msg.chat.id, dialogue, pool, draft).await?;
+        }
+        [WizardBtn::CANCEL] => {
+            dialogue.exit().await?;
+            bot.edit_message_text(msg.chat.id, msg.id, "Process cancelled")
+                .await?;
+        }
+        [WizardBtn::OPEN, id] => {
+            let id: i64 = id.parse()?;
+            dialogue.exit().await?;
+            bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+            let rst = db::get_restaurant(pool, db::RestaurantSearchProps::Id(id)).await?;
+            match rst.first() {
+                Some(rst) => send_restaurant_menu(&bot, msg.chat.id, pool, rst).await?,
+                None => send!([bot, msg], "Incorrect id, no restaurant found"),
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_draft_steps() {
+    assert!(RestaurantDraft::new(None, None, None, Some("soon".into()), None).is_err());
+    let draft = RestaurantDraft::new(None, None, None, Some("10:00-22:00".into()), None).unwrap();
+    assert_eq!(draft.hours.as_deref(), Some("daily 10:00-22:00"));
+
+    let mut draft = RestaurantDraft::new(Some("老乡鸡".into()), None, None, None, None).unwrap();
+    assert_eq!(draft.step, Step::Address);
+    draft.advance();
+    assert_eq!(draft.step, Step::Phone);
+    draft.advance();
+    draft.advance();
+    draft.advance();
+    assert_eq!(draft.step, Step::Preview);
+
+    draft.step = Step::Name;
+    draft.editing = true;
+    draft.advance();
+    assert_eq!(draft.step, Step::Preview);
+    assert!(!draft.editing);
+
+    assert!(draft.clone().into_props().is_none());
+    draft.address = Some("武汉".into());
+    assert!(draft.into_props().is_some());
+
+    assert_eq!(
+        normalize_tags(" 川菜，noodles, ,spicy"),
+        "川菜,noodles,spicy"
+    );
+}
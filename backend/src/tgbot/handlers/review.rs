@@ -0,0 +1,441 @@
+use super::args;
+use super::{auth::Caller, BtnPrefix, ChatState, Dialogue};
+use crate::db::{self, Permission, Pool, RatingDimension};
+use teloxide::{
+    prelude::*,
+    types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, Message},
+    Bot,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    #[default]
+    Details,
+    Score,
+    Rating,
+    Price,
+    Preview,
+}
+
+/// The review being written, stored in the dialogue between each steps. The dialogue is of the
+/// chat, so the draft remembers who is writing it.
+#[derive(Debug, Clone)]
+pub(crate) struct ReviewDraft {
+    author: i64,
+    dish: i64,
+    dish_name: String,
+    step: Step,
+    // go back to preview after the current step, when the user is editing from the preview
+    editing: bool,
+    details: Option<String>,
+    score: Option<u8>,
+    ratings: Vec<(RatingDimension, u8)>,
+    // number of dimensions that are rated or skipped
+    asked: usize,
+    price: Option<f64>,
+}
+
+impl ReviewDraft {
+    fn new(author: i64, dish: i64, dish_name: String) -> Self {
+        Self {
+            author,
+            dish,
+            dish_name,
+            step: Step::default(),
+            editing: false,
+            details: None,
+            score: None,
+            ratings: Vec::new(),
+            asked: 0,
+            price: None,
+        }
+    }
+
+    /// The dimension waiting for rating
+    fn pending(&self) -> Option<RatingDimension> {
+        if self.step != Step::Rating {
+            return None;
+        }
+        RatingDimension::ALL.get(self.asked).copied()
+    }
+
+    fn advance(&mut self) {
+        self.step = match self.step {
+            _ if self.editing => Step::Preview,
+            Step::Details => Step::Score,
+            Step::Score => Step::Rating,
+            Step::Rating if self.asked < RatingDimension::ALL.len() => Step::Rating,
+            Step::Rating => Step::Price,
+            Step::Price | Step::Preview => Step::Preview,
+        };
+        if self.step == Step::Preview {
+            self.editing = false;
+        }
+    }
+
+    fn rate(&mut self, score: Option<u8>) {
+        if let (Some(dimension), Some(score)) = (self.pending(), score) {
+            self.ratings.push((dimension, score));
+        }
+        self.asked += 1;
+        self.advance();
+    }
+
+    fn skip_ratings(&mut self) {
+        self.asked = RatingDimension::ALL.len();
+        self.advance();
+    }
+
+    fn preview(&self) -> String {
+        let mut text = format!(
+            "Please confirm your review of {}:\n\nScore: {}\n",
+            self.dish_name,
+            self.score.map(stars).unwrap_or_else(|| "-".to_string())
+        );
+        if !self.ratings.is_empty() {
+            let ratings = self
+                .ratings
+                .iter()
+                .map(|(dimension, score)| format!("{dimension} {score}"))
+                .collect::<Vec<_>>();
+            text.push_str(&format!("Ratings: {}\n", ratings.join(", ")));
+        }
+        if let Some(price) = self.price {
+            text.push_str(&format!("Price: {price}\n"));
+        }
+        text.push_str(&format!(
+            "\n{}",
+            self.details.as_deref().unwrap_or_default()
+        ));
+        text
+    }
+
+    fn into_props(self) -> Option<db::NewReviewProps> {
+        let mut builder = db::NewReviewPropsBuilder::default();
+        builder
+            .dish(db::DishProp::Id(self.dish))
+            .reviewer(db::ReviewerProp::Id(self.author))
+            .details(self.details?)
+            .score(self.score?)
+            .ratings(self.ratings);
+        if let Some(price) = self.price {
+            builder.price(price);
+        }
+        builder.build().ok()
+    }
+}
+
+fn stars(score: u8) -> String {
+    let score = score.min(5) as usize;
+    format!("{}{} ({score})", "★".repeat(score), "☆".repeat(5 - score))
+}
+
+struct ReviewBtn;
+impl ReviewBtn {
+    // followed by the score
+    const SCORE: &str = "score";
+    // followed by the dimension and the score, or skip
+    const RATE: &str = "rate";
+    const SKIP: &str = "skip";
+    const EDIT_TEXT: &str = "text";
+    const CHANGE_SCORE: &str = "change";
+    const SUBMIT: &str = "submit";
+    const CANCEL: &str = "cancel";
+}
+
+fn callback_data(args: &[&str]) -> String {
+    format!("{}-{}", BtnPrefix::REVIEW, args.join("-"))
+}
+
+fn score_markup() -> InlineKeyboardMarkup {
+    let btn = |score: u8| {
+        let text = if score == 0 {
+            "0".to_string()
+        } else {
+            "★".repeat(score as usize)
+        };
+        InlineKeyboardButton::callback(text, callback_data(&[ReviewBtn::SCORE, &score.to_string()]))
+    };
+    InlineKeyboardMarkup::default()
+        .append_row((0..=2).map(btn))
+        .append_row((3..=5).map(btn))
+}
+
+fn rating_markup(dimension: RatingDimension) -> InlineKeyboardMarkup {
+    let dimension = dimension.to_string();
+    let btn = |text: String, value: &str| {
+        InlineKeyboardButton::callback(text, callback_data(&[ReviewBtn::RATE, &dimension, value]))
+    };
+    InlineKeyboardMarkup::default()
+        .append_row((0..=5).map(|n| btn(n.to_string(), &n.to_string())))
+        .append_row(vec![btn("Skip".to_string(), ReviewBtn::SKIP)])
+}
+
+fn preview_markup() -> InlineKeyboardMarkup {
+    let btn =
+        |text: &str, action: &str| InlineKeyboardButton::callback(text, callback_data(&[action]));
+    InlineKeyboardMarkup::default()
+        .append_row(vec![
+            btn("Edit Text", ReviewBtn::EDIT_TEXT),
+            btn("Change Score", ReviewBtn::CHANGE_SCORE),
+        ])
+        .append_row(vec![
+            btn("Submit", ReviewBtn::SUBMIT),
+            btn("Cancel", ReviewBtn::CANCEL),
+        ])
+}
+
+/// Ask for the current step of the draft, and save the draft into dialogue
+async fn ask(
+    bot: &Bot,
+    chat: ChatId,
+    dialogue: &Dialogue,
+    draft: ReviewDraft,
+) -> anyhow::Result<()> {
+    match draft.step {
+        Step::Details => {
+            bot.send_message(
+                chat,
+                format!("Please send your review of {}, or /cancel", draft.dish_name),
+            )
+            .await?;
+        }
+        Step::Score => {
+            bot.send_message(chat, "Please pick your score for this dish, or /cancel")
+                .reply_markup(score_markup())
+                .await?;
+        }
+        Step::Rating => {
+            let dimension = draft
+                .pending()
+                .expect("rating step without pending dimension");
+            bot.send_message(
+                chat,
+                format!("How about the {dimension}? Pick 0 - 5, or skip the rest with /skip"),
+            )
+            .reply_markup(rating_markup(dimension))
+            .await?;
+        }
+        Step::Price => {
+            bot.send_message(
+                chat,
+                "How much did you pay for it? Send the price, or /skip",
+            )
+            .await?;
+        }
+        Step::Preview => {
+            bot.send_message(chat, draft.preview())
+                .reply_markup(preview_markup())
+                .await?;
+        }
+    }
+    dialogue.update(ChatState::CreatingReview(draft)).await?;
+    Ok(())
+}
+
+/// Start writing a review of the dish by the author. The dish is checked here, so the user won't
+/// find out it doesn't exist after writing the whole review.
+pub(super) async fn start(
+    bot: &Bot,
+    chat: ChatId,
+    dialogue: &Dialogue,
+    pool: &Pool,
+    author: i64,
+    dish: i64,
+) -> anyhow::Result<()> {
+    let Some(found) = db::get_dish(pool, 0, Some(dish)).await?.pop() else {
+        bot.send_message(chat, format!("No dish with id {dish}"))
+            .await?;
+        dialogue.exit().await?;
+        return Ok(());
+    };
+    ask(
+        bot,
+        chat,
+        dialogue,
+        ReviewDraft::new(author, dish, found.name),
+    )
+    .await
+}
+
+pub(super) async fn message_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    caller: Caller,
+    mut draft: ReviewDraft,
+) -> anyhow::Result<()> {
+    // the others in a group keep chatting
+    if caller.db_id() != draft.author {
+        return Ok(());
+    }
+    let text = msg.text().map(str::trim).unwrap_or_default();
+    if text.contains("/cancel") {
+        dialogue.exit().await?;
+        send!([bot, msg], "Process cancelled");
+        return Ok(());
+    }
+    let skipped = text.contains("/skip");
+
+    match draft.step {
+        Step::Details => {
+            if text.is_empty() || skipped {
+                send!([bot, msg], "I need text message, please resend or /cancel");
+                return Ok(());
+            }
+            draft.details = Some(text.to_string());
+        }
+        Step::Score => match text.parse::<u8>() {
+            // typing the number still works
+            Ok(score) if score <= 5 => draft.score = Some(score),
+            _ => {
+                send!(
+                    [bot, msg],
+                    "Please pick the score with the buttons above, or /cancel"
+                );
+                return Ok(());
+            }
+        },
+        Step::Rating => {
+            if !skipped {
+                send!(
+                    [bot, msg],
+                    "Please pick with the buttons above, /skip the ratings or /cancel"
+                );
+                return Ok(());
+            }
+            draft.skip_ratings();
+            return ask(&bot, msg.chat.id, &dialogue, draft).await;
+        }
+        Step::Price => {
+            if !skipped {
+                match args::parse_price(text) {
+                    Ok(price) => draft.price = Some(price),
+                    Err(e) => {
+                        send!([bot, msg], format!("{e}, or /skip"));
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Step::Preview => {
+            send!(
+                [bot, msg],
+                "Please choose with the buttons above, or /cancel"
+            );
+            return Ok(());
+        }
+    }
+
+    draft.advance();
+    ask(&bot, msg.chat.id, &dialogue, draft).await
+}
+
+/// Handle the buttons of the review flow. The callback format is `REVIEW-action[-argument]`.
+pub(super) async fn callback_handler(
+    bot: Bot,
+    msg: Message,
+    args: &[&str],
+    dialogue: &Dialogue,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    let Some(ChatState::CreatingReview(mut draft)) = dialogue.get().await? else {
+        bot.edit_message_text(msg.chat.id, msg.id, "This review is expired")
+            .await?;
+        return Ok(());
+    };
+    if caller.db_id() != draft.author {
+        bot.send_message(msg.chat.id, "Only the author can change this review")
+            .await?;
+        return Ok(());
+    }
+
+    match (draft.step, args) {
+        (Step::Score, [ReviewBtn::SCORE, score]) => {
+            let score = score.parse::<u8>()?.min(5);
+            draft.score = Some(score);
+            bot.edit_message_text(msg.chat.id, msg.id, format!("Score: {}", stars(score)))
+                .await?;
+            draft.advance();
+            ask(&bot, msg.chat.id, dialogue, draft).await?;
+        }
+        (Step::Rating, [ReviewBtn::RATE, dimension, value]) => {
+            let dimension: RatingDimension = dimension.parse()?;
+            // buttons from an older message
+            if draft.pending() != Some(dimension) {
+                return Ok(());
+            }
+            let score = match *value {
+                ReviewBtn::SKIP => None,
+                value => Some(value.parse::<u8>()?.min(5)),
+            };
+            let answer = match score {
+                Some(score) => format!("{dimension}: {score}"),
+                None => format!("{dimension}: skipped"),
+            };
+            bot.edit_message_text(msg.chat.id, msg.id, answer).await?;
+            draft.rate(score);
+            ask(&bot, msg.chat.id, dialogue, draft).await?;
+        }
+        (Step::Preview, [ReviewBtn::EDIT_TEXT | ReviewBtn::CHANGE_SCORE]) => {
+            draft.step = if args[0] == ReviewBtn::EDIT_TEXT {
+                Step::Details
+            } else {
+                Step::Score
+            };
+            draft.editing = true;
+            bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+            ask(&bot, msg.chat.id, dialogue, draft).await?;
+        }
+        (Step::Preview, [ReviewBtn::SUBMIT]) => {
+            if !caller.permit(&bot, msg.chat.id, Permission::Review).await {
+                dialogue.exit().await?;
+                return Ok(());
+            }
+            let Some(props) = draft.into_props() else {
+                anyhow::bail!("submitting an incomplete review draft");
+            };
+            db::add_new_review(pool, props).await?;
+            dialogue.exit().await?;
+            bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+            send!([bot, msg], "New review added");
+        }
+        (_, [ReviewBtn::CANCEL]) => {
+            dialogue.exit().await?;
+            bot.edit_message_text(msg.chat.id, msg.id, "Process cancelled")
+                .await?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_review_draft() {
+    let mut draft = ReviewDraft::new(1, 1, "吮指原味鸡".to_string());
+    draft.details = Some("好恰".to_string());
+    draft.advance();
+    assert_eq!(draft.step, Step::Score);
+    assert!(draft.clone().into_props().is_none());
+    draft.score = Some(4);
+    draft.advance();
+    assert_eq!(draft.pending(), Some(RatingDimension::Taste));
+    draft.rate(Some(5));
+    draft.rate(None);
+    assert_eq!(draft.pending(), Some(RatingDimension::Value));
+    draft.skip_ratings();
+    assert_eq!(draft.step, Step::Price);
+    draft.advance();
+    assert_eq!(draft.step, Step::Preview);
+    assert_eq!(draft.ratings, vec![(RatingDimension::Taste, 5)]);
+
+    // editing from the preview goes back to the preview
+    draft.step = Step::Score;
+    draft.editing = true;
+    draft.advance();
+    assert_eq!(draft.step, Step::Preview);
+    assert!(draft.preview().contains("★★★★☆ (4)"));
+    assert!(draft.into_props().is_some());
+}
@@ -0,0 +1,162 @@
+use super::{auth::Caller, BtnPrefix, ChatState, Dialogue};
+use crate::db::{self, EntityKind, Permission, Pool};
+use teloxide::{
+    prelude::*,
+    types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, Message},
+    Bot,
+};
+
+// how many of the popular tags are suggested in the editor
+const SUGGESTIONS: usize = 8;
+
+struct TagBtn;
+impl TagBtn {
+    // followed by the tag id
+    const TOGGLE: &str = "toggle";
+    const NEW: &str = "new";
+    const OPEN: &str = "open";
+    const DONE: &str = "done";
+}
+
+/// Callback data of the buttons for opening the tag editor of the row
+pub(super) fn open_cbd(kind: EntityKind, id: i64) -> String {
+    format!("{}-{kind}-{id}-{}", BtnPrefix::TAG, TagBtn::OPEN)
+}
+
+// Text and buttons of the tag editor: the current tags are checked, and pressing a button
+// toggles the tag.
+async fn editor(
+    pool: &Pool,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let current = db::get_tags(pool, kind, id).await?;
+    let cloud = db::tag_cloud(pool).await?;
+
+    // build the callback data by "{category}-{kind}-{id}-{action}"
+    let cbd = |action: &str| format!("{}-{kind}-{id}-{action}", BtnPrefix::TAG);
+    let checked = cloud.iter().filter(|t| current.contains(&t.name));
+    let suggested = cloud
+        .iter()
+        .filter(|t| !current.contains(&t.name))
+        .take(SUGGESTIONS);
+    let buttons = checked
+        .map(|t| (format!("✅ {}", t.name), t.id))
+        .chain(suggested.map(|t| (t.name.clone(), t.id)))
+        .map(|(label, tag)| {
+            InlineKeyboardButton::callback(label, cbd(&format!("{}-{tag}", TagBtn::TOGGLE)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut markup = InlineKeyboardMarkup::default();
+    for row in buttons.chunks(3) {
+        markup = markup.append_row(row.to_vec());
+    }
+    let markup = markup.append_row(vec![
+        InlineKeyboardButton::callback("Add New", cbd(TagBtn::NEW)),
+        InlineKeyboardButton::callback("Done", cbd(TagBtn::DONE)),
+    ]);
+
+    let text = if current.is_empty() {
+        format!("{kind} {id} has no tag yet, pick some or add new ones")
+    } else {
+        format!("Tags of {kind} {id}: {}", current.join(", "))
+    };
+    Ok((text, markup))
+}
+
+/// Handle the tag editor buttons, the callback format is `TAG-kind-id-action[-tag id]`
+pub(super) async fn callback_handler(
+    bot: Bot,
+    msg: Message,
+    args: &[&str],
+    dialogue: &Dialogue,
+    pool: &Pool,
+    caller: &Caller,
+) -> anyhow::Result<()> {
+    let (kind, id, action, tag) = match args {
+        [kind, id, action] => (kind, id, *action, None),
+        [kind, id, action, tag] => (kind, id, *action, Some(tag.parse::<i64>()?)),
+        _ => anyhow::bail!("invalid tag callback data {args:?}"),
+    };
+    let (kind, id): (EntityKind, i64) = (kind.parse()?, id.parse()?);
+    if action == TagBtn::DONE {
+        bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+        return Ok(());
+    }
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        return Ok(());
+    }
+
+    match (action, tag) {
+        (TagBtn::OPEN, _) => send_editor(&bot, msg.chat.id, pool, kind, id).await?,
+        (TagBtn::NEW, _) => {
+            bot.send_message(
+                msg.chat.id,
+                format!("Please send the new tags of {kind} {id} separated by comma, or /cancel"),
+            )
+            .await?;
+            dialogue.update(ChatState::EditingTags(kind, id)).await?;
+        }
+        (TagBtn::TOGGLE, Some(tag)) => {
+            let Some(name) = db::get_tag_name(pool, tag).await? else {
+                return Ok(());
+            };
+            let current = db::get_tags(pool, kind, id).await?;
+            let tags = [name];
+            if current.contains(&tags[0]) {
+                db::remove_tags(pool, caller.actor(), kind, id, &tags).await?;
+            } else {
+                db::add_tags(pool, caller.actor(), kind, id, &tags).await?;
+            }
+            let (text, markup) = editor(pool, kind, id).await?;
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .reply_markup(markup)
+                .await?;
+        }
+        _ => anyhow::bail!("invalid tag callback data {args:?}"),
+    }
+
+    Ok(())
+}
+
+/// Add the tags sent by the user after pressing the "Add New" button
+pub(super) async fn message_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: Dialogue,
+    pool: Pool,
+    caller: Caller,
+    (kind, id): (EntityKind, i64),
+) -> anyhow::Result<()> {
+    if !caller.permit(&bot, msg.chat.id, Permission::Edit).await {
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let Some(text) = msg.text() else {
+        send!([bot, msg], "Please send the tags in text, or /cancel");
+        return Ok(());
+    };
+    if text.contains("/cancel") {
+        dialogue.exit().await?;
+        send!([bot, msg], "Process cancelled");
+        return Ok(());
+    }
+
+    db::add_tags(&pool, caller.actor(), kind, id, &[text.to_string()]).await?;
+    dialogue.exit().await?;
+    send_editor(&bot, msg.chat.id, &pool, kind, id).await
+}
+
+pub(super) async fn send_editor(
+    bot: &Bot,
+    chat: ChatId,
+    pool: &Pool,
+    kind: EntityKind,
+    id: i64,
+) -> anyhow::Result<()> {
+    let (text, markup) = editor(pool, kind, id).await?;
+    bot.send_message(chat, text).reply_markup(markup).await?;
+    Ok(())
+}
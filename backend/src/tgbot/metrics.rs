@@ -0,0 +1,186 @@
+use super::handlers::ChatState;
+use super::webhook::WebhookRoute;
+use crate::db::Pool;
+use crate::ops;
+use actix_web::{dev::Server, web, App, HttpServer};
+use prometheus::{
+    register_int_counter, register_int_counter_vec, register_int_gauge_vec, IntCounter,
+    IntCounterVec, IntGaugeVec,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use teloxide::dispatching::dialogue::{InMemStorage, InMemStorageError, Storage};
+use teloxide::types::{ChatId, Update, UpdateKind};
+
+/// Default address of `/healthz`, `/readyz` and `/metrics`, local only
+pub const ADDR: &str = "127.0.0.1:9091";
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+fn updates() -> &'static IntCounterVec {
+    static METRIC: OnceLock<IntCounterVec> = OnceLock::new();
+    METRIC.get_or_init(|| {
+        register_int_counter_vec!("tgbot_updates_total", "Updates received by kind", &["kind"])
+            .expect("fail to register metric")
+    })
+}
+
+fn errors() -> &'static IntCounter {
+    static METRIC: OnceLock<IntCounter> = OnceLock::new();
+    METRIC.get_or_init(|| {
+        register_int_counter!(
+            "tgbot_handler_errors_total",
+            "Errors returned by the handlers"
+        )
+        .expect("fail to register metric")
+    })
+}
+
+fn dialogues() -> &'static IntGaugeVec {
+    static METRIC: OnceLock<IntGaugeVec> = OnceLock::new();
+    METRIC.get_or_init(|| {
+        register_int_gauge_vec!(
+            "tgbot_dialogues",
+            "Chats in each dialogue state",
+            &["state"]
+        )
+        .expect("fail to register metric")
+    })
+}
+
+pub(super) fn count_update(update: Update) {
+    let kind = match update.kind {
+        UpdateKind::Message(_) => "message",
+        UpdateKind::EditedMessage(_) => "edited_message",
+        UpdateKind::CallbackQuery(_) => "callback_query",
+        UpdateKind::InlineQuery(_) => "inline_query",
+        _ => "other",
+    };
+    updates().with_label_values(&[kind]).inc();
+}
+
+pub(super) async fn handle_error(err: anyhow::Error) {
+    errors().inc();
+    tracing::error!("Error occur when handling update: {err:?}");
+}
+
+/// Serve the operational endpoints with the readiness of the database, and the webhook
+pub fn serve(addr: &str, pool: Pool, webhook: Option<WebhookRoute>) -> std::io::Result<Server> {
+    let pool = web::Data::new(pool);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(pool.clone())
+            .configure(ops::configure)
+            .configure(|cfg| {
+                if let Some(webhook) = &webhook {
+                    webhook.configure(cfg)
+                }
+            })
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(addr)?
+    .run();
+    Ok(server)
+}
+
+/// Dialogues kept in memory, counting the chats in each state
+pub(super) struct DialogueStorage {
+    inner: Arc<InMemStorage<ChatState>>,
+    states: Mutex<HashMap<ChatId, &'static str>>,
+}
+
+impl DialogueStorage {
+    pub(super) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: InMemStorage::new(),
+            states: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn track(&self, chat: ChatId, state: Option<&'static str>) {
+        let mut states = self.states.lock().unwrap();
+        let old = match state {
+            Some(state) => states.insert(chat, state),
+            None => states.remove(&chat),
+        };
+        if let Some(old) = old {
+            dialogues().with_label_values(&[old]).dec();
+        }
+        if let Some(state) = state {
+            dialogues().with_label_values(&[state]).inc();
+        }
+    }
+}
+
+impl Storage<ChatState> for DialogueStorage {
+    type Error = InMemStorageError;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Result<(), Self::Error>> {
+        Box::pin(async move {
+            self.inner.clone().remove_dialogue(chat_id).await?;
+            self.track(chat_id, None);
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: ChatState,
+    ) -> BoxFuture<Result<(), Self::Error>> {
+        let state = dialogue.name();
+        Box::pin(async move {
+            self.inner
+                .clone()
+                .update_dialogue(chat_id, dialogue)
+                .await?;
+            self.track(chat_id, Some(state));
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<Result<Option<ChatState>, Self::Error>> {
+        self.inner.clone().get_dialogue(chat_id)
+    }
+}
+
+#[tokio::test]
+async fn test_dialogue_storage() {
+    let storage = DialogueStorage::new();
+    let gauge = |state: &str| dialogues().with_label_values(&[state]).get();
+    let (a, b) = (ChatId(1), ChatId(2));
+
+    storage
+        .clone()
+        .update_dialogue(a, ChatState::EditingRstName(1))
+        .await
+        .unwrap();
+    storage
+        .clone()
+        .update_dialogue(b, ChatState::EditingRstName(2))
+        .await
+        .unwrap();
+    assert_eq!(gauge("EditingRstName"), 2);
+
+    storage
+        .clone()
+        .update_dialogue(a, ChatState::EditingRstAddr(1))
+        .await
+        .unwrap();
+    storage.clone().remove_dialogue(b).await.unwrap();
+    assert_eq!(gauge("EditingRstName"), 0);
+    assert_eq!(gauge("EditingRstAddr"), 1);
+    assert!(matches!(
+        storage.clone().get_dialogue(a).await.unwrap(),
+        Some(ChatState::EditingRstAddr(1))
+    ));
+    // the failed removal doesn't change the counts
+    assert!(storage.clone().remove_dialogue(b).await.is_err());
+    assert_eq!(gauge("EditingRstAddr"), 1);
+}
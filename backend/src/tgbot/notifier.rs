@@ -0,0 +1,337 @@
+use crate::db::{self, Event, Pool};
+use crate::ops::Shutdown;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use teloxide::{prelude::*, types::ChatId, Bot};
+use tokio::sync::broadcast::{
+    error::{RecvError, TryRecvError},
+    Receiver,
+};
+use tokio::time::{Duration, Instant};
+
+// how often the queued notifications are checked
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+// a user gets at most one message in this interval, the updates in between are sent together
+const MIN_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// updates listed in one message, the rest are only counted
+const DIGEST_LINES: usize = 10;
+
+/// Where the messages go, the bot in production and a recorder in the tests
+pub(super) trait TextSender {
+    fn send_text(
+        &self,
+        chat: ChatId,
+        text: String,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+impl TextSender for Bot {
+    fn send_text(
+        &self,
+        chat: ChatId,
+        text: String,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let request = self.send_message(chat, text);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+}
+
+/// Send the new reviews, dishes and photos to the users following them
+pub(super) struct Notifier<S> {
+    sender: S,
+    pool: Pool,
+    // queued updates of each user
+    pending: BTreeMap<i64, Vec<String>>,
+    last_sent: HashMap<i64, Instant>,
+}
+
+impl<S: TextSender> Notifier<S> {
+    pub(super) fn new(sender: S, pool: Pool) -> Self {
+        Self {
+            sender,
+            pool,
+            pending: BTreeMap::new(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Queue the event for its subscribers
+    async fn push(&mut self, event: &Event) -> anyhow::Result<()> {
+        let subscribers = db::subscribers(&self.pool, event).await?;
+        if subscribers.is_empty() {
+            return Ok(());
+        }
+        let text = db::describe_event(&self.pool, event).await?;
+        for id in subscribers {
+            self.pending.entry(id).or_default().push(text.clone());
+        }
+        Ok(())
+    }
+
+    /// Send the queued updates to the users who haven't got a message in [`MIN_INTERVAL`]
+    async fn flush(&mut self, now: Instant) {
+        let ready = self
+            .pending
+            .keys()
+            .filter(|id| {
+                self.last_sent
+                    .get(id)
+                    .is_none_or(|sent| now.duration_since(*sent) >= MIN_INTERVAL)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        self.send(ready, now).await
+    }
+
+    /// Send every queued update, they would be lost with the process
+    async fn drain(&mut self, now: Instant) {
+        let queued = self.pending.keys().copied().collect();
+        self.send(queued, now).await
+    }
+
+    async fn send(&mut self, users: Vec<i64>, now: Instant) {
+        for id in users {
+            let Some(lines) = self.pending.remove(&id) else {
+                continue;
+            };
+            // the private chat with a user has the same id as the user
+            match self.sender.send_text(ChatId(id), digest(&lines)).await {
+                Ok(()) => {
+                    self.last_sent.insert(id, now);
+                }
+                Err(e) => {
+                    tracing::warn!("fail to notify user {id}: {e:#}");
+                    // retry with the next flush, before the updates queued meanwhile
+                    let queued = self.pending.entry(id).or_default();
+                    queued.splice(0..0, lines);
+                }
+            }
+        }
+    }
+
+    pub(super) async fn run(mut self, mut events: Receiver<Event>, mut shutdown: Shutdown) {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = shutdown.wait() => break,
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if let Err(e) = self.push(&event).await {
+                            tracing::error!("fail to queue notification of {event:?}: {e:#}");
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("notifier is too slow, {missed} events are dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = ticker.tick() => self.flush(Instant::now()).await,
+            }
+        }
+        // the events of the updates handled before the shutdown
+        loop {
+            match events.try_recv() {
+                Ok(event) => {
+                    if let Err(e) = self.push(&event).await {
+                        tracing::error!("fail to queue notification of {event:?}: {e:#}");
+                    }
+                }
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        self.drain(Instant::now()).await;
+    }
+}
+
+fn digest(lines: &[String]) -> String {
+    if let [line] = lines {
+        return line.clone();
+    }
+    let mut text = format!("{} updates from what you follow:", lines.len());
+    for line in lines.iter().take(DIGEST_LINES) {
+        text.push_str(&format!("\n* {line}"));
+    }
+    if lines.len() > DIGEST_LINES {
+        text.push_str(&format!("\nand {} more", lines.len() - DIGEST_LINES));
+    }
+    text
+}
+
+/// Keep the sent messages for the tests
+#[cfg(test)]
+#[derive(Default, Clone)]
+pub(super) struct Recorder(pub(super) std::sync::Arc<std::sync::Mutex<Vec<(ChatId, String)>>>);
+
+#[cfg(test)]
+impl TextSender for Recorder {
+    fn send_text(
+        &self,
+        chat: ChatId,
+        text: String,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        self.0.lock().unwrap().push((chat, text));
+        async { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+pub(super) use db::test_pool;
+
+#[tokio::test]
+async fn test_notifier() {
+    use crate::db::{Actor, FollowTarget};
+
+    let pool = test_pool().await;
+    for (id, name) in [(1, "alice"), (2, "bob"), (3, "carol")] {
+        db::register_reviewer(&pool, id, name).await.unwrap();
+    }
+    let kfc = db::add_restaurant(&pool, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    db::follow(&pool, 1, FollowTarget::Restaurant(kfc))
+        .await
+        .unwrap();
+    db::follow(&pool, 3, FollowTarget::Reviewer(2))
+        .await
+        .unwrap();
+
+    let recorder = Recorder::default();
+    let mut notifier = Notifier::new(recorder.clone(), pool.clone());
+    let mut events = db::subscribe_events();
+    // the photo sent with the new dish
+    let dish = db::add_dish(&pool, Actor::User(2), kfc, "汉堡", Some("file-id".into()))
+        .await
+        .unwrap();
+    while let Ok(event) = events.try_recv() {
+        notifier.push(&event).await.unwrap();
+    }
+
+    let start = Instant::now();
+    notifier.flush(start).await;
+    {
+        let sent = recorder.0.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].0, ChatId(1));
+        assert!(sent[0].1.starts_with("2 updates"));
+        assert!(sent[0].1.contains("New dish 汉堡 at KFC"));
+        assert!(sent[0].1.contains("New photo of 汉堡 at KFC"));
+    }
+
+    // the next update waits for the interval
+    let event = Event::DishAdded {
+        id: dish,
+        restaurant: kfc,
+        actor: None,
+    };
+    notifier.push(&event).await.unwrap();
+    notifier.flush(start + FLUSH_INTERVAL).await;
+    assert_eq!(recorder.0.lock().unwrap().len(), 2);
+    notifier.flush(start + MIN_INTERVAL).await;
+    let sent = recorder.0.lock().unwrap();
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[2], (ChatId(1), "New dish 汉堡 at KFC".to_string()));
+}
+
+#[tokio::test]
+async fn test_notifier_retry() {
+    use crate::db::{Actor, FollowTarget};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Fail the first message, then record the others
+    #[derive(Clone)]
+    struct Flaky(Arc<AtomicBool>, Recorder);
+
+    impl TextSender for Flaky {
+        fn send_text(
+            &self,
+            chat: ChatId,
+            text: String,
+        ) -> impl Future<Output = anyhow::Result<()>> + Send {
+            let failed = self.0.swap(true, Ordering::SeqCst);
+            let sent = failed.then(|| self.1.send_text(chat, text));
+            async move {
+                match sent {
+                    Some(sent) => sent.await,
+                    None => anyhow::bail!("network is down"),
+                }
+            }
+        }
+    }
+
+    let pool = test_pool().await;
+    db::register_reviewer(&pool, 1, "alice").await.unwrap();
+    let kfc = db::add_restaurant(&pool, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    db::follow(&pool, 1, FollowTarget::Restaurant(kfc))
+        .await
+        .unwrap();
+
+    let recorder = Recorder::default();
+    let flaky = Flaky(Arc::default(), recorder.clone());
+    let mut notifier = Notifier::new(flaky, pool.clone());
+    let event = |id| Event::DishAdded {
+        id,
+        restaurant: kfc,
+        actor: None,
+    };
+    for name in ["汉堡", "薯条"] {
+        let dish = db::add_dish(&pool, Actor::System, kfc, name, None)
+            .await
+            .unwrap();
+        notifier.push(&event(dish)).await.unwrap();
+        if name == "汉堡" {
+            // fails, and stays queued for the next flush
+            notifier.flush(Instant::now()).await;
+        }
+    }
+    assert!(recorder.0.lock().unwrap().is_empty());
+
+    notifier.flush(Instant::now()).await;
+    let sent = recorder.0.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0].1.starts_with("2 updates"));
+    let burger = sent[0].1.find("汉堡").unwrap();
+    assert!(burger < sent[0].1.find("薯条").unwrap());
+}
+
+#[tokio::test]
+async fn test_notifier_shutdown() {
+    use crate::db::{Actor, FollowTarget};
+    use crate::ops::Tasks;
+    use tokio::sync::broadcast;
+
+    let pool = test_pool().await;
+    db::register_reviewer(&pool, 1, "alice").await.unwrap();
+    let kfc = db::add_restaurant(&pool, Actor::System, "KFC", "光谷")
+        .await
+        .unwrap();
+    db::follow(&pool, 1, FollowTarget::Restaurant(kfc))
+        .await
+        .unwrap();
+    let dish = db::add_dish(&pool, Actor::System, kfc, "汉堡", None)
+        .await
+        .unwrap();
+
+    let recorder = Recorder::default();
+    let notifier = Notifier::new(recorder.clone(), pool.clone());
+    let (bus, events) = broadcast::channel(16);
+    let mut tasks = Tasks::default();
+    tasks.spawn(|shutdown| notifier.run(events, shutdown));
+    // queued long before the next flush, sent on the way out
+    bus.send(Event::DishAdded {
+        id: dish,
+        restaurant: kfc,
+        actor: None,
+    })
+    .unwrap();
+    tasks.shutdown().await;
+    let sent = recorder.0.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0], (ChatId(1), "New dish 汉堡 at KFC".to_string()));
+}
@@ -0,0 +1,84 @@
+use super::notifier::TextSender;
+use crate::db::{self, Pool};
+use crate::ops::Shutdown;
+use chrono::NaiveDateTime;
+use teloxide::types::ChatId;
+use tokio::time::Duration;
+
+// how often the digest settings are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Post the weekly digest to the chats whose schedule is due at `now`, return how many are posted
+pub(super) async fn post_due_digests<S: TextSender>(
+    sender: &S,
+    pool: &Pool,
+    now: NaiveDateTime,
+) -> anyhow::Result<usize> {
+    let due = db::due_digests(pool, now).await?;
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let text = db::weekly_digest(pool, now).await?.to_string();
+    for setting in &due {
+        if let Err(e) = sender.send_text(ChatId(setting.chat), text.clone()).await {
+            tracing::warn!("fail to post digest to chat {}: {e:#}", setting.chat);
+        }
+        // a chat that removed the bot is not retried every minute
+        db::mark_digest_sent(pool, setting.chat, now).await?;
+    }
+    Ok(due.len())
+}
+
+pub(super) async fn run<S: TextSender>(sender: S, pool: Pool, mut shutdown: Shutdown) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => (),
+            _ = shutdown.wait() => break,
+        }
+        if let Err(e) = post_due_digests(&sender, &pool, db::local_now()).await {
+            tracing::error!("fail to post the digests: {e:#}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_post_due_digests() {
+    use super::notifier::{test_pool, Recorder};
+    use chrono::Weekday;
+
+    let pool = test_pool().await;
+    let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    db::set_digest(
+        &pool,
+        db::Actor::System,
+        -1,
+        Weekday::Fri,
+        18,
+        at("2023-01-16 10:00"),
+    )
+    .await
+    .unwrap();
+    db::set_digest(
+        &pool,
+        db::Actor::System,
+        -2,
+        Weekday::Mon,
+        9,
+        at("2023-01-16 10:00"),
+    )
+    .await
+    .unwrap();
+
+    let recorder = Recorder::default();
+    let now = at("2023-01-20 18:00");
+    assert_eq!(post_due_digests(&recorder, &pool, now).await.unwrap(), 1);
+    assert_eq!(post_due_digests(&recorder, &pool, now).await.unwrap(), 0);
+    let sent = recorder.0.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, ChatId(-1));
+    assert!(sent[0]
+        .1
+        .starts_with("Weekly digest 2023-01-13 - 2023-01-19"));
+}
@@ -0,0 +1,236 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::Stream;
+use reqwest::Url;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use teloxide::{
+    dispatching::update_listeners::{AsUpdateStream, UpdateListener},
+    payloads::SetWebhookSetters,
+    prelude::*,
+    stop::{mk_stop_token, StopFlag, StopToken},
+    types::Update,
+};
+use tokio::sync::mpsc;
+
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+// updates waiting for the dispatcher, telegram retries the ones refused when it's full
+const QUEUE: usize = 256;
+
+/// Telegram pushes the updates to `TGBOT_WEBHOOK_URL` instead of being polled, with the secret
+/// token from `TGBOT_WEBHOOK_SECRET` or a random one.
+///
+/// The path of the url is served by the local HTTP server of the bot, or by the api-server in the
+/// combined server, so the url is usually a reverse proxy in front of them. A recorded update can
+/// be replayed with
+///
+/// ```text
+/// curl -H "X-Telegram-Bot-Api-Secret-Token: $TGBOT_WEBHOOK_SECRET" \
+///     -H "Content-Type: application/json" -d @update.json http://127.0.0.1:9091/<path>
+/// ```
+pub struct Webhook {
+    url: Url,
+    secret: String,
+}
+
+impl Webhook {
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("TGBOT_WEBHOOK_URL").ok()?;
+        let url = match Url::parse(&url) {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::error!("invalid TGBOT_WEBHOOK_URL {url}: {e}, use polling");
+                return None;
+            }
+        };
+        let secret = match std::env::var("TGBOT_WEBHOOK_SECRET") {
+            Ok(secret) if is_valid_secret(&secret) => secret,
+            Ok(_) => {
+                tracing::warn!("TGBOT_WEBHOOK_SECRET must be 1-256 of A-Z, a-z, 0-9, _ and -");
+                hex::encode(rand::random::<[u8; 24]>())
+            }
+            Err(_) => hex::encode(rand::random::<[u8; 24]>()),
+        };
+        Some(Self { url, secret })
+    }
+
+    /// The route receiving the updates and the listener handing them to the dispatcher
+    pub fn channel(&self) -> (WebhookRoute, WebhookListener) {
+        let (sender, receiver) = mpsc::channel(QUEUE);
+        let (stop, stopped) = mk_stop_token();
+        let route = WebhookRoute {
+            path: self.url.path().to_string(),
+            secret: self.secret.clone(),
+            sender,
+            stopped: stopped.clone(),
+        };
+        let listener = WebhookListener {
+            stream: UpdateStream { receiver, stopped },
+            stop,
+        };
+        (route, listener)
+    }
+
+    /// Ask telegram to push the updates to the url
+    pub async fn register(&self, bot: &Bot) -> anyhow::Result<()> {
+        bot.set_webhook(self.url.clone())
+            .secret_token(self.secret.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+fn is_valid_secret(secret: &str) -> bool {
+    (1..=256).contains(&secret.len())
+        && secret
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+}
+
+/// Listener of the updates received by [`WebhookRoute`]
+pub struct WebhookListener {
+    stream: UpdateStream,
+    stop: StopToken,
+}
+
+impl<'a> AsUpdateStream<'a> for WebhookListener {
+    type StreamErr = Infallible;
+    type Stream = &'a mut UpdateStream;
+
+    fn as_stream(&'a mut self) -> Self::Stream {
+        &mut self.stream
+    }
+}
+
+impl UpdateListener for WebhookListener {
+    type Err = Infallible;
+
+    fn stop_token(&mut self) -> StopToken {
+        self.stop.clone()
+    }
+}
+
+/// Updates received by the route until the dispatcher stops listening
+pub struct UpdateStream {
+    receiver: mpsc::Receiver<Update>,
+    stopped: StopFlag,
+}
+
+impl Stream for UpdateStream {
+    type Item = Result<Update, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if Pin::new(&mut self.stopped).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        self.receiver.poll_recv(cx).map(|update| update.map(Ok))
+    }
+}
+
+/// Endpoint of the webhook, mount it with [`WebhookRoute::configure`]
+#[derive(Clone)]
+pub struct WebhookRoute {
+    path: String,
+    secret: String,
+    sender: mpsc::Sender<Update>,
+    stopped: StopFlag,
+}
+
+impl WebhookRoute {
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.service(
+            web::resource(self.path.as_str())
+                .app_data(web::Data::new(self.clone()))
+                .route(web::post().to(receive)),
+        );
+    }
+}
+
+/// Compare in constant time, the secret is the only authentication of the route
+fn secret_matches(expected: &str, given: &[u8]) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn receive(
+    route: web::Data<WebhookRoute>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    let given = req.headers().get(SECRET_HEADER).map(|v| v.as_bytes());
+    if !given.is_some_and(|given| secret_matches(&route.secret, given)) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    if route.stopped.is_stopped() {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+    let update = match serde_json::from_slice::<Update>(&body) {
+        Ok(update) => update,
+        Err(e) => {
+            // telegram would send it again and again, drop it
+            tracing::error!("fail to parse the update from the webhook: {e}");
+            return HttpResponse::Ok().finish();
+        }
+    };
+    match route.sender.try_send(update) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            tracing::warn!("refuse the update from the webhook: {e}");
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_webhook_route() {
+    use actix_web::{http::StatusCode, test, App};
+    use futures_util::StreamExt;
+
+    // recorded from telegram
+    const UPDATE: &str = r#"{
+        "update_id": 10000,
+        "message": {
+            "message_id": 1365,
+            "date": 1675590000,
+            "chat": { "id": 1111, "type": "private", "first_name": "Test", "username": "test" },
+            "from": { "id": 1111, "is_bot": false, "first_name": "Test", "username": "test" },
+            "text": "/whoami",
+            "entities": [{ "offset": 0, "length": 7, "type": "bot_command" }]
+        }
+    }"#;
+
+    let webhook = Webhook {
+        url: Url::parse("https://example.com/telegram/update").unwrap(),
+        secret: "secret-token".to_string(),
+    };
+    let (route, mut listener) = webhook.channel();
+    let app = test::init_service(App::new().configure(|cfg| route.configure(cfg))).await;
+    let post = |secret: &str| {
+        test::TestRequest::post()
+            .uri("/telegram/update")
+            .insert_header((SECRET_HEADER, secret))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(UPDATE)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, post("wrong-token")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, post("secret-token")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let update = listener.as_stream().next().await.unwrap().unwrap();
+    assert_eq!(update.id, 10000);
+    assert_eq!(update.user().unwrap().id, UserId(1111));
+
+    // nothing is taken after the dispatcher stops listening
+    listener.stop_token().stop();
+    assert!(listener.as_stream().next().await.is_none());
+    let resp = test::call_service(&app, post("secret-token")).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
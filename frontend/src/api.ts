@@ -1,3 +1,4 @@
+import { useEffect } from "react";
 import useSWR from "swr";
 import config from "../config.json";
 
@@ -6,13 +7,63 @@ async function jsonFetcher<T>(url: URL): Promise<T> {
   return await resp.json();
 }
 
-export function useBackend<T>(suffix: string) {
+// Event sent by `/api/v1/events` when something is added
+export interface LiveEvent {
+  type: "restaurant_added" | "dish_added" | "review_added" | "photo_added";
+  id?: number;
+  dish?: number;
+  restaurant: number;
+}
+
+// Which live events refresh the data, every event when empty
+export interface LiveFilter {
+  restaurant?: number;
+  dish?: number;
+}
+
+function matches(event: LiveEvent, filter: LiveFilter) {
+  if (filter.dish !== undefined) {
+    return event.dish === filter.dish;
+  }
+  return filter.restaurant === undefined || event.restaurant === filter.restaurant;
+}
+
+// Call `onEvent` for the live events matching the filter, or with null when some are missed
+export function useLiveEvents(
+  filter: LiveFilter | undefined,
+  onEvent: (event: LiveEvent | null) => void
+) {
+  const restaurant = filter?.restaurant;
+  const dish = filter?.dish;
+  useEffect(() => {
+    if (!filter) {
+      return;
+    }
+    const url = new URL("/api/v1/events", config.backend.address);
+    if (restaurant !== undefined) {
+      url.searchParams.set("restaurant", `${restaurant}`);
+    }
+    const source = new EventSource(url);
+    source.onmessage = (msg) => {
+      const event: LiveEvent = JSON.parse(msg.data);
+      if (matches(event, { restaurant, dish })) {
+        onEvent(event);
+      }
+    };
+    source.addEventListener("lagged", () => onEvent(null));
+    return () => source.close();
+  }, [filter !== undefined, restaurant, dish]);
+}
+
+// Fetch from the backend, refetch on the live events matching `live` if given
+export function useBackend<T>(suffix: string, live?: LiveFilter) {
   const url = new URL(suffix, config.backend.address);
-  const { data, error } = useSWR<T>(url.href, jsonFetcher);
+  const { data, error, mutate } = useSWR<T>(url.href, jsonFetcher);
+  useLiveEvents(live, () => mutate());
 
   return {
     isLoading: !data && !error,
     isError: error,
     result: data,
   };
-}
\ No newline at end of file
+}
@@ -17,7 +17,9 @@ export default function RestaurantDetail() {
       </div>
     );
   }
-  const detail = useBackend<Dish[]>(`/api/v1/restaurants/${id}`);
+  const detail = useBackend<Dish[]>(`/api/v1/restaurants/${id}`, {
+    restaurant: Number(id),
+  });
   if (detail.isLoading) {
     return (
       <div>
@@ -3,6 +3,7 @@ import { useBackend } from "../api";
 
 interface Review {
   reviewer: number;
+  reviewer_name?: string;
   score: number;
   details: string;
 }
@@ -13,7 +14,9 @@ export default function Review() {
     throw Error("Page not found");
   }
 
-  const response = useBackend<Review>(`/api/v1/dishes/${id}`);
+  const response = useBackend<Review>(`/api/v1/dishes/${id}`, {
+    dish: Number(id),
+  });
   if (response.isLoading) {
     return (
       <div>
@@ -34,6 +37,7 @@ export default function Review() {
 
   return <div>
     <h2>评分</h2><div>{response.result.score}</div>
+    <div>{response.result.reviewer_name ?? `#${response.result.reviewer}`}</div>
     <p>{response.result.details}</p>
   </div>
 }
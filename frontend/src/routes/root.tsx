@@ -17,7 +17,7 @@ interface Restaurant {
 }
 
 function RestaurantsList() {
-  const resp = useBackend<Restaurant[]>("/api/v1/restaurants");
+  const resp = useBackend<Restaurant[]>("/api/v1/restaurants", {});
   if (resp.isLoading) {
     return (
       <div>